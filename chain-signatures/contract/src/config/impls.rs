@@ -13,6 +13,30 @@ const MAX_EXPECTED_PARTICIPANTS: u32 = 32;
 const NETWORK_MULTIPLIER: u32 = 128;
 
 impl Config {
+    /// Applies a partial configuration on top of `base`. Entries missing from `overlay` keep
+    /// their value from `base`, and nested objects are merged entry by entry, so an update only
+    /// needs to name the fields it changes.
+    pub fn merge(base: Config, overlay: serde_json::Value) -> Result<Config, serde_json::Error> {
+        let mut merged = serde_json::to_value(base)?;
+        merge_value(&mut merged, overlay);
+        serde_json::from_value(merged)
+    }
+
+    /// Renders the configuration as TOML, e.g. for operators to edit and submit back through
+    /// [`Config::from_toml`]. Fails if one of the dynamic entries holds a value TOML can not
+    /// express, such as `null`.
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Parses a configuration in the format produced by [`Config::to_toml`]. See
+    /// `config.sample.toml` for a documented example.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(s: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(s)
+    }
+
     pub fn get(&self, key: &str) -> Option<serde_json::Value> {
         match key {
             "protocol" => Some(serde_json::to_value(self.protocol.clone()).unwrap()),
@@ -24,6 +48,17 @@ impl Config {
     }
 }
 
+fn merge_value(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_value(base.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
 impl Default for ProtocolConfig {
     fn default() -> Self {
         Self {
@@ -7,6 +7,12 @@ use std::collections::HashMap;
 use borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
 
+use crate::errors::InitError;
+
+/// Smallest threshold the network can be run with. With a threshold of one, any single
+/// participant could produce signatures on its own.
+pub const MIN_THRESHOLD: usize = 2;
+
 /// Dynamic value is used to store any kind of value in the contract state. These values
 /// can be deserialized on the fly to get the actual configurations, but the contract will
 /// not be the ones directly utilizing these values unless they are concrete types.
@@ -24,6 +30,20 @@ pub struct Config {
     pub other: HashMap<String, DynamicValue>,
 }
 
+impl Config {
+    /// Checks that `threshold` is at least [`MIN_THRESHOLD`] and can be reached by the given
+    /// number of participants.
+    pub fn validate_threshold(threshold: usize, participants: usize) -> Result<(), InitError> {
+        if threshold < MIN_THRESHOLD {
+            return Err(InitError::ThresholdTooLow);
+        }
+        if threshold > participants {
+            return Err(InitError::ThresholdTooHigh);
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
 pub struct ProtocolConfig {
     /// Message timeout in milliseconds for any protocol message that gets sent over the wire.
@@ -97,7 +117,71 @@ pub struct SignatureConfig {
 
 #[cfg(test)]
 mod tests {
-    use crate::config::Config;
+    use crate::config::{Config, MIN_THRESHOLD};
+    use crate::errors::InitError;
+
+    #[test]
+    fn test_merge_config() {
+        let base = Config::default();
+        let merged = Config::merge(
+            base.clone(),
+            serde_json::json!({
+                "protocol": {
+                    "max_concurrent_generation": 10000,
+                    "triple": { "min_triples": 7 }
+                },
+                "extra": "value"
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(merged.protocol.max_concurrent_generation, 10000);
+        assert_eq!(merged.protocol.triple.min_triples, 7);
+        assert_eq!(
+            merged.protocol.triple.max_triples,
+            base.protocol.triple.max_triples
+        );
+        assert_eq!(merged.protocol.presignature, base.protocol.presignature);
+        assert_eq!(merged.get("extra").unwrap(), serde_json::json!("value"));
+
+        assert_eq!(
+            Config::merge(base.clone(), serde_json::json!({})).unwrap(),
+            base
+        );
+        assert!(Config::merge(base, serde_json::json!({ "protocol": { "triple": 1 } })).is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_round_trip() {
+        let mut config = Config::default();
+        config.protocol.triple.min_triples = 7;
+        config.other.insert(
+            "extra".to_string(),
+            serde_json::json!({ "key": "value" }).into(),
+        );
+
+        let toml = config.to_toml().unwrap();
+        assert_eq!(Config::from_toml(&toml).unwrap(), config);
+
+        let sample = Config::from_toml(include_str!("../../config.sample.toml")).unwrap();
+        assert_eq!(sample, Config::default());
+        assert!(Config::from_toml("protocol = 1").is_err());
+    }
+
+    #[test]
+    fn test_validate_threshold() {
+        assert_eq!(
+            Config::validate_threshold(MIN_THRESHOLD - 1, 3),
+            Err(InitError::ThresholdTooLow)
+        );
+        assert_eq!(
+            Config::validate_threshold(4, 3),
+            Err(InitError::ThresholdTooHigh)
+        );
+        assert_eq!(Config::validate_threshold(MIN_THRESHOLD, 3), Ok(()));
+        assert_eq!(Config::validate_threshold(3, 3), Ok(()));
+    }
 
     #[test]
     fn test_load_config() {
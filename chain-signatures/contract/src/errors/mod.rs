@@ -37,6 +37,8 @@ pub enum PublicKeyError {
 pub enum InitError {
     #[error("Threshold cannot be greater than the number of candidates")]
     ThresholdTooHigh,
+    #[error("Threshold cannot be lower than the minimum threshold")]
+    ThresholdTooLow,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
@@ -9,8 +9,8 @@ use crypto_shared::{
     types::SignatureResponse, ScalarExt as _,
 };
 use errors::{
-    ConversionError, InitError, InvalidParameters, InvalidState, JoinError, PublicKeyError,
-    RespondError, SignError, VoteError,
+    ConversionError, InvalidParameters, InvalidState, JoinError, PublicKeyError, RespondError,
+    SignError, VoteError,
 };
 use k256::elliptic_curve::sec1::ToEncodedPoint;
 use k256::Scalar;
@@ -602,9 +602,7 @@ impl VersionedMpcContract {
             config,
         );
 
-        if threshold > candidates.len() {
-            return Err(InitError::ThresholdTooHigh.into());
-        }
+        Config::validate_threshold(threshold, candidates.len())?;
 
         Ok(Self::V0(MpcContract::init(threshold, candidates, config)))
     }
@@ -630,9 +628,7 @@ impl VersionedMpcContract {
             config,
         );
 
-        if threshold > participants.len() {
-            return Err(InitError::ThresholdTooHigh.into());
-        }
+        Config::validate_threshold(threshold, participants.len())?;
 
         Ok(Self::V0(MpcContract {
             protocol_state: ProtocolContractState::Running(RunningContractState {
@@ -662,6 +658,7 @@ impl VersionedMpcContract {
     #[handle_result]
     pub fn migrate() -> Result<Self, Error> {
         let old: MpcContract = env::state_read().ok_or(InvalidState::ContractStateIsMissing)?;
+        update::bump_update_version();
         Ok(VersionedMpcContract::V0(old))
     }
 
@@ -682,6 +679,12 @@ impl VersionedMpcContract {
         env!("CARGO_PKG_VERSION").to_string()
     }
 
+    /// Number of code and config updates applied to this contract so far. Starts at 0 and
+    /// increments every time an update passes the vote and is applied successfully.
+    pub fn update_version(&self) -> u64 {
+        update::update_version()
+    }
+
     #[private]
     pub fn sign_helper(&mut self, contract_signature_request: ContractSignatureRequest) {
         match self {
@@ -794,6 +797,7 @@ impl VersionedMpcContract {
         match self {
             Self::V0(mpc_contract) => {
                 mpc_contract.config = config;
+                update::bump_update_version();
             }
         }
     }
@@ -7,7 +7,7 @@ use crate::primitives::StorageKey;
 use borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::store::IterableMap;
-use near_sdk::{env, AccountId, Gas, NearToken, Promise};
+use near_sdk::{env, AccountId, Gas, IntoStorageKey, NearToken, Promise};
 
 #[derive(
     Copy,
@@ -149,6 +149,24 @@ impl ProposedUpdates {
     }
 }
 
+/// Number of updates successfully applied to the contract. This lives under its own storage
+/// key instead of in the contract state, so that the state layout stays the same.
+pub(crate) fn update_version() -> u64 {
+    env::storage_read(&StorageKey::UpdateVersion.into_storage_key())
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_le_bytes)
+        .unwrap_or(0)
+}
+
+/// Records that an update got applied. Must only be called once the update can not fail
+/// anymore, i.e. from `migrate` and `update_config`.
+pub(crate) fn bump_update_version() {
+    env::storage_write(
+        &StorageKey::UpdateVersion.into_storage_key(),
+        &(update_version() + 1).to_le_bytes(),
+    );
+}
+
 fn bytes_used(code: &Option<Vec<u8>>, config: &Option<Config>) -> u128 {
     let mut bytes_used = std::mem::size_of::<UpdateEntry>() as u128;
 
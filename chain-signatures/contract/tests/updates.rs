@@ -82,13 +82,11 @@ async fn test_propose_update_config() {
         .contains(&errors::VoteError::VoterNotParticipant.to_string()));
 
     // have each participant propose a new update:
-    let new_config = Config {
-        protocol: ProtocolConfig {
-            max_concurrent_generation: 10000,
-            ..ProtocolConfig::default()
-        },
-        ..Config::default()
-    };
+    let new_config = Config::merge(
+        Config::default(),
+        serde_json::json!({ "protocol": { "max_concurrent_generation": 10000 } }),
+    )
+    .unwrap();
 
     let mut proposals = Vec::with_capacity(accounts.len());
     for account in &accounts {
@@ -145,6 +143,13 @@ async fn test_propose_update_config() {
     let config: serde_json::Value = contract.view("config").await.unwrap().json().unwrap();
     assert_ne!(config, old_config);
     assert_eq!(config, new_config);
+    let update_version: u64 = contract
+        .view("update_version")
+        .await
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(update_version, 1);
 
     // Check that we can partially set hardcoded configs, while leaving other configs as dynamic values:
     #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -175,6 +180,13 @@ async fn test_propose_update_contract() {
     assert!(execution.is_success());
     let proposal_id: UpdateId = execution.json().unwrap();
     vote_update_till_completion(&contract, &accounts, &proposal_id).await;
+    let update_version: u64 = contract
+        .view("update_version")
+        .await
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(update_version, 1);
 
     // Try calling into state and see if it works.
     let execution = accounts[0]
@@ -228,6 +240,15 @@ async fn test_invalid_contract_deploy() {
     dbg!(&execution);
     let state: mpc_contract::ProtocolContractState = execution.json().unwrap();
     dbg!(state);
+
+    // The failed update must not count as applied.
+    let update_version: u64 = contract
+        .view("update_version")
+        .await
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(update_version, 0);
 }
 
 #[tokio::test]
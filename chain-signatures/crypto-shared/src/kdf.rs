@@ -7,6 +7,8 @@ use k256::{
 };
 use near_account_id::AccountId;
 use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::str::FromStr;
 
 // Constant prefix that ensures epsilon derivation values are used specifically for
 // near-mpc-recovery with key derivation protocol vX.Y.Z.
@@ -28,6 +30,85 @@ pub fn derive_epsilon(predecessor_id: &AccountId, path: &str) -> Scalar {
     Scalar::from_non_biased(hash)
 }
 
+/// Offset marking a hardened index in a BIP-32 derivation path.
+const BIP32_HARDENED_OFFSET: u32 = 0x8000_0000;
+
+/// A validated BIP-32 derivation path such as `m/44'/60'/0'/0/0`.
+///
+/// Hardened indices may be written as either `44'` or `44h`. The path is always serialized
+/// back in the canonical `m/44'/60'/0'/0/0` form, so equivalent notations derive the same
+/// epsilon.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Bip32Path {
+    indices: Vec<u32>,
+}
+
+impl Bip32Path {
+    /// The raw child indices, with the hardened bit set for hardened components.
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+}
+
+impl FromStr for Bip32Path {
+    type Err = anyhow::Error;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let mut components = path.split('/');
+        if components.next() != Some("m") {
+            anyhow::bail!("bip32 path `{path}` must start with `m`");
+        }
+
+        let indices = components
+            .map(|component| {
+                let (index, hardened) = match component
+                    .strip_suffix('\'')
+                    .or_else(|| component.strip_suffix('h'))
+                {
+                    Some(index) => (index, true),
+                    None => (component, false),
+                };
+                if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                    anyhow::bail!("invalid bip32 path component `{component}` in `{path}`");
+                }
+                let index: u32 = index
+                    .parse()
+                    .with_context(|| format!("bip32 index `{component}` out of range"))?;
+                if index >= BIP32_HARDENED_OFFSET {
+                    anyhow::bail!("bip32 index `{component}` out of range");
+                }
+                Ok(if hardened {
+                    index + BIP32_HARDENED_OFFSET
+                } else {
+                    index
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self { indices })
+    }
+}
+
+impl fmt::Display for Bip32Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.indices {
+            if *index >= BIP32_HARDENED_OFFSET {
+                write!(f, "/{}'", index - BIP32_HARDENED_OFFSET)?;
+            } else {
+                write!(f, "/{index}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Derive the epsilon for a BIP-32 style path. The path is hashed in its canonical form, so
+/// `m/44h/60h` and `m/44'/60'` yield the same epsilon as `derive_epsilon(.., "m/44'/60'")`.
+pub fn derive_epsilon_bip32(predecessor_id: &AccountId, bip32_path: &Bip32Path) -> Scalar {
+    derive_epsilon(predecessor_id, &bip32_path.to_string())
+}
+
 pub fn derive_key(public_key: PublicKey, epsilon: Scalar) -> PublicKey {
     (<Secp256k1 as CurveArithmetic>::ProjectivePoint::GENERATOR * epsilon + public_key).to_affine()
 }
@@ -96,3 +177,48 @@ pub fn recover(
     ))
     .context("Failed to parse returned key")
 }
+
+#[test]
+fn bip32_path_parsing() {
+    let path: Bip32Path = "m/44'/60'/0'/0/0".parse().unwrap();
+    assert_eq!(
+        path.indices(),
+        &[0x8000_002C, 0x8000_003C, 0x8000_0000, 0, 0]
+    );
+    assert_eq!(path.to_string(), "m/44'/60'/0'/0/0");
+
+    let alternate: Bip32Path = "m/44h/60h/0h/0/0".parse().unwrap();
+    assert_eq!(path, alternate);
+
+    let master: Bip32Path = "m".parse().unwrap();
+    assert!(master.indices().is_empty());
+
+    for invalid in [
+        "",
+        "44'/60'",
+        "m/",
+        "m//0",
+        "m/-1",
+        "m/+1",
+        "m/0''",
+        "m/abc",
+        "m/2147483648",
+        "m/4294967296'",
+    ] {
+        assert!(invalid.parse::<Bip32Path>().is_err(), "{invalid} parsed");
+    }
+}
+
+#[test]
+fn bip32_epsilon_is_consistent() {
+    let account_id: AccountId = "alice.near".parse().unwrap();
+    let path: Bip32Path = "m/44'/60'/0'/0/0".parse().unwrap();
+    let alternate: Bip32Path = "m/44h/60h/0h/0/0".parse().unwrap();
+
+    let epsilon = derive_epsilon_bip32(&account_id, &path);
+    assert_eq!(epsilon, derive_epsilon(&account_id, "m/44'/60'/0'/0/0"));
+    assert_eq!(epsilon, derive_epsilon_bip32(&account_id, &alternate));
+
+    let other: Bip32Path = "m/44'/60'/0'/0/1".parse().unwrap();
+    assert_ne!(epsilon, derive_epsilon_bip32(&account_id, &other));
+}
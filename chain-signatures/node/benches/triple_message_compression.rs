@@ -0,0 +1,102 @@
+//! Compares sending triple generation messages of a 20 participant set as is against sending
+//! them as [`CompressedTripleMessage`]s. Run with `cargo bench --bench triple_message_compression`.
+
+use cait_sith::protocol::{Action, Participant};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use k256::Secp256k1;
+use mpc_node::protocol::message::{CompressedTripleMessage, TripleMessage};
+
+const PARTICIPANTS: u32 = 20;
+
+/// Collects the messages the first participant sends in a 20 participant triple generation.
+fn triple_messages() -> Vec<TripleMessage> {
+    let participants: Vec<Participant> = (0..PARTICIPANTS).map(Participant::from).collect();
+    let mut protocol =
+        cait_sith::triples::generate_triple::<Secp256k1>(&participants, participants[0], 14)
+            .unwrap();
+
+    let mut messages = Vec::new();
+    loop {
+        match protocol.poke().unwrap() {
+            Action::SendMany(data) | Action::SendPrivate(_, data) => messages.push(TripleMessage {
+                id: 0,
+                epoch: 0,
+                from: participants[0],
+                data,
+                timestamp: 0,
+                participant_set_hash: None,
+            }),
+            _ => break,
+        }
+    }
+    messages
+}
+
+fn clone_message(message: &TripleMessage) -> TripleMessage {
+    TripleMessage {
+        id: message.id,
+        epoch: message.epoch,
+        from: message.from,
+        data: message.data.clone(),
+        timestamp: message.timestamp,
+        participant_set_hash: message.participant_set_hash,
+    }
+}
+
+fn bench_compression(c: &mut Criterion) {
+    let messages = triple_messages();
+    let raw: usize = messages.iter().map(|m| m.data.len()).sum();
+    let compressed: usize = messages
+        .iter()
+        .map(|m| CompressedTripleMessage::from(clone_message(m)).data.len())
+        .sum();
+    println!(
+        "{} messages: {raw} bytes uncompressed, {compressed} bytes compressed",
+        messages.len()
+    );
+
+    let mut group = c.benchmark_group("triple_message_transmission");
+    group.bench_function("uncompressed", |b| {
+        b.iter(|| {
+            for message in &messages {
+                let encoded = serde_json::to_vec(black_box(message)).unwrap();
+                let decoded: TripleMessage = serde_json::from_slice(&encoded).unwrap();
+                black_box(decoded);
+            }
+        })
+    });
+    group.bench_function("compressed", |b| {
+        b.iter(|| {
+            for message in &messages {
+                let wrapped = CompressedTripleMessage::from(clone_message(black_box(message)));
+                let encoded = serde_json::to_vec(&wrapped).unwrap();
+                let decoded: CompressedTripleMessage = serde_json::from_slice(&encoded).unwrap();
+                black_box(TripleMessage::try_from(decoded).unwrap());
+            }
+        })
+    });
+    group.bench_function("decompress_only", |b| {
+        let wrapped: Vec<_> = messages
+            .iter()
+            .map(|m| CompressedTripleMessage::from(clone_message(m)))
+            .collect();
+        b.iter(|| {
+            for message in &wrapped {
+                let decoded = CompressedTripleMessage {
+                    id: message.id,
+                    epoch: message.epoch,
+                    from: message.from,
+                    compressed: message.compressed,
+                    data: message.data.clone(),
+                    timestamp: message.timestamp,
+                    participant_set_hash: message.participant_set_hash,
+                };
+                black_box(TripleMessage::try_from(decoded).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_compression);
+criterion_main!(benches);
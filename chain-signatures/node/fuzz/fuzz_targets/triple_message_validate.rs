@@ -0,0 +1,22 @@
+#![no_main]
+
+use cait_sith::protocol::Participant;
+use libfuzzer_sys::fuzz_target;
+use mpc_node::protocol::message::TripleMessage;
+
+fuzz_target!(|data: &[u8]| {
+    // The first bytes pick the expected epoch and the known participants, the rest is
+    // interpreted as a JSON encoded message like the ones received over the network.
+    if data.len() < 9 {
+        return;
+    }
+    let expected_epoch = u64::from_le_bytes(data[..8].try_into().unwrap());
+    let participant_count = data[8] as usize % 16;
+    let known_participants: Vec<Participant> = (0..participant_count as u32)
+        .map(Participant::from)
+        .collect();
+
+    if let Ok(message) = serde_json::from_slice::<TripleMessage>(&data[9..]) {
+        let _ = message.validate(expected_epoch, &known_participants);
+    }
+});
@@ -1,4 +1,7 @@
-use crate::config::{Config, LocalConfig, NetworkConfig, OverrideConfig};
+use crate::config::{
+    Config, LocalConfig, NetworkConfig, OverrideConfig, DEFAULT_MAX_PRESIGNATURE_AGE_SECS,
+    DEFAULT_TRIPLE_POOL_ALERT_THRESHOLD,
+};
 use crate::gcp::GcpService;
 use crate::protocol::{MpcSignProtocol, SignQueue};
 use crate::storage::app_data_storage;
@@ -58,6 +61,24 @@ pub enum Cli {
         /// Storage options
         #[clap(flatten)]
         storage_options: storage::Options,
+        /// Triple pool load factor above which the pool is reported as degraded.
+        #[arg(
+            long,
+            env("MPC_TRIPLE_POOL_ALERT_THRESHOLD"),
+            default_value_t = DEFAULT_TRIPLE_POOL_ALERT_THRESHOLD
+        )]
+        triple_pool_alert_threshold: f64,
+        /// Age in seconds of the oldest owned presignature above which the presignature pool
+        /// is reported as degraded.
+        #[arg(
+            long,
+            env("MPC_MAX_PRESIGNATURE_AGE_SECS"),
+            default_value_t = DEFAULT_MAX_PRESIGNATURE_AGE_SECS
+        )]
+        max_presignature_age_secs: u64,
+        /// Maximum number of triple generators running at once. Unbounded if not set.
+        #[arg(long, env("MPC_MAX_TRIPLE_GENERATORS"))]
+        max_triple_generators: Option<usize>,
         /// The set of configurations that we will use to override contract configurations.
         #[arg(long, env("MPC_OVERRIDE_CONFIG"), value_parser = clap::value_parser!(OverrideConfig))]
         override_config: Option<OverrideConfig>,
@@ -86,6 +107,9 @@ impl Cli {
                 indexer_options,
                 my_address,
                 storage_options,
+                triple_pool_alert_threshold,
+                max_presignature_age_secs,
+                max_triple_generators,
                 override_config,
                 client_header_referer,
                 mesh_options,
@@ -109,6 +133,10 @@ impl Cli {
                     cipher_sk,
                     "--redis-url".to_string(),
                     storage_options.redis_url.to_string(),
+                    "--triple-pool-alert-threshold".to_string(),
+                    triple_pool_alert_threshold.to_string(),
+                    "--max-presignature-age-secs".to_string(),
+                    max_presignature_age_secs.to_string(),
                 ];
                 if let Some(sign_sk) = sign_sk {
                     args.extend(["--sign-sk".to_string(), sign_sk.to_string()]);
@@ -116,6 +144,12 @@ impl Cli {
                 if let Some(my_address) = my_address {
                     args.extend(["--my-address".to_string(), my_address.to_string()]);
                 }
+                if let Some(max_triple_generators) = max_triple_generators {
+                    args.extend([
+                        "--max-triple-generators".to_string(),
+                        max_triple_generators.to_string(),
+                    ]);
+                }
                 if let Some(override_config) = override_config {
                     args.extend([
                         "--override-config".to_string(),
@@ -185,6 +219,9 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
             indexer_options,
             my_address,
             storage_options,
+            triple_pool_alert_threshold,
+            max_presignature_age_secs,
+            max_triple_generators,
             override_config,
             client_header_referer,
             mesh_options,
@@ -204,9 +241,12 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
 
             let redis_cfg = deadpool_redis::Config::from_url(redis_url);
             let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
-            let triple_storage = storage::triple_storage::init(&redis_pool, &account_id);
+            let triple_storage = storage::triple_storage::init(&redis_pool, &account_id)
+                .with_durability(storage_options.durability)
+                .with_key_prefix(storage_options.redis_key_prefix.clone());
             let presignature_storage =
-                storage::presignature_storage::init(&redis_pool, &account_id);
+                storage::presignature_storage::init(&redis_pool, &account_id)
+                    .with_key_prefix(storage_options.redis_key_prefix.clone());
             let app_data_storage = app_data_storage::init(&redis_pool, &account_id);
 
             let mut rpc_client = near_fetch::Client::new(&near_rpc);
@@ -252,6 +292,9 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
                 triple_storage,
                 presignature_storage,
                 Config::new(LocalConfig {
+                    triple_pool_alert_threshold,
+                    max_presignature_age: Duration::from_secs(max_presignature_age_secs),
+                    max_triple_generators,
                     over: override_config.unwrap_or_else(Default::default),
                     network: NetworkConfig {
                         cipher_pk: hpke::PublicKey::try_from_bytes(&hex::decode(cipher_pk)?)?,
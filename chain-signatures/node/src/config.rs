@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::Duration;
 
 use mpc_contract::config::ProtocolConfig;
 use mpc_keys::hpke;
@@ -61,12 +62,40 @@ impl Config {
     }
 }
 
+/// Triple pool load factor above which a node reports its triple pool as degraded.
+pub const DEFAULT_TRIPLE_POOL_ALERT_THRESHOLD: f64 = 0.8;
+
+/// Age in seconds of the oldest owned presignature above which a node reports its
+/// presignature pool as degraded.
+pub const DEFAULT_MAX_PRESIGNATURE_AGE_SECS: u64 = 60 * 60;
+
 /// All the local configurations on a node that are not accessible by anyone else
 /// but the current node.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct LocalConfig {
     pub network: NetworkConfig,
     pub over: OverrideConfig,
+    /// Load factor of the triple pool above which it is reported as degraded, see
+    /// [`crate::protocol::triple::TripleManager::load_factor`].
+    pub triple_pool_alert_threshold: f64,
+    /// Age of the oldest owned presignature above which the presignature pool is reported as
+    /// degraded, see [`crate::protocol::presignature::PresignatureManager::age_of_oldest_mine`].
+    pub max_presignature_age: Duration,
+    /// Maximum number of triple generators running at once, see
+    /// [`crate::protocol::triple::TripleManager::with_max_generators`].
+    pub max_triple_generators: Option<usize>,
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        Self {
+            network: NetworkConfig::default(),
+            over: OverrideConfig::default(),
+            triple_pool_alert_threshold: DEFAULT_TRIPLE_POOL_ALERT_THRESHOLD,
+            max_presignature_age: Duration::from_secs(DEFAULT_MAX_PRESIGNATURE_AGE_SECS),
+            max_triple_generators: None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
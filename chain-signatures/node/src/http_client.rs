@@ -121,6 +121,10 @@ impl MessageQueue {
         self.deque.push_back((info, msg, Instant::now()));
     }
 
+    /// Encrypts and sends all queued messages, batched per recipient. Recipient urls are
+    /// looked up in `participants`, which callers keep in memory from the contract state,
+    /// so dispatching does not wait on redis or rpc. Messages for participants not in
+    /// `participants` are kept for the next call.
     pub async fn send_encrypted(
         &mut self,
         from: Participant,
@@ -232,6 +236,10 @@ impl MessageQueue {
 /// go wrong somewhere and the message needs to be requeued to be sent later.
 type EncryptedMessage = (Ciphered, (ParticipantInfo, MpcMessage, Instant));
 
+/// Splits the encrypted messages into batches of at most 256kb, each of which is sent as one
+/// HTTP request. A single message is never split: messages travel over TCP, which already
+/// segments them to the path MTU and reassembles them in order, so large cait-sith messages
+/// need no fragmentation or reassembly buffer at the application level.
 fn partition_ciphered_256kb(encrypted: Vec<EncryptedMessage>) -> Vec<Vec<EncryptedMessage>> {
     let mut result = Vec::new();
     let mut current_partition = Vec::new();
@@ -261,7 +269,9 @@ fn timeout(msg: &MpcMessage, cfg: &ProtocolConfig) -> Duration {
     match msg {
         MpcMessage::Generating(_) => Duration::from_millis(cfg.message_timeout),
         MpcMessage::Resharing(_) => Duration::from_millis(cfg.message_timeout),
-        MpcMessage::Triple(_) => Duration::from_millis(cfg.triple.generation_timeout),
+        MpcMessage::Triple(_) | MpcMessage::TripleBatch(_) => {
+            Duration::from_millis(cfg.triple.generation_timeout)
+        }
         MpcMessage::Presignature(_) => Duration::from_millis(cfg.presignature.generation_timeout),
         MpcMessage::Signature(_) => Duration::from_millis(cfg.signature.generation_timeout),
     }
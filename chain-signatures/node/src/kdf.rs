@@ -1,7 +1,11 @@
 use anyhow::Context;
 use crypto_shared::{kdf::recover, x_coordinate, ScalarExt, SignatureResponse};
 use hkdf::Hkdf;
-use k256::{ecdsa::RecoveryId, elliptic_curve::sec1::ToEncodedPoint, AffinePoint, Scalar};
+use k256::{
+    ecdsa::RecoveryId,
+    elliptic_curve::{scalar::IsHigh, sec1::ToEncodedPoint},
+    AffinePoint, ProjectivePoint, Scalar,
+};
 use near_primitives::hash::CryptoHash;
 use sha3::Sha3_256;
 
@@ -63,3 +67,102 @@ pub fn into_eth_sig(
 
     anyhow::bail!("cannot use either recovery id (0 or 1) to recover pubic key")
 }
+
+/// Packs the signature `(big_r, s)` as `r || s`, with both halves as 32 byte big endian
+/// integers. This is the 64 byte signature expected by Solana's secp256k1 program, which takes
+/// the recovery id separately.
+///
+/// Solana only accepts low-s signatures, so a high `s` is replaced by `-s`. The returned flag
+/// is `true` when that happened, in which case the caller must flip the recovery id it passes
+/// along with the signature (`0 <-> 1`), since negating `s` negates the recovered `R`.
+pub fn into_solana_sig(big_r: &AffinePoint, s: &Scalar) -> ([u8; 64], bool) {
+    let negated = bool::from(s.is_high());
+    let s = if negated { -*s } else { *s };
+    let mut signature = [0; 64];
+    signature[..32].copy_from_slice(&x_coordinate(big_r).to_bytes());
+    signature[32..].copy_from_slice(&s.to_bytes());
+    (signature, negated)
+}
+
+/// Verifies the signature `(big_r, s)` of `msg_hash` against `user_pk` directly with the
+/// ECDSA equation `x((msg_hash * G + r * user_pk) / s) == r`, without recovering the public key.
+/// This is the inverse of [`into_eth_sig`].
+pub fn verify_eth_sig(
+    user_pk: &AffinePoint,
+    big_r: &AffinePoint,
+    s: &Scalar,
+    msg_hash: Scalar,
+) -> bool {
+    let r = x_coordinate(big_r);
+    if bool::from(r.is_zero()) {
+        return false;
+    }
+    let Some(s_inv) = Option::<Scalar>::from(s.invert()) else {
+        return false;
+    };
+    let u1 = msg_hash * s_inv;
+    let u2 = r * s_inv;
+    let point =
+        (ProjectivePoint::GENERATOR * u1 + ProjectivePoint::from(*user_pk) * u2).to_affine();
+    x_coordinate(&point) == r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{into_eth_sig, into_solana_sig, verify_eth_sig};
+    use crypto_shared::x_coordinate;
+    use k256::ecdsa::signature::hazmat::PrehashVerifier;
+    use k256::ecdsa::{Signature, VerifyingKey};
+    use k256::elliptic_curve::Field;
+    use k256::{ProjectivePoint, Scalar};
+
+    #[test]
+    fn test_verify_eth_sig() {
+        let mut rng = rand::thread_rng();
+        let sk = Scalar::random(&mut rng);
+        let user_pk = (ProjectivePoint::GENERATOR * sk).to_affine();
+        let msg_hash = Scalar::random(&mut rng);
+
+        let k = Scalar::random(&mut rng);
+        let big_r = (ProjectivePoint::GENERATOR * k).to_affine();
+        let s = k.invert().unwrap() * (msg_hash + x_coordinate(&big_r) * sk);
+
+        let signature = into_eth_sig(&user_pk, &big_r, &s, msg_hash).unwrap();
+        assert!(verify_eth_sig(
+            &user_pk,
+            &signature.big_r.affine_point,
+            &signature.s.scalar,
+            msg_hash
+        ));
+
+        assert!(!verify_eth_sig(
+            &user_pk,
+            &big_r,
+            &s,
+            msg_hash + Scalar::ONE
+        ));
+        assert!(!verify_eth_sig(&user_pk, &big_r, &Scalar::ZERO, msg_hash));
+    }
+
+    #[test]
+    fn test_into_solana_sig() {
+        let mut rng = rand::thread_rng();
+        let sk = Scalar::random(&mut rng);
+        let user_pk = (ProjectivePoint::GENERATOR * sk).to_affine();
+        let msg_hash = Scalar::random(&mut rng);
+        let k = Scalar::random(&mut rng);
+        let big_r = (ProjectivePoint::GENERATOR * k).to_affine();
+        let s = k.invert().unwrap() * (msg_hash + x_coordinate(&big_r) * sk);
+
+        let (packed, negated) = into_solana_sig(&big_r, &s);
+        let signature = Signature::from_slice(&packed).unwrap();
+        assert_eq!(*signature.r().as_ref(), x_coordinate(&big_r));
+        assert_eq!(*signature.s().as_ref(), if negated { -s } else { s });
+        assert!(signature.normalize_s().is_none());
+
+        VerifyingKey::from_affine(user_pk)
+            .unwrap()
+            .verify_prehash(&msg_hash.to_bytes(), &signature)
+            .unwrap();
+    }
+}
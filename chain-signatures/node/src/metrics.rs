@@ -134,6 +134,15 @@ pub(crate) static NUM_TRIPLES_TOTAL: Lazy<IntGaugeVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static TRIPLE_POOL_LOAD_FACTOR: Lazy<GaugeVec> = Lazy::new(|| {
+    try_create_gauge_vec(
+        "multichain_triple_pool_load_factor",
+        "1 - generated triples / max_triples, close to 1 when the triple pool is nearly empty",
+        &["node_account_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static NUM_PRESIGNATURES_MINE: Lazy<IntGaugeVec> = Lazy::new(|| {
     try_create_int_gauge_vec(
         "multichain_num_presignatures_mine",
@@ -465,6 +474,14 @@ pub fn try_create_int_gauge_vec(name: &str, help: &str, labels: &[&str]) -> Resu
     Ok(gauge)
 }
 
+pub fn try_create_gauge_vec(name: &str, help: &str, labels: &[&str]) -> Result<GaugeVec> {
+    check_metric_multichain_prefix(name)?;
+    let opts = Opts::new(name, help);
+    let gauge = GaugeVec::new(opts, labels)?;
+    prometheus::register(Box::new(gauge.clone()))?;
+    Ok(gauge)
+}
+
 pub fn try_create_counter_vec(name: &str, help: &str, labels: &[&str]) -> Result<CounterVec> {
     check_metric_multichain_prefix(name)?;
     let opts = Opts::new(name, help);
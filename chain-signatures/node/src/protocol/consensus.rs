@@ -22,7 +22,7 @@ use std::cmp::Ordering;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use cait_sith::protocol::InitializationError;
+use cait_sith::protocol::{InitializationError, Participant};
 use serde_json::json;
 use tokio::sync::RwLock;
 use url::Url;
@@ -125,22 +125,40 @@ impl ConsensusProtocol for StartedState {
                                     tracing::info!(
                                         "started: contract state is running and we are already a participant"
                                     );
-                                    let triple_manager = Arc::new(RwLock::new(TripleManager::new(
+                                    let mut triple_manager = TripleManager::from_contract_state(
+                                        &contract_state,
                                         me,
-                                        contract_state.threshold,
-                                        epoch,
                                         ctx.my_account_id(),
                                         ctx.triple_storage(),
-                                    )));
+                                    );
+                                    if let Some(max) = ctx.cfg().local.max_triple_generators {
+                                        triple_manager = triple_manager.with_max_generators(max);
+                                    }
+                                    let triple_manager = Arc::new(RwLock::new(triple_manager));
 
+                                    let presignature_manager = PresignatureManager::new(
+                                        me,
+                                        contract_state.threshold,
+                                        epoch,
+                                        ctx.my_account_id(),
+                                        ctx.presignature_storage(),
+                                    );
+                                    if let Err(err) = presignature_manager.compact_storage().await {
+                                        tracing::warn!(
+                                            ?err,
+                                            "failed to compact presignature storage on startup"
+                                        );
+                                    }
+                                    if let Err(err) =
+                                        presignature_manager.rehydrate_from_storage().await
+                                    {
+                                        tracing::warn!(
+                                            ?err,
+                                            "failed to rehydrate presignature storage on startup"
+                                        );
+                                    }
                                     let presignature_manager =
-                                        Arc::new(RwLock::new(PresignatureManager::new(
-                                            me,
-                                            contract_state.threshold,
-                                            epoch,
-                                            ctx.my_account_id(),
-                                            ctx.presignature_storage(),
-                                        )));
+                                        Arc::new(RwLock::new(presignature_manager));
 
                                     let signature_manager =
                                         Arc::new(RwLock::new(SignatureManager::new(
@@ -366,13 +384,17 @@ impl ConsensusProtocol for WaitingForConsensusState {
                         );
                     }
 
-                    let triple_manager = Arc::new(RwLock::new(TripleManager::new(
+                    let mut triple_manager = TripleManager::new(
                         me,
                         self.threshold,
                         self.epoch,
                         ctx.my_account_id(),
                         ctx.triple_storage(),
-                    )));
+                    );
+                    if let Some(max) = ctx.cfg().local.max_triple_generators {
+                        triple_manager = triple_manager.with_max_generators(max);
+                    }
+                    let triple_manager = Arc::new(RwLock::new(triple_manager));
 
                     let presignature_manager = Arc::new(RwLock::new(PresignatureManager::new(
                         me,
@@ -416,6 +438,30 @@ impl ConsensusProtocol for WaitingForConsensusState {
                         if contract_state.public_key != self.public_key {
                             return Err(ConsensusError::MismatchedPublicKey);
                         }
+
+                        // Presignatures generated with participants that are leaving can't be
+                        // used anymore, so drop them before the new epoch starts.
+                        let removed: Vec<Participant> = contract_state
+                            .old_participants
+                            .iter()
+                            .filter(|(_, info)| {
+                                !contract_state
+                                    .new_participants
+                                    .contains_account_id(&info.account_id)
+                            })
+                            .map(|(participant, _)| *participant)
+                            .collect();
+                        // Ongoing triple generation involves the old participant set, so it
+                        // can't complete once resharing starts.
+                        self.triple_manager.write().await.cancel_all_generators();
+                        if !removed.is_empty() {
+                            self.presignature_manager
+                                .write()
+                                .await
+                                .expire_for_participants(&removed)
+                                .await;
+                        }
+
                         start_resharing(Some(self.private_share), ctx, contract_state).await
                     }
                     Ordering::Greater => {
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::PoisonError;
 
 use super::state::{GeneratingState, NodeState, ResharingState, RunningState};
@@ -5,8 +6,9 @@ use super::Config;
 use crate::gcp::error::SecretStorageError;
 use crate::http_client::SendError;
 use crate::mesh::Mesh;
-use crate::protocol::message::{GeneratingMessage, ResharingMessage};
+use crate::protocol::message::{batch_encode, GeneratingMessage, ResharingMessage, TripleMessage};
 use crate::protocol::state::{PersistentNodeData, WaitingForConsensusState};
+use crate::protocol::triple::TripleId;
 use crate::protocol::MpcMessage;
 use crate::storage::secret_storage::SecretNodeStorageBox;
 use async_trait::async_trait;
@@ -51,6 +53,8 @@ pub enum CryptographicError {
     InvalidStateHandle(String),
     #[error("secret storage error: {0}")]
     SecretStorageError(#[from] SecretStorageError),
+    #[error("triple id {0} was already spent")]
+    IdConflict(TripleId),
 }
 
 impl<T> From<PoisonError<T>> for CryptographicError {
@@ -372,12 +376,35 @@ impl CryptographicProtocol for RunningState {
         crate::metrics::MESSAGE_QUEUE_SIZE
             .with_label_values(&[my_account_id.as_str()])
             .set(messages.len() as i64);
-        if let Err(err) = triple_manager.stockpile(active, protocol_cfg).await {
+        triple_manager.gc().await;
+        if let Err(err) = triple_manager
+            .stockpile(active, &self.participants, protocol_cfg)
+            .await
+        {
             tracing::warn!(?err, "running: failed to stockpile triples");
         }
+        triple_manager
+            .record_load_factor(protocol_cfg, ctx.cfg().local.triple_pool_alert_threshold)
+            .await;
+        let mut outgoing: HashMap<Participant, Vec<TripleMessage>> = HashMap::new();
         for (p, msg) in triple_manager.poke(protocol_cfg).await {
+            outgoing.entry(p).or_default().push(msg);
+        }
+        for (p, mut batch) in outgoing {
             let info = self.fetch_participant(&p)?;
-            messages.push(info.clone(), MpcMessage::Triple(msg));
+            let msg = if batch.len() == 1 {
+                MpcMessage::Triple(batch.swap_remove(0))
+            } else {
+                let count = batch.len();
+                match batch_encode(batch) {
+                    Ok(frame) => MpcMessage::TripleBatch(frame.to_vec()),
+                    Err(err) => {
+                        tracing::error!(?err, ?p, count, "failed to batch triple messages");
+                        continue;
+                    }
+                }
+            };
+            messages.push(info.clone(), msg);
         }
 
         crate::metrics::NUM_TRIPLES_MINE
@@ -397,6 +424,7 @@ impl CryptographicProtocol for RunningState {
         if let Err(err) = presignature_manager
             .stockpile(
                 active,
+                &self.participants,
                 &self.public_key,
                 &self.private_share,
                 &mut triple_manager,
@@ -406,6 +434,9 @@ impl CryptographicProtocol for RunningState {
         {
             tracing::warn!(?err, "running: failed to stockpile presignatures");
         }
+        presignature_manager
+            .record_age_of_oldest_mine(ctx.cfg().local.max_presignature_age)
+            .await;
         drop(triple_manager);
         for (p, msg) in presignature_manager.poke().await {
             let info = self.fetch_participant(&p)?;
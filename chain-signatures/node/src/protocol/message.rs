@@ -2,14 +2,16 @@ use super::cryptography::CryptographicError;
 use super::presignature::{GenerationError, PresignatureId};
 use super::signature::SignRequestIdentifier;
 use super::state::{GeneratingState, NodeState, ResharingState, RunningState};
-use super::triple::TripleId;
+use super::triple::{participant_set_hash, TripleId};
 use crate::gcp::error::SecretStorageError;
 use crate::http_client::SendError;
 use crate::indexer::ContractSignRequest;
 use crate::mesh::Mesh;
 use crate::util;
 
+use anyhow::Context;
 use async_trait::async_trait;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use cait_sith::protocol::{InitializationError, MessageData, Participant, ProtocolError};
 use k256::Scalar;
 use mpc_keys::hpke::{self, Ciphered};
@@ -39,6 +41,11 @@ pub struct ResharingMessage {
     pub data: MessageData,
 }
 
+/// A message of a cait-sith triple generation protocol. `data` is opaque to the node and
+/// its size does not tell the protocol round apart: commitment rounds carry short hashes,
+/// while later rounds carry polynomial commitments and the payloads of the multiplication
+/// subprotocols. cait-sith buffers messages until the protocol waits for them, so they do not
+/// have to be delivered in round order either.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct TripleMessage {
     pub id: u64,
@@ -47,6 +54,178 @@ pub struct TripleMessage {
     pub data: MessageData,
     // UNIX timestamp as seconds since the epoch
     pub timestamp: u64,
+    /// Hash of the participants the sender runs the protocol with, see
+    /// [`participant_set_hash`]. Nodes predating it do not send one.
+    #[serde(default)]
+    pub participant_set_hash: Option<[u8; 32]>,
+}
+
+impl TripleMessage {
+    /// Checks that an incoming message is well formed and addressed to the expected epoch
+    /// by one of the known participants.
+    pub fn validate(&self, expected_epoch: u64, known_participants: &[Participant]) -> bool {
+        let reason = if self.epoch != expected_epoch {
+            "unexpected epoch"
+        } else if !known_participants.contains(&self.from) {
+            "unknown sender"
+        } else if self.data.is_empty() {
+            "empty data"
+        } else {
+            return true;
+        };
+        tracing::warn!(
+            id = self.id,
+            epoch = self.epoch,
+            expected_epoch,
+            from = ?self.from,
+            reason,
+            "invalid triple message"
+        );
+        false
+    }
+
+    /// Checks that the sender runs the protocol with the participant set hashing to
+    /// `expected`. Messages without a hash are accepted.
+    pub fn matches_participant_set(&self, expected: &[u8; 32]) -> bool {
+        match &self.participant_set_hash {
+            Some(hash) if hash != expected => {
+                tracing::warn!(
+                    id = self.id,
+                    from = ?self.from,
+                    "discarding triple message for a different participant set"
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Packs multiple triple messages destined to the same participant into a single frame.
+///
+/// The frame is a big-endian `u32` message count followed by each message encoded as a
+/// big-endian `u32` length prefix and its JSON serialized bytes.
+pub fn batch_encode(messages: Vec<TripleMessage>) -> Result<Bytes, serde_json::Error> {
+    let mut buf = BytesMut::new();
+    buf.put_u32(messages.len() as u32);
+    for message in messages {
+        let encoded = serde_json::to_vec(&message)?;
+        buf.put_u32(encoded.len() as u32);
+        buf.put_slice(&encoded);
+    }
+    Ok(buf.freeze())
+}
+
+/// Unpacks a frame produced by [`batch_encode`] back into the original triple messages.
+pub fn batch_decode(mut bytes: Bytes) -> anyhow::Result<Vec<TripleMessage>> {
+    if bytes.remaining() < 4 {
+        anyhow::bail!("triple batch frame is missing the message count");
+    }
+    let count = bytes.get_u32() as usize;
+    let mut messages = Vec::with_capacity(count.min(bytes.remaining() / 4));
+    for i in 0..count {
+        if bytes.remaining() < 4 {
+            anyhow::bail!("triple batch frame is missing the length of message {i}");
+        }
+        let len = bytes.get_u32() as usize;
+        if bytes.remaining() < len {
+            anyhow::bail!(
+                "triple batch frame truncated: message {i} needs {len} bytes, {} left",
+                bytes.remaining()
+            );
+        }
+        let encoded = bytes.split_to(len);
+        messages.push(serde_json::from_slice(&encoded)?);
+    }
+    if bytes.has_remaining() {
+        anyhow::bail!(
+            "triple batch frame has {} trailing bytes",
+            bytes.remaining()
+        );
+    }
+    Ok(messages)
+}
+
+/// Size of `data` above which [`CompressedTripleMessage`] compresses it by default.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Largest decompressed size accepted for the `data` of a [`CompressedTripleMessage`]. The size
+/// prefix is checked against it before decompressing so a small message cannot make the node
+/// allocate an arbitrary amount of memory.
+pub const MAX_DECOMPRESSED_DATA_SIZE: usize = 16 * 1024 * 1024;
+
+/// A [`TripleMessage`] whose `data` is LZ4 compressed if it is larger than a threshold, which
+/// keeps the large `SendMany` messages of big participant sets small on the wire.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CompressedTripleMessage {
+    pub id: u64,
+    pub epoch: u64,
+    pub from: Participant,
+    /// Whether `data` is LZ4 compressed.
+    pub compressed: bool,
+    pub data: MessageData,
+    // UNIX timestamp as seconds since the epoch
+    pub timestamp: u64,
+    #[serde(default)]
+    pub participant_set_hash: Option<[u8; 32]>,
+}
+
+impl CompressedTripleMessage {
+    /// Wraps `message`, compressing its data if it is larger than `threshold` bytes and
+    /// compression actually makes it smaller.
+    pub fn compress(message: TripleMessage, threshold: usize) -> Self {
+        let packed = (message.data.len() > threshold)
+            .then(|| lz4_flex::compress_prepend_size(&message.data))
+            .filter(|packed| packed.len() < message.data.len());
+        let compressed = packed.is_some();
+        let data = packed.unwrap_or(message.data);
+        Self {
+            id: message.id,
+            epoch: message.epoch,
+            from: message.from,
+            compressed,
+            data,
+            timestamp: message.timestamp,
+            participant_set_hash: message.participant_set_hash,
+        }
+    }
+}
+
+impl From<TripleMessage> for CompressedTripleMessage {
+    fn from(message: TripleMessage) -> Self {
+        Self::compress(message, DEFAULT_COMPRESSION_THRESHOLD)
+    }
+}
+
+impl TryFrom<CompressedTripleMessage> for TripleMessage {
+    type Error = anyhow::Error;
+
+    fn try_from(message: CompressedTripleMessage) -> Result<Self, Self::Error> {
+        let data = if message.compressed {
+            let size = message
+                .data
+                .get(..4)
+                .map(|prefix| u32::from_le_bytes(prefix.try_into().unwrap()) as usize)
+                .context("compressed triple message is missing its size prefix")?;
+            if size > MAX_DECOMPRESSED_DATA_SIZE {
+                anyhow::bail!(
+                    "compressed triple message claims {size} bytes, more than the \
+                     {MAX_DECOMPRESSED_DATA_SIZE} bytes allowed"
+                );
+            }
+            lz4_flex::decompress_size_prepended(&message.data)?
+        } else {
+            message.data
+        };
+        Ok(Self {
+            id: message.id,
+            epoch: message.epoch,
+            from: message.from,
+            data,
+            timestamp: message.timestamp,
+            participant_set_hash: message.participant_set_hash,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -81,6 +260,9 @@ pub enum MpcMessage {
     Generating(GeneratingMessage),
     Resharing(ResharingMessage),
     Triple(TripleMessage),
+    /// Triple messages for the same participant, packed into one frame by [`batch_encode`]
+    /// so that they are signed, encrypted and sent as a single message.
+    TripleBatch(Vec<u8>),
     Presignature(PresignatureMessage),
     Signature(SignatureMessage),
 }
@@ -91,6 +273,7 @@ impl MpcMessage {
             MpcMessage::Generating(_) => "Generating",
             MpcMessage::Resharing(_) => "Resharing",
             MpcMessage::Triple(_) => "Triple",
+            MpcMessage::TripleBatch(_) => "TripleBatch",
             MpcMessage::Presignature(_) => "Presignature",
             MpcMessage::Signature(_) => "Signature",
         }
@@ -122,6 +305,14 @@ impl MpcMessageQueue {
                 .entry(message.id)
                 .or_default()
                 .push_back(message),
+            MpcMessage::TripleBatch(frame) => match batch_decode(Bytes::from(frame)) {
+                Ok(messages) => {
+                    for message in messages {
+                        self.push(MpcMessage::Triple(message));
+                    }
+                }
+                Err(err) => tracing::warn!(?err, "discarding malformed triple batch"),
+            },
             MpcMessage::Presignature(message) => self
                 .presignature_bins
                 .entry(message.epoch)
@@ -166,6 +357,8 @@ pub enum MessageHandleError {
     RpcError(#[from] near_fetch::Error),
     #[error("secret storage error: {0}")]
     SecretStorageError(#[from] SecretStorageError),
+    #[error("triple id {0} was already spent")]
+    IdConflict(TripleId),
 }
 
 impl From<CryptographicError> for MessageHandleError {
@@ -183,6 +376,7 @@ impl From<CryptographicError> for MessageHandleError {
             CryptographicError::InvalidStateHandle(e) => Self::InvalidStateHandle(e),
             CryptographicError::RpcError(e) => Self::RpcError(e),
             CryptographicError::SecretStorageError(e) => Self::SecretStorageError(e),
+            CryptographicError::IdConflict(id) => Self::IdConflict(id),
         }
     }
 }
@@ -242,8 +436,22 @@ impl MessageHandler for RunningState {
 
         // remove the triple_id that has already failed or taken from the triple_bins
         // and refresh the timestamp of failed and taken
+        let epoch = self.epoch;
+        let known_participants = self.participants.keys_vec();
+        let active_hash = participant_set_hash(&participants.keys_vec());
         let triple_messages = queue.triple_bins.entry(self.epoch).or_default();
         triple_messages.retain(|id, queue| {
+            // A protocol we already run keeps the participants it was started with.
+            let expected_hash = triple_manager
+                .generators
+                .get(id)
+                .map_or(active_hash, |generator| {
+                    participant_set_hash(&generator.participants)
+                });
+            queue.retain(|msg| {
+                msg.validate(epoch, &known_participants)
+                    && msg.matches_participant_set(&expected_hash)
+            });
             if queue.is_empty()
                 || queue.iter().any(|msg| {
                     util::is_elapsed_longer_than_timeout(
@@ -273,6 +481,8 @@ impl MessageHandler for RunningState {
                 }
             };
 
+            // Messages can be handed over in any order. cait-sith buffers each message until the
+            // protocol reaches the point waiting for it, so messages for later rounds are kept.
             if let Some(protocol) = protocol {
                 while let Some(message) = queue.pop_front() {
                     protocol.message(message.from, message.data);
@@ -318,6 +528,7 @@ impl MessageHandler for RunningState {
             let protocol = match presignature_manager
                 .get_or_start_generation(
                     participants,
+                    &known_participants,
                     *id,
                     *triple0,
                     *triple1,
@@ -336,7 +547,8 @@ impl MessageHandler for RunningState {
                 Err(
                     err @ (GenerationError::AlreadyGenerated
                     | GenerationError::TripleIsGarbageCollected(_)
-                    | GenerationError::TripleIsMissing(_)),
+                    | GenerationError::TripleIsMissing(_)
+                    | GenerationError::TripleIsStale(_)),
                 ) => {
                     // This triple has already been generated or removed from the triple manager, so we will have to bin
                     // the entirety of the messages we received for this presignature id, and have the other nodes timeout
@@ -582,3 +794,176 @@ where
         Ok(serde_json::from_slice(&msg)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        batch_decode, batch_encode, CompressedTripleMessage, MpcMessage, MpcMessageQueue,
+        TripleMessage, DEFAULT_COMPRESSION_THRESHOLD, MAX_DECOMPRESSED_DATA_SIZE,
+    };
+    use cait_sith::protocol::Participant;
+
+    #[test]
+    fn test_triple_batch_codec_roundtrip() {
+        let messages = (0..3)
+            .map(|i| TripleMessage {
+                id: i,
+                epoch: 1,
+                from: Participant::from(i as u32),
+                data: vec![i as u8; 10 * i as usize],
+                timestamp: 1234,
+                participant_set_hash: Some([i as u8; 32]),
+            })
+            .collect::<Vec<_>>();
+        let clone_message = |m: &TripleMessage| TripleMessage {
+            id: m.id,
+            epoch: m.epoch,
+            from: m.from,
+            data: m.data.clone(),
+            timestamp: m.timestamp,
+            participant_set_hash: m.participant_set_hash,
+        };
+        let encoded = batch_encode(messages.iter().map(clone_message).collect()).unwrap();
+        assert_eq!(batch_decode(encoded.clone()).unwrap(), messages);
+
+        // A received batch is unpacked into the bins of the individual triples.
+        let mut queue = MpcMessageQueue::default();
+        queue.push(MpcMessage::TripleBatch(encoded.to_vec()));
+        queue.push(MpcMessage::TripleBatch(vec![1, 2, 3]));
+        assert_eq!(queue.triple_bins[&1].len(), 3);
+        assert_eq!(queue.triple_bins[&1][&2][0], messages[2]);
+
+        assert!(batch_decode(batch_encode(Vec::new()).unwrap())
+            .unwrap()
+            .is_empty());
+        let mut truncated = batch_encode(vec![TripleMessage {
+            id: 0,
+            epoch: 0,
+            from: Participant::from(0),
+            data: vec![1, 2, 3],
+            timestamp: 0,
+            participant_set_hash: None,
+        }])
+        .unwrap();
+        truncated.truncate(truncated.len() - 1);
+        assert!(batch_decode(truncated).is_err());
+    }
+
+    #[test]
+    fn test_triple_message_validate() {
+        let known = [Participant::from(0), Participant::from(1)];
+        let message = |id, epoch, from: u32, data: Vec<u8>| TripleMessage {
+            id,
+            epoch,
+            from: Participant::from(from),
+            data,
+            timestamp: 0,
+            participant_set_hash: None,
+        };
+
+        assert!(message(1, 2, 1, vec![1]).validate(2, &known));
+        assert!(!message(1, 3, 1, vec![1]).validate(2, &known));
+        assert!(!message(1, 2, 5, vec![1]).validate(2, &known));
+        assert!(message(0, 2, 1, vec![1]).validate(2, &known));
+        assert!(!message(1, 2, 1, vec![]).validate(2, &known));
+    }
+
+    #[test]
+    fn test_triple_message_matches_participant_set() {
+        let mut message = TripleMessage {
+            id: 1,
+            epoch: 0,
+            from: Participant::from(0),
+            data: vec![1],
+            timestamp: 0,
+            participant_set_hash: None,
+        };
+        assert!(message.matches_participant_set(&[1; 32]));
+        message.participant_set_hash = Some([1; 32]);
+        assert!(message.matches_participant_set(&[1; 32]));
+        assert!(!message.matches_participant_set(&[2; 32]));
+
+        // Messages from nodes predating the hash still deserialize.
+        let legacy = r#"{"id":1,"epoch":0,"from":0,"data":[1],"timestamp":0}"#;
+        let legacy: TripleMessage = serde_json::from_str(legacy).unwrap();
+        assert_eq!(legacy.participant_set_hash, None);
+    }
+
+    #[test]
+    fn test_compressed_triple_message_roundtrip() {
+        let small = TripleMessage {
+            id: 1,
+            epoch: 2,
+            from: Participant::from(3),
+            data: vec![7; DEFAULT_COMPRESSION_THRESHOLD],
+            timestamp: 1234,
+            participant_set_hash: None,
+        };
+        let compressed = CompressedTripleMessage::from(small);
+        assert!(!compressed.compressed);
+        assert_eq!(compressed.data.len(), DEFAULT_COMPRESSION_THRESHOLD);
+        let small = TripleMessage::try_from(compressed).unwrap();
+        assert_eq!(small.data, vec![7; DEFAULT_COMPRESSION_THRESHOLD]);
+
+        let data: Vec<u8> = (0..8 * DEFAULT_COMPRESSION_THRESHOLD)
+            .map(|i| (i % 16) as u8)
+            .collect();
+        let large = TripleMessage {
+            id: 1,
+            epoch: 2,
+            from: Participant::from(3),
+            data: data.clone(),
+            timestamp: 1234,
+            participant_set_hash: Some([9; 32]),
+        };
+        let compressed = CompressedTripleMessage::from(large);
+        assert!(compressed.compressed);
+        assert!(compressed.data.len() < data.len());
+        let large = TripleMessage::try_from(compressed).unwrap();
+        assert_eq!(
+            large,
+            TripleMessage {
+                id: 1,
+                epoch: 2,
+                from: Participant::from(3),
+                data,
+                timestamp: 1234,
+                participant_set_hash: Some([9; 32]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_compressed_triple_message_limits() {
+        // Random data does not compress, so it is sent as is.
+        let data: Vec<u8> = (0..4 * DEFAULT_COMPRESSION_THRESHOLD)
+            .map(|_| rand::random())
+            .collect();
+        let message = TripleMessage {
+            id: 1,
+            epoch: 2,
+            from: Participant::from(3),
+            data: data.clone(),
+            timestamp: 1234,
+            participant_set_hash: None,
+        };
+        let compressed = CompressedTripleMessage::from(message);
+        assert!(!compressed.compressed);
+        assert_eq!(compressed.data, data);
+
+        let oversized = CompressedTripleMessage {
+            id: 1,
+            epoch: 2,
+            from: Participant::from(3),
+            compressed: true,
+            data: ((MAX_DECOMPRESSED_DATA_SIZE + 1) as u32)
+                .to_le_bytes()
+                .into_iter()
+                .chain([0; 8])
+                .collect(),
+            timestamp: 1234,
+            participant_set_hash: None,
+        };
+        assert!(TripleMessage::try_from(oversized).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use super::message::PresignatureMessage;
-use super::triple::{Triple, TripleId, TripleManager};
+use super::triple::{PoolHealth, TakeError, Triple, TripleId, TripleManager};
 use crate::protocol::contract::primitives::Participants;
 use crate::storage::presignature_storage::PresignatureStorage;
 use crate::types::{PresignatureProtocol, SecretKeyShare};
@@ -30,6 +30,10 @@ pub struct Presignature {
     pub id: PresignatureId,
     pub output: PresignOutput<Secp256k1>,
     pub participants: Vec<Participant>,
+    /// The epoch in which this presignature was generated.
+    pub origin_epoch: u64,
+    /// UNIX timestamp as seconds since the epoch of when this presignature was generated.
+    pub created_at: u64,
 }
 
 impl Serialize for Presignature {
@@ -37,12 +41,14 @@ impl Serialize for Presignature {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("Presignature", 5)?;
+        let mut state = serializer.serialize_struct("Presignature", 7)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("output_big_r", &self.output.big_r)?;
         state.serialize_field("output_k", &self.output.k)?;
         state.serialize_field("output_sigma", &self.output.sigma)?;
         state.serialize_field("participants", &self.participants)?;
+        state.serialize_field("origin_epoch", &self.origin_epoch)?;
+        state.serialize_field("created_at", &self.created_at)?;
         state.end()
     }
 }
@@ -59,6 +65,12 @@ impl<'de> Deserialize<'de> for Presignature {
             output_k: Scalar,
             output_sigma: Scalar,
             participants: Vec<Participant>,
+            // Presignatures stored before `origin_epoch` was introduced default to epoch 0.
+            #[serde(default)]
+            origin_epoch: u64,
+            // Presignatures stored before `created_at` was introduced count as the oldest ones.
+            #[serde(default)]
+            created_at: u64,
         }
 
         let fields = PresignatureFields::deserialize(deserializer)?;
@@ -71,10 +83,78 @@ impl<'de> Deserialize<'de> for Presignature {
                 sigma: fields.output_sigma,
             },
             participants: fields.participants,
+            origin_epoch: fields.origin_epoch,
+            created_at: fields.created_at,
         })
     }
 }
 
+/// A mine presignature that has been removed from the mine index but whose data is still
+/// kept in storage. Either `commit` it once it is used, or `rollback` to make it available
+/// again. Dropping the guard without committing rolls it back.
+pub struct ReservedPresignature {
+    presignature: Option<Presignature>,
+    storage: PresignatureStorage,
+}
+
+impl ReservedPresignature {
+    pub fn id(&self) -> PresignatureId {
+        self.presignature().id
+    }
+
+    pub fn presignature(&self) -> &Presignature {
+        self.presignature
+            .as_ref()
+            .expect("presignature is only taken when the guard is consumed")
+    }
+
+    /// Permanently deletes the presignature from storage and hands it out to the caller.
+    pub async fn commit(mut self) -> anyhow::Result<Presignature> {
+        let presignature = self
+            .presignature
+            .take()
+            .expect("presignature is only taken when the guard is consumed");
+        if let Err(e) = self.storage.commit_reserved(&presignature.id).await {
+            self.presignature = Some(presignature);
+            return Err(e);
+        }
+        tracing::debug!(id = presignature.id, "committed reserved presignature");
+        Ok(presignature)
+    }
+
+    /// Makes the presignature available to be taken again.
+    pub async fn rollback(mut self) -> anyhow::Result<()> {
+        let presignature = self
+            .presignature
+            .take()
+            .expect("presignature is only taken when the guard is consumed");
+        tracing::debug!(id = presignature.id, "rolling back reserved presignature");
+        self.storage.rollback_reserved(&presignature.id).await
+    }
+}
+
+impl Drop for ReservedPresignature {
+    fn drop(&mut self) {
+        let Some(presignature) = self.presignature.take() else {
+            return;
+        };
+        let id = presignature.id;
+        let storage = self.storage.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = storage.rollback_reserved(&id).await {
+                        tracing::error!(id, ?e, "failed to roll back dropped presignature");
+                    }
+                });
+            }
+            Err(_) => {
+                tracing::error!(id, "no runtime to roll back dropped presignature");
+            }
+        }
+    }
+}
+
 /// An ongoing presignature generator.
 pub struct PresignatureGenerator {
     pub participants: Vec<Participant>,
@@ -137,6 +217,8 @@ pub enum GenerationError {
     TripleIsGenerating(TripleId),
     #[error("triple {0} is in garbage collection")]
     TripleIsGarbageCollected(TripleId),
+    #[error("triple {0} is not valid for the current participants")]
+    TripleIsStale(TripleId),
     #[error("presignature {0} is generating")]
     PresignatureIsGenerating(PresignatureId),
     #[error("presignature {0} is missing")]
@@ -145,6 +227,22 @@ pub enum GenerationError {
     PresignatureIsGarbageCollected(TripleId),
     #[error("presignature bad parameters")]
     PresignatureBadParameters,
+    #[error("failed to take triples: {0}")]
+    TriplesUnavailable(TakeError),
+}
+
+impl From<TakeError> for GenerationError {
+    fn from(err: TakeError) -> Self {
+        match err {
+            TakeError::NotFound { id } => Self::TripleIsMissing(id),
+            TakeError::AlreadySpent { id } => Self::TripleIsGarbageCollected(id),
+            TakeError::Generating { id } => Self::TripleIsGenerating(id),
+            TakeError::Stale { id } => Self::TripleIsStale(id),
+            err @ (TakeError::InsufficientMine { .. } | TakeError::Storage(_)) => {
+                Self::TriplesUnavailable(err)
+            }
+        }
+    }
 }
 
 /// Abstracts how triples are generated by providing a way to request a new triple that will be
@@ -159,6 +257,9 @@ pub struct PresignatureManager {
     /// will be maintained for at most presignature timeout period just so messages are
     /// cycled through the system.
     gc: HashMap<PresignatureId, Instant>,
+    /// Age of the oldest owned presignature and health of the pool, as of the last
+    /// `record_age_of_oldest_mine`.
+    pool_age: Option<(Duration, PoolHealth)>,
     me: Participant,
     threshold: usize,
     epoch: u64,
@@ -178,6 +279,7 @@ impl PresignatureManager {
             generators: HashMap::new(),
             introduced: HashSet::new(),
             gc: HashMap::new(),
+            pool_age: None,
             me,
             threshold,
             epoch,
@@ -194,6 +296,29 @@ impl PresignatureManager {
         }
     }
 
+    /// Stores the given presignatures using a single redis round-trip, e.g. when reloading
+    /// them after a restart. Returns the number of stored presignatures. On failure the error
+    /// is a [`crate::storage::presignature_storage::BatchInsertError`] holding the
+    /// presignatures that were not stored.
+    pub async fn batch_insert(
+        &mut self,
+        presignatures: Vec<Presignature>,
+    ) -> anyhow::Result<usize> {
+        tracing::debug!(count = presignatures.len(), "inserting presignature batch");
+        for presignature in &presignatures {
+            self.gc.remove(&presignature.id);
+        }
+        let stored = self
+            .presignature_storage
+            .batch_insert(presignatures)
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, "failed to insert presignature batch");
+                err
+            })?;
+        Ok(stored)
+    }
+
     pub async fn insert_mine(&mut self, presignature: Presignature) {
         tracing::debug!(id = ?presignature.id, "inserting mine presignature");
         // Remove from taken list if it was there
@@ -203,6 +328,20 @@ impl PresignatureManager {
         }
     }
 
+    /// Restores the storage entries that presignatures stored by older versions are missing,
+    /// and returns how many were restored. Presignatures stay in storage and are never cached
+    /// by the manager, so this is all a restart needs; the presignature data is not rewritten.
+    pub async fn rehydrate_from_storage(&self) -> anyhow::Result<usize> {
+        let restored = self.presignature_storage.backfill().await.map_err(|err| {
+            tracing::error!(?err, "failed to backfill presignature storage");
+            err
+        })?;
+        if restored > 0 {
+            tracing::info!(restored, "rehydrated presignatures from storage");
+        }
+        Ok(restored)
+    }
+
     /// Returns true if the presignature with the given id is already generated
     pub async fn contains(&self, id: &PresignatureId) -> bool {
         self.presignature_storage
@@ -214,6 +353,20 @@ impl PresignatureManager {
             .unwrap_or(false)
     }
 
+    /// Returns true if the presignature with the given id is already generated and was
+    /// generated in the given epoch. Presignatures from other epochs are not usable anymore,
+    /// even though they may still be in storage.
+    pub async fn contains_from_epoch(&self, id: PresignatureId, epoch: u64) -> bool {
+        self.presignature_storage
+            .origin_epoch(&id)
+            .await
+            .map_err(|e| {
+                tracing::warn!(?e, "failed to look up presignature epoch");
+            })
+            .unwrap_or(None)
+            == Some(epoch)
+    }
+
     /// Returns true if the mine presignature with the given id is already generated
     pub async fn contains_mine(&self, id: &PresignatureId) -> bool {
         self.presignature_storage
@@ -247,20 +400,75 @@ impl PresignatureManager {
         Err(GenerationError::PresignatureIsMissing(id))
     }
 
-    pub async fn take_mine(&mut self) -> Option<Presignature> {
-        if let Some(presignature) = self
+    /// Takes the mine presignature that was generated first, so that presignatures are used
+    /// up in the order they were generated and none sits in storage until it expires.
+    pub async fn take_oldest_mine(&mut self) -> Option<Presignature> {
+        let presignature = self
             .presignature_storage
-            .take_mine()
+            .take_oldest_mine()
             .await
             .map_err(|e| {
-                tracing::error!(?e, "failed to look for mine presignature");
+                tracing::error!(?e, "failed to look for oldest mine presignature");
             })
-            .ok()?
-        {
-            tracing::debug!(id = ?presignature.id, "took presignature of mine");
-            return Some(presignature);
-        }
-        None
+            .ok()??;
+        tracing::debug!(id = ?presignature.id, "took oldest presignature of mine");
+        Some(presignature)
+    }
+
+    /// Takes the mine presignature that was generated last.
+    pub async fn take_newest_mine(&mut self) -> Option<Presignature> {
+        let presignature = self
+            .presignature_storage
+            .take_newest_mine()
+            .await
+            .map_err(|e| {
+                tracing::error!(?e, "failed to look for newest mine presignature");
+            })
+            .ok()??;
+        tracing::debug!(id = ?presignature.id, "took newest presignature of mine");
+        Some(presignature)
+    }
+
+    /// Takes a mine presignature that `signer` took part in generating, so that a designated
+    /// signer is guaranteed to be able to fulfill its role in the signing session. Returns
+    /// `None` if there is no such presignature.
+    pub async fn take_for_signer(&mut self, signer: Participant) -> Option<Presignature> {
+        let presignature = self
+            .presignature_storage
+            .take_mine_with_participant(signer)
+            .await
+            .map_err(|e| {
+                tracing::error!(
+                    ?e,
+                    ?signer,
+                    "failed to look for mine presignature for signer"
+                );
+            })
+            .ok()??;
+        tracing::debug!(
+            id = presignature.id,
+            ?signer,
+            "took presignature of mine for signer"
+        );
+        Some(presignature)
+    }
+
+    /// Reserves a mine presignature by id. Only a single caller can reserve a given id, so
+    /// unlike `contains_mine` followed by `take_oldest_mine`, this is free of races.
+    pub async fn reserve(&self, id: PresignatureId) -> Option<ReservedPresignature> {
+        let presignature = self
+            .presignature_storage
+            .reserve_mine(&id)
+            .await
+            .map_err(|e| {
+                tracing::error!(?e, id, "failed to reserve mine presignature");
+            })
+            .ok()??;
+        tracing::debug!(id, "reserved mine presignature");
+        Some(ReservedPresignature {
+            presignature: Some(presignature),
+            storage: self.presignature_storage.clone(),
+        })
     }
 
     /// Returns the number of unspent presignatures available in the manager.
@@ -285,6 +493,135 @@ impl PresignatureManager {
             .unwrap_or(0)
     }
 
+    /// How long ago the oldest presignature owned by this node was generated, or `None` if
+    /// this node owns no presignatures with a known generation time.
+    pub async fn age_of_oldest_mine(&self) -> Option<Duration> {
+        let created_at = self
+            .presignature_storage
+            .oldest_mine_created_at()
+            .await
+            .map_err(|e| {
+                tracing::error!(?e, "failed to find the oldest mine presignature");
+            })
+            .ok()??;
+        let now = Utc::now().timestamp() as u64;
+        Some(Duration::from_secs(now.saturating_sub(created_at)))
+    }
+
+    /// Computes the current [`PresignatureManager::age_of_oldest_mine`] and keeps it, together
+    /// with the resulting pool health, for [`PresignatureManager::pool_age`]. The pool is
+    /// degraded while its oldest owned presignature is older than `max_age`.
+    pub async fn record_age_of_oldest_mine(&mut self, max_age: Duration) -> Option<Duration> {
+        let Some(age) = self.age_of_oldest_mine().await else {
+            self.pool_age = None;
+            return None;
+        };
+        let health = if age > max_age {
+            PoolHealth::Degraded
+        } else {
+            PoolHealth::Healthy
+        };
+        let previous = self.pool_age.replace((age, health));
+        if health == PoolHealth::Degraded
+            && previous.map_or(true, |(_, previous)| previous != health)
+        {
+            tracing::warn!(?age, ?max_age, "presignature pool is degraded");
+        }
+        Some(age)
+    }
+
+    /// Age of the oldest owned presignature and the resulting pool health, as of the last
+    /// [`PresignatureManager::record_age_of_oldest_mine`]. `None` if nothing was recorded yet
+    /// or this node owned no presignatures at the time.
+    pub fn pool_age(&self) -> Option<(Duration, PoolHealth)> {
+        self.pool_age
+    }
+
+    /// Returns the number of unspent presignatures grouped by the epoch they were generated in.
+    pub async fn len_by_epoch(&self) -> HashMap<u64, usize> {
+        self.presignature_storage
+            .len_by_epoch()
+            .await
+            .map_err(|e| {
+                tracing::error!(?e, "failed to count presignatures by epoch");
+            })
+            .unwrap_or_default()
+    }
+
+    /// Removes all presignatures, stored or still being generated, that involve any of the
+    /// `removed` participants, as those can no longer be used once the participants leave.
+    /// Returns the number of removed presignatures.
+    pub async fn expire_for_participants(&mut self, removed: &[Participant]) -> usize {
+        let expired_generators: Vec<PresignatureId> = self
+            .generators
+            .iter()
+            .filter(|(_, generator)| generator.participants.iter().any(|p| removed.contains(p)))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired_generators {
+            self.generators.remove(id);
+            self.introduced.remove(id);
+            self.gc.insert(*id, Instant::now());
+        }
+
+        let expired_stored = self
+            .presignature_storage
+            .remove_with_participants(removed)
+            .await
+            .map_err(|e| {
+                tracing::error!(?e, "failed to expire presignatures of removed participants");
+            })
+            .unwrap_or(0);
+
+        let expired = expired_generators.len() + expired_stored;
+        if expired > 0 {
+            tracing::info!(
+                ?removed,
+                expired,
+                "expired presignatures of removed participants"
+            );
+        }
+        expired
+    }
+
+    /// Removes all stored presignatures that need a participant outside of `online`, since
+    /// they can not be used while those participants are offline. Returns the number of
+    /// pruned presignatures.
+    pub async fn prune_for_offline_participants(&mut self, online: &[Participant]) -> usize {
+        let pruned = self
+            .presignature_storage
+            .remove_not_within(online)
+            .await
+            .map_err(|e| {
+                tracing::error!(?e, "failed to prune presignatures of offline participants");
+            })
+            .unwrap_or(0);
+        if pruned > 0 {
+            tracing::info!(
+                ?online,
+                pruned,
+                "pruned presignatures of offline participants"
+            );
+        }
+        pruned
+    }
+
+    /// Removes duplicate and dangling presignature entries left behind in storage, e.g. by
+    /// crashes or storage version changes. Returns the number of removed entries.
+    pub async fn compact_storage(&self) -> anyhow::Result<usize> {
+        let removed = self.presignature_storage.compact().await?;
+        if removed > 0 {
+            tracing::info!(removed, "compacted presignature storage");
+        }
+        Ok(removed)
+    }
+
+    /// Returns the number of presignature generation protocols that have not completed yet.
+    /// Unlike [`PresignatureManager::len_potential`], completed presignatures are not counted.
+    pub fn len_pending_generation(&self) -> usize {
+        self.generators.len()
+    }
+
     /// Returns if there are unspent presignatures available in the manager.
     pub async fn is_empty(&self) -> bool {
         self.len_generated().await == 0
@@ -399,9 +736,70 @@ impl PresignatureManager {
         Ok(())
     }
 
+    /// Starts a new presignature generation protocol from two specific mine triples instead of
+    /// random ones. The presignature id is derived from the triple ids, so retrying with the
+    /// same triples, e.g. after a restart, can never produce a second presignature from them.
+    /// The triples are checked against the `contract_participants`, while the presignature is
+    /// generated with the `active` ones.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate_with_triples(
+        &mut self,
+        triple_id_0: TripleId,
+        triple_id_1: TripleId,
+        active: &Participants,
+        contract_participants: &Participants,
+        triple_manager: &mut TripleManager,
+        public_key: &PublicKey,
+        private_share: &SecretKeyShare,
+        timeout: u64,
+    ) -> Result<PresignatureId, GenerationError> {
+        let id = hash_as_id(triple_id_0, triple_id_1);
+        if self.generators.contains_key(&id) {
+            return Err(GenerationError::PresignatureIsGenerating(id));
+        } else if self.contains(&id).await {
+            return Err(GenerationError::AlreadyGenerated);
+        } else if self.gc.contains_key(&id) {
+            return Err(GenerationError::PresignatureIsGarbageCollected(id));
+        }
+
+        let (triple0, triple1) = triple_manager
+            .take_two_mine_by_id(triple_id_0, triple_id_1, &contract_participants.keys_vec())
+            .await?;
+        let presig_participants =
+            active.intersection(&[&triple0.public.participants, &triple1.public.participants]);
+        if presig_participants.len() < self.threshold {
+            tracing::warn!(
+                participants = ?presig_participants.keys_vec(),
+                "the intersection of participants is less than the threshold"
+            );
+            triple_manager.insert_mine(triple0).await;
+            triple_manager.insert_mine(triple1).await;
+            return Err(GenerationError::CaitSithInitializationError(
+                InitializationError::BadParameters(format!(
+                    "not enough participants for presignature {id}"
+                )),
+            ));
+        }
+
+        self.generate(
+            &presig_participants,
+            triple0,
+            triple1,
+            public_key,
+            private_share,
+            timeout,
+        )
+        .await?;
+        triple_manager
+            .log_used(&[triple_id_0, triple_id_1], id)
+            .await;
+        Ok(id)
+    }
+
     pub async fn stockpile(
         &mut self,
         active: &Participants,
+        contract_participants: &Participants,
         pk: &PublicKey,
         sk_share: &SecretKeyShare,
         triple_manager: &mut TripleManager,
@@ -417,6 +815,7 @@ impl PresignatureManager {
                 // We will always try to generate a new triple if we have less than the minimum
                 self.len_mine().await < cfg.presignature.min_presignatures as usize
                     && self.introduced.len() < cfg.max_concurrent_introduction as usize
+                    && self.len_pending_generation() < cfg.max_concurrent_generation as usize
             }
         };
 
@@ -424,8 +823,13 @@ impl PresignatureManager {
             tracing::debug!("not enough presignatures, generating");
             // To ensure there is no contention between different nodes we are only using triples
             // that we proposed. This way in a non-BFT environment we are guaranteed to never try
-            // to use the same triple as any other node.
-            if let Some((triple0, triple1)) = triple_manager.take_two_mine().await {
+            // to use the same triple as any other node. Triples are only stale once one of their
+            // participants left the contract; an offline participant is handled by the
+            // intersection below.
+            if let Ok((triple0, triple1)) = triple_manager
+                .take_two_mine(&contract_participants.keys_vec())
+                .await
+            {
                 let presig_participants = active
                     .intersection(&[&triple0.public.participants, &triple1.public.participants]);
                 if presig_participants.len() < self.threshold {
@@ -439,6 +843,7 @@ impl PresignatureManager {
                     triple_manager.insert_mine(triple0).await;
                     triple_manager.insert_mine(triple1).await;
                 } else {
+                    let used = [triple0.id, triple1.id];
                     self.generate(
                         &presig_participants,
                         triple0,
@@ -448,6 +853,9 @@ impl PresignatureManager {
                         cfg.presignature.generation_timeout,
                     )
                     .await?;
+                    triple_manager
+                        .log_used(&used, hash_as_id(used[0], used[1]))
+                        .await;
                 }
             }
         }
@@ -465,6 +873,7 @@ impl PresignatureManager {
     pub async fn get_or_start_generation(
         &mut self,
         participants: &Participants,
+        contract_participants: &[Participant],
         id: PresignatureId,
         triple0: TripleId,
         triple1: TripleId,
@@ -473,7 +882,9 @@ impl PresignatureManager {
         private_share: &SecretKeyShare,
         cfg: &ProtocolConfig,
     ) -> Result<&mut PresignatureProtocol, GenerationError> {
-        if id != hash_as_id(triple0, triple1) {
+        if id != hash_as_id(triple0, triple1)
+            && id != presignature_id_from_triple_ids(triple0, triple1, self.epoch)
+        {
             tracing::error!(id, "presignature id does not match the expected hash");
             Err(GenerationError::PresignatureBadParameters)
         } else if self.contains(&id).await {
@@ -486,44 +897,32 @@ impl PresignatureManager {
             match self.generators.entry(id) {
                 Entry::Vacant(entry) => {
                     tracing::info!(id, "joining protocol to generate a new presignature");
-                    let (triple0, triple1) = match triple_manager.take_two(triple0, triple1).await {
+                    let (triple0_id, triple1_id) = (triple0, triple1);
+                    let (triple0, triple1) = match triple_manager
+                        .take_two(triple0, triple1, contract_participants)
+                        .await
+                    {
                         Ok(result) => result,
-                        Err(error) => match error {
-                            GenerationError::TripleIsGenerating(_) => {
-                                tracing::warn!(
-                                    ?error,
-                                    id,
-                                    triple0,
-                                    triple1,
-                                    "could not initiate non-introduced presignature: one triple is generating"
-                                );
-                                return Err(error);
-                            }
-                            GenerationError::TripleIsGarbageCollected(_) => {
-                                tracing::warn!(
-                                    ?error,
-                                    id,
-                                    triple0,
-                                    triple1,
-                                    "could not initiate non-introduced presignature: one triple is in garbage collection"
-                                );
-                                return Err(error);
-                            }
-                            GenerationError::TripleIsMissing(_) => {
-                                tracing::warn!(
-                                    ?error,
-                                    id,
-                                    triple0,
-                                    triple1,
-                                    "could not initiate non-introduced presignature: one triple is missing"
-                                );
-                                return Err(error);
-                            }
-                            _ => {
-                                tracing::error!(?error, "Unexpected Generation Error");
-                                return Err(error);
-                            }
-                        },
+                        Err(error) => {
+                            let reason = match error {
+                                TakeError::Generating { .. } => "one triple is generating",
+                                TakeError::AlreadySpent { .. } => "one triple was already spent",
+                                TakeError::NotFound { .. } => "one triple is missing",
+                                TakeError::Stale { .. } => "one triple has stale participants",
+                                TakeError::InsufficientMine { .. } | TakeError::Storage(_) => {
+                                    "triples could not be taken"
+                                }
+                            };
+                            let error = GenerationError::from(error);
+                            tracing::warn!(
+                                ?error,
+                                id,
+                                triple0,
+                                triple1,
+                                "could not initiate non-introduced presignature: {reason}"
+                            );
+                            return Err(error);
+                        }
                     };
                     let generator = Self::generate_internal(
                         participants,
@@ -536,6 +935,7 @@ impl PresignatureManager {
                         false,
                         cfg.presignature.generation_timeout,
                     )?;
+                    triple_manager.log_used(&[triple0_id, triple1_id], id).await;
                     let generator = entry.insert(generator);
                     crate::metrics::NUM_TOTAL_HISTORICAL_PRESIGNATURE_GENERATORS
                         .with_label_values(&[self.my_account_id.as_str()])
@@ -552,10 +952,37 @@ impl PresignatureManager {
     ///
     /// An empty vector means we cannot progress until we receive a new message.
     pub async fn poke(&mut self) -> Vec<(Participant, PresignatureMessage)> {
+        self.poke_protocols().await.messages
+    }
+
+    /// Pokes all of the ongoing generation protocols like [`Self::poke`], and additionally
+    /// reports which presignatures completed and the pair of triples each of them consumed.
+    /// Consumed triples that are still present in `triple_manager` are reported as errors,
+    /// since they could be used a second time.
+    pub async fn poke_and_report(
+        &mut self,
+        triple_manager: &TripleManager,
+    ) -> PresignaturePokeResult {
+        let result = self.poke_protocols().await;
+        for (triple0, triple1) in &result.consumed_triples {
+            for id in [triple0, triple1] {
+                if triple_manager.contains(id).await {
+                    tracing::error!(
+                        triple_id = id,
+                        "triple consumed by a presignature is still stored"
+                    );
+                }
+            }
+        }
+        result
+    }
+
+    async fn poke_protocols(&mut self) -> PresignaturePokeResult {
         let mut messages = Vec::new();
         let mut errors = Vec::new();
         let mut new_presignatures = Vec::new();
         let mut new_mine_presignatures = Vec::new();
+        let mut consumed_triples = Vec::new();
         self.generators.retain(|id, generator| {
             loop {
                 let action = match generator.poke() {
@@ -615,6 +1042,8 @@ impl PresignatureManager {
                             id: *id,
                             output,
                             participants: generator.participants.clone(),
+                            origin_epoch: self.epoch,
+                            created_at: Utc::now().timestamp() as u64,
                         };
                         if generator.mine {
                             tracing::info!(id, "assigning presignature to myself");
@@ -625,6 +1054,7 @@ impl PresignatureManager {
                         } else {
                             new_presignatures.push(presignature);
                         }
+                        consumed_triples.push((generator.triple0, generator.triple1));
                         self.introduced.remove(id);
 
                         crate::metrics::PRESIGNATURE_LATENCY
@@ -640,6 +1070,12 @@ impl PresignatureManager {
             }
         });
 
+        let new_presignature_ids = new_presignatures
+            .iter()
+            .chain(new_mine_presignatures.iter())
+            .map(|presignature| presignature.id)
+            .collect();
+
         for presignature in new_presignatures {
             self.insert(presignature).await;
         }
@@ -652,10 +1088,25 @@ impl PresignatureManager {
             tracing::warn!(?errors, "failed to generate some presignatures");
         }
 
-        messages
+        PresignaturePokeResult {
+            new_presignatures: new_presignature_ids,
+            consumed_triples,
+            messages,
+        }
     }
 }
 
+/// Outcome of [`PresignatureManager::poke_and_report`].
+#[derive(Debug, Default)]
+pub struct PresignaturePokeResult {
+    /// Presignatures that completed generation during this poke.
+    pub new_presignatures: Vec<PresignatureId>,
+    /// The pair of triples each completed presignature was generated from.
+    pub consumed_triples: Vec<(TripleId, TripleId)>,
+    /// Messages to be sent to the respective participant.
+    pub messages: Vec<(Participant, PresignatureMessage)>,
+}
+
 pub fn hash_as_id(triple0: TripleId, triple1: TripleId) -> PresignatureId {
     let mut hasher = Sha3_256::new();
     hasher.update(triple0.to_le_bytes());
@@ -666,6 +1117,31 @@ pub fn hash_as_id(triple0: TripleId, triple1: TripleId) -> PresignatureId {
     PresignatureId::from(id)
 }
 
+/// Derives a presignature id from the pair of triples it consumes and the epoch it is
+/// generated in. The triple ids are sorted first so the id does not depend on the order
+/// the initiator picked them in.
+///
+/// Peers accept ids derived either this way or through [`hash_as_id`], so initiators can
+/// switch over once every node in the network verifies both.
+pub fn presignature_id_from_triple_ids(
+    triple0: TripleId,
+    triple1: TripleId,
+    epoch: u64,
+) -> PresignatureId {
+    let (lo, hi) = if triple0 <= triple1 {
+        (triple0, triple1)
+    } else {
+        (triple1, triple0)
+    };
+    let mut hasher = Sha3_256::new();
+    hasher.update(lo.to_le_bytes());
+    hasher.update(hi.to_le_bytes());
+    hasher.update(epoch.to_le_bytes());
+    let id: [u8; 32] = hasher.finalize().into();
+
+    PresignatureId::from_le_bytes(first_8_bytes(id))
+}
+
 const fn first_8_bytes(input: [u8; 32]) -> [u8; 8] {
     let mut output = [0u8; 8];
     let mut i = 0;
@@ -678,10 +1154,23 @@ const fn first_8_bytes(input: [u8; 32]) -> [u8; 8] {
 
 #[cfg(test)]
 mod tests {
+    use cait_sith::protocol::{Action, MessageData, Protocol, ProtocolError};
+    use cait_sith::triples::{TriplePub, TripleShare};
     use cait_sith::{protocol::Participant, PresignOutput};
-    use k256::{elliptic_curve::CurveArithmetic, Secp256k1};
+    use chrono::Utc;
+    use k256::{elliptic_curve::CurveArithmetic, AffinePoint, Scalar, Secp256k1};
+    use mpc_contract::config::ProtocolConfig;
+    use near_account_id::AccountId;
+    use std::time::Duration;
 
-    use crate::protocol::presignature::Presignature;
+    use crate::protocol::contract::primitives::{ParticipantInfo, Participants};
+    use crate::protocol::presignature::{
+        hash_as_id, presignature_id_from_triple_ids, GenerationError, Presignature,
+        PresignatureGenerator, PresignatureManager,
+    };
+    use crate::protocol::triple::{PoolHealth, Triple, TripleId, TripleManager};
+    use crate::storage::presignature_storage::PresignatureStorage;
+    use crate::storage::triple_storage::TripleStorage;
 
     #[tokio::test]
     async fn test_presignature_serialize_deserialize() {
@@ -693,6 +1182,8 @@ mod tests {
                 sigma: <Secp256k1 as CurveArithmetic>::Scalar::ONE,
             },
             participants: vec![Participant::from(1), Participant::from(2)],
+            origin_epoch: 3,
+            created_at: 1_700_000_000,
         };
 
         // Serialize Presignature to JSON
@@ -709,5 +1200,339 @@ mod tests {
         assert_eq!(presignature.output.k, deserialized.output.k);
         assert_eq!(presignature.output.sigma, deserialized.output.sigma);
         assert_eq!(presignature.participants, deserialized.participants);
+        assert_eq!(presignature.origin_epoch, deserialized.origin_epoch);
+        assert_eq!(presignature.created_at, deserialized.created_at);
+    }
+
+    fn presignature(id: u64, participants: Vec<Participant>) -> Presignature {
+        Presignature {
+            id,
+            output: PresignOutput {
+                big_r: AffinePoint::GENERATOR,
+                k: Scalar::ONE,
+                sigma: Scalar::ONE,
+            },
+            participants,
+            origin_epoch: 0,
+            created_at: 1_700_000_000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_take_oldest_mine() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let me = Participant::from(0);
+        let storage = PresignatureStorage::in_memory(&account_id);
+        let mut manager = PresignatureManager::new(me, 2, 0, &account_id, &storage);
+        let participants = vec![me, Participant::from(1)];
+        for (id, created_at) in [(1, 300), (2, 100), (3, 200), (4, 400)] {
+            manager
+                .insert_mine(Presignature {
+                    created_at,
+                    ..presignature(id, participants.clone())
+                })
+                .await;
+        }
+        // Foreign presignatures are never taken.
+        manager
+            .insert(Presignature {
+                created_at: 0,
+                ..presignature(5, participants.clone())
+            })
+            .await;
+
+        assert_eq!(manager.take_oldest_mine().await.unwrap().id, 2);
+        assert_eq!(manager.take_newest_mine().await.unwrap().id, 4);
+        assert_eq!(manager.take_oldest_mine().await.unwrap().id, 3);
+        assert_eq!(manager.take_oldest_mine().await.unwrap().id, 1);
+        assert!(manager.take_oldest_mine().await.is_none());
+        assert!(manager.take_newest_mine().await.is_none());
+        assert!(manager.contains(&5).await);
+    }
+
+    #[tokio::test]
+    async fn test_record_age_of_oldest_mine() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let me = Participant::from(0);
+        let storage = PresignatureStorage::in_memory(&account_id);
+        let mut manager = PresignatureManager::new(me, 2, 0, &account_id, &storage);
+        let max_age = Duration::from_secs(60 * 60);
+        assert_eq!(manager.record_age_of_oldest_mine(max_age).await, None);
+        assert_eq!(manager.pool_age(), None);
+
+        let now = Utc::now().timestamp() as u64;
+        manager
+            .insert_mine(Presignature {
+                created_at: now,
+                ..presignature(1, vec![me, Participant::from(1)])
+            })
+            .await;
+        manager.record_age_of_oldest_mine(max_age).await.unwrap();
+        assert_eq!(manager.pool_age().unwrap().1, PoolHealth::Healthy);
+
+        manager
+            .insert_mine(Presignature {
+                created_at: now - 2 * 60 * 60,
+                ..presignature(2, vec![me, Participant::from(1)])
+            })
+            .await;
+        let age = manager.record_age_of_oldest_mine(max_age).await.unwrap();
+        assert!(age > max_age);
+        assert_eq!(manager.pool_age(), Some((age, PoolHealth::Degraded)));
+    }
+
+    #[test]
+    fn test_presignature_id_from_triple_ids() {
+        assert_eq!(
+            presignature_id_from_triple_ids(1, 2, 7),
+            presignature_id_from_triple_ids(2, 1, 7)
+        );
+        assert_ne!(
+            presignature_id_from_triple_ids(1, 2, 7),
+            presignature_id_from_triple_ids(1, 2, 8)
+        );
+        assert_ne!(presignature_id_from_triple_ids(1, 2, 7), hash_as_id(1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_take_for_signer() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let me = Participant::from(0);
+        let storage = PresignatureStorage::in_memory(&account_id);
+        let mut manager = PresignatureManager::new(me, 2, 0, &account_id, &storage);
+        manager
+            .insert_mine(presignature(1, vec![me, Participant::from(1)]))
+            .await;
+        manager
+            .insert_mine(presignature(2, vec![me, Participant::from(2)]))
+            .await;
+
+        let taken = manager.take_for_signer(Participant::from(2)).await.unwrap();
+        assert_eq!(taken.id, 2);
+        assert!(manager
+            .take_for_signer(Participant::from(2))
+            .await
+            .is_none());
+        assert!(manager
+            .take_for_signer(Participant::from(3))
+            .await
+            .is_none());
+
+        let taken = manager.take_for_signer(Participant::from(1)).await.unwrap();
+        assert_eq!(taken.id, 1);
+        assert_eq!(manager.len_mine().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_prune_for_offline_participants() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let (p0, p1, p2) = (
+            Participant::from(0),
+            Participant::from(1),
+            Participant::from(2),
+        );
+        let storage = PresignatureStorage::in_memory(&account_id);
+        let mut manager = PresignatureManager::new(p0, 2, 0, &account_id, &storage);
+        manager.insert_mine(presignature(1, vec![p0, p1])).await;
+        manager.insert(presignature(2, vec![p1, p2])).await;
+        manager.insert_mine(presignature(3, vec![p0, p2])).await;
+
+        // Participant 2 is offline, so only the presignature generated with 0 and 1 stays usable.
+        assert_eq!(manager.prune_for_offline_participants(&[p0, p1]).await, 2);
+        assert!(manager.contains_mine(&1).await);
+        assert!(!manager.contains(&2).await);
+        assert!(!manager.contains_mine(&3).await);
+        assert_eq!(manager.len_generated().await, 1);
+        assert_eq!(manager.len_mine().await, 1);
+
+        assert_eq!(manager.prune_for_offline_participants(&[p0, p1]).await, 0);
+    }
+
+    fn triple(id: TripleId, participants: Vec<Participant>) -> Triple {
+        Triple {
+            id,
+            share: TripleShare {
+                a: Scalar::ONE,
+                b: Scalar::ONE,
+                c: Scalar::ONE,
+            },
+            public: TriplePub {
+                big_a: AffinePoint::GENERATOR,
+                big_b: AffinePoint::GENERATOR,
+                big_c: AffinePoint::GENERATOR,
+                participants,
+                threshold: 2,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_triples() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let me = Participant::from(0);
+        let mut active = Participants::default();
+        for id in 0..2 {
+            active.insert(&Participant::from(id), ParticipantInfo::new(id));
+        }
+        let triple_storage = TripleStorage::in_memory(&account_id);
+        let mut triple_manager = TripleManager::new(me, 2, 0, &account_id, &triple_storage);
+        let presignature_storage = PresignatureStorage::in_memory(&account_id);
+        let mut presignature_manager =
+            PresignatureManager::new(me, 2, 0, &account_id, &presignature_storage);
+        triple_manager
+            .insert_mine(triple(1, active.keys_vec()))
+            .await;
+        triple_manager
+            .insert_mine(triple(2, active.keys_vec()))
+            .await;
+        let (public_key, private_share) = (AffinePoint::GENERATOR, Scalar::ONE);
+
+        // Nothing is consumed when one of the triples is unknown.
+        assert!(matches!(
+            presignature_manager
+                .generate_with_triples(
+                    1,
+                    3,
+                    &active,
+                    &active,
+                    &mut triple_manager,
+                    &public_key,
+                    &private_share,
+                    60_000
+                )
+                .await,
+            Err(GenerationError::TripleIsMissing(3))
+        ));
+        assert!(triple_manager.contains_mine(&1).await);
+
+        let id = presignature_manager
+            .generate_with_triples(
+                1,
+                2,
+                &active,
+                &active,
+                &mut triple_manager,
+                &public_key,
+                &private_share,
+                60_000,
+            )
+            .await
+            .unwrap();
+        assert_eq!(id, hash_as_id(1, 2));
+        assert!(!triple_manager.contains(&1).await);
+        assert!(!triple_manager.contains(&2).await);
+
+        // Retrying with the same triples does not start a second presignature.
+        assert!(matches!(
+            presignature_manager
+                .generate_with_triples(
+                    1,
+                    2,
+                    &active,
+                    &active,
+                    &mut triple_manager,
+                    &public_key,
+                    &private_share,
+                    60_000
+                )
+                .await,
+            Err(GenerationError::PresignatureIsGenerating(retry)) if retry == id
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stockpile_backpressure() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let me = Participant::from(0);
+        let mut active = Participants::default();
+        for id in 0..2 {
+            active.insert(&Participant::from(id), ParticipantInfo::new(id));
+        }
+        let triple_storage = TripleStorage::in_memory(&account_id);
+        let mut triple_manager = TripleManager::new(me, 2, 0, &account_id, &triple_storage);
+        let presignature_storage = PresignatureStorage::in_memory(&account_id);
+        let mut presignature_manager =
+            PresignatureManager::new(me, 2, 0, &account_id, &presignature_storage);
+        for id in 1..=4 {
+            triple_manager
+                .insert_mine(triple(id, active.keys_vec()))
+                .await;
+        }
+        let (public_key, private_share) = (AffinePoint::GENERATOR, Scalar::ONE);
+        let cfg = ProtocolConfig {
+            max_concurrent_introduction: 2,
+            max_concurrent_generation: 1,
+            ..Default::default()
+        };
+
+        for _ in 0..2 {
+            presignature_manager
+                .stockpile(
+                    &active,
+                    &active,
+                    &public_key,
+                    &private_share,
+                    &mut triple_manager,
+                    &cfg,
+                )
+                .await
+                .unwrap();
+        }
+        // The second call is held back by the ongoing generation.
+        assert_eq!(presignature_manager.len_pending_generation(), 1);
+        assert_eq!(triple_manager.len_mine().await, 2);
+    }
+
+    /// Protocol that completes with `output` on the first poke.
+    struct CompletedPresignProtocol(Option<PresignOutput<Secp256k1>>);
+
+    impl Protocol for CompletedPresignProtocol {
+        type Output = PresignOutput<Secp256k1>;
+
+        fn poke(&mut self) -> Result<Action<Self::Output>, ProtocolError> {
+            Ok(self.0.take().map_or(Action::Wait, Action::Return))
+        }
+
+        fn message(&mut self, _from: Participant, _data: MessageData) {}
+    }
+
+    #[tokio::test]
+    async fn test_poke_and_report() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let me = Participant::from(0);
+        let participants = vec![me, Participant::from(1)];
+        let triple_storage = TripleStorage::in_memory(&account_id);
+        let triple_manager = TripleManager::new(me, 2, 0, &account_id, &triple_storage);
+        let presignature_storage = PresignatureStorage::in_memory(&account_id);
+        let mut presignature_manager =
+            PresignatureManager::new(me, 2, 0, &account_id, &presignature_storage);
+
+        let id = hash_as_id(1, 2);
+        let output = PresignOutput {
+            big_r: AffinePoint::GENERATOR,
+            k: Scalar::ONE,
+            sigma: Scalar::ONE,
+        };
+        presignature_manager.generators.insert(
+            id,
+            PresignatureGenerator::new(
+                Box::new(CompletedPresignProtocol(Some(output))),
+                participants,
+                1,
+                2,
+                true,
+                60_000,
+            ),
+        );
+
+        let result = presignature_manager.poke_and_report(&triple_manager).await;
+        assert_eq!(result.new_presignatures, vec![id]);
+        assert_eq!(result.consumed_triples, vec![(1, 2)]);
+        assert!(result.messages.is_empty());
+        assert!(presignature_manager.contains_mine(&id).await);
+
+        let result = presignature_manager.poke_and_report(&triple_manager).await;
+        assert!(result.new_presignatures.is_empty());
+        assert!(result.consumed_triples.is_empty());
     }
 }
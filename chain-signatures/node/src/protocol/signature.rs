@@ -634,7 +634,7 @@ impl SignatureManager {
             if self.failed.is_empty() && my_requests.is_empty() {
                 None
             } else {
-                presignature_manager.take_mine().await
+                presignature_manager.take_oldest_mine().await
             }
         } {
             let sig_participants = stable.intersection(&[&presignature.participants]);
@@ -672,7 +672,7 @@ impl SignatureManager {
                     continue;
                 }
 
-                if let Some(another_presignature) = presignature_manager.take_mine().await {
+                if let Some(another_presignature) = presignature_manager.take_oldest_mine().await {
                     presignature = another_presignature;
                 } else {
                     break;
@@ -1,23 +1,28 @@
 use super::contract::primitives::Participants;
+use super::contract::RunningContractState;
 use super::cryptography::CryptographicError;
 use super::message::TripleMessage;
-use super::presignature::GenerationError;
+use super::presignature::PresignatureId;
 use crate::storage::triple_storage::TripleStorage;
 use crate::types::TripleProtocol;
-use crate::util::AffinePointExt;
 
 use cait_sith::protocol::{Action, InitializationError, Participant, ProtocolError};
 use cait_sith::triples::{TripleGenerationOutput, TriplePub, TripleShare};
 use chrono::Utc;
+use crypto_shared::ScalarExt;
 use highway::{HighwayHash, HighwayHasher};
 use k256::elliptic_curve::group::GroupEncoding;
-use k256::Secp256k1;
+use k256::{Scalar, Secp256k1};
 use mpc_contract::config::ProtocolConfig;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch, RwLock};
 
 use near_account_id::AccountId;
 
@@ -26,8 +31,27 @@ use near_account_id::AccountId;
 /// messages.
 pub type TripleId = u64;
 
+/// Number of recent completions and takes used to estimate triple throughput.
+const THROUGHPUT_HISTORY_LEN: usize = 32;
+
+/// How long the number of ready mine triples used by [`TripleManager::stockpile`] is cached.
+/// Counting them loads every mine triple from storage, which is too costly for every tick of
+/// the protocol loop.
+const MINE_READY_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often [`TripleManager::gc`] checks the mine set for stale ids. Stale ids only appear
+/// after a crash between storage writes, so there is no need to check on every tick.
+const STALE_MINE_GC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum number of entries listed per section of [`TripleManager::debug_dump`].
+pub const DEBUG_DUMP_LIMIT: usize = 100;
+
 // TODO: why do we have Clone here? Triples can not be reused.
 /// A completed triple.
+///
+/// The secret scalars of the share are serialized through k256's serde impl, which encodes
+/// `Scalar::to_bytes()` with `serdect`. Both are constant-time, so storing triples does not
+/// leak the share through timing.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Triple {
     pub id: TripleId,
@@ -35,12 +59,97 @@ pub struct Triple {
     pub public: TriplePub<Secp256k1>,
 }
 
+impl Triple {
+    /// Builds a deterministic [`TripleId`] out of an epoch and an index within that epoch.
+    /// The epoch occupies the upper 32 bits and the index the lower 32 bits, so only epochs
+    /// up to `u32::MAX` can be encoded; higher bits of `epoch` are discarded.
+    pub fn id_from_epoch_and_index(epoch: u64, index: u32) -> TripleId {
+        (epoch << 32) | index as u64
+    }
+
+    /// Extracts the epoch out of an id built by [`Triple::id_from_epoch_and_index`].
+    pub fn epoch_from_id(id: TripleId) -> u64 {
+        id >> 32
+    }
+
+    /// Extracts the index out of an id built by [`Triple::id_from_epoch_and_index`].
+    pub fn index_from_id(id: TripleId) -> u32 {
+        id as u32
+    }
+
+    /// Returns true if every participant of this triple is still part of `current`. After a
+    /// reshare a triple may reference participants that left, and such a triple can not be
+    /// used to generate a presignature anymore.
+    pub fn public_participants_valid(&self, current: &[Participant]) -> bool {
+        self.public
+            .participants
+            .iter()
+            .all(|participant| current.contains(participant))
+    }
+
+    /// Short fingerprint of the public part of this triple: the first 8 bytes of the SHA-256
+    /// hash over the compressed `big_a`, `big_b` and `big_c`. All participants compute the
+    /// same fingerprint, which makes it easy to correlate logs across nodes.
+    pub fn public_key_fingerprint(&self) -> [u8; 8] {
+        let mut hasher = Sha256::new();
+        for point in [self.public.big_a, self.public.big_b, self.public.big_c] {
+            hasher.update(point.to_bytes());
+        }
+        let hash: [u8; 32] = hasher.finalize().into();
+        let mut fingerprint = [0; 8];
+        fingerprint.copy_from_slice(&hash[..8]);
+        fingerprint
+    }
+
+    /// Returns true if all components of the share are zero. Such a triple is never the
+    /// output of a real generation, only of test fixtures, and signing with it produces an
+    /// invalid signature.
+    pub fn is_zero(&self) -> bool {
+        self.share.a == Scalar::ZERO && self.share.b == Scalar::ZERO && self.share.c == Scalar::ZERO
+    }
+}
+
+/// Compact binary encoding of a [`TripleShare`]: the big-endian bytes of `a`, `b` and `c`,
+/// in that order. This is a third of the size of the serde encoding.
+pub trait TripleShareExt: Sized {
+    fn to_bytes(&self) -> [u8; 96];
+    /// Returns `None` if any of the three scalars is not below the order of Secp256k1.
+    fn from_bytes(bytes: [u8; 96]) -> Option<Self>;
+}
+
+impl TripleShareExt for TripleShare<Secp256k1> {
+    fn to_bytes(&self) -> [u8; 96] {
+        let mut bytes = [0; 96];
+        for (chunk, scalar) in bytes.chunks_exact_mut(32).zip([self.a, self.b, self.c]) {
+            chunk.copy_from_slice(&scalar.to_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: [u8; 96]) -> Option<Self> {
+        let scalar = |i: usize| {
+            let mut chunk = [0; 32];
+            chunk.copy_from_slice(&bytes[i * 32..(i + 1) * 32]);
+            <Scalar as ScalarExt>::from_bytes(chunk)
+        };
+        Some(TripleShare {
+            a: scalar(0)?,
+            b: scalar(1)?,
+            c: scalar(2)?,
+        })
+    }
+}
+
 pub struct TripleGenerator {
     pub id: TripleId,
     pub participants: Vec<Participant>,
     pub protocol: TripleProtocol,
+    /// When the generator was created, i.e. when generation was requested.
+    pub created: Instant,
     pub timestamp: Option<Instant>,
     pub timeout: Duration,
+    /// Participants the last outgoing message of this protocol was addressed to.
+    pub last_sent_to: Vec<Participant>,
 }
 
 impl TripleGenerator {
@@ -54,8 +163,10 @@ impl TripleGenerator {
             id,
             participants,
             protocol,
+            created: Instant::now(),
             timestamp: None,
             timeout: Duration::from_millis(timeout),
+            last_sent_to: Vec::new(),
         }
     }
 
@@ -76,6 +187,139 @@ impl TripleGenerator {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum PokeError {
+    /// The deadline passed before all generators were poked. The generators poked until then
+    /// did advance, so their `messages` still have to be sent.
+    #[error("poking triple protocols timed out after {timeout:?}")]
+    Timeout {
+        timeout: Duration,
+        messages: Vec<(Participant, TripleMessage)>,
+    },
+    #[error("triple protocol failed: {0}")]
+    Protocol(ProtocolError),
+}
+
+/// Version of the [`TripleManagerSnapshot`] state written by this code.
+pub const TRIPLE_MANAGER_SNAPSHOT_VERSION: u32 = SNAPSHOT_MIGRATIONS.len() as u32 + 1;
+
+/// Upgrades the state of a snapshot by one version, in place.
+pub type SnapshotMigration = fn(&mut serde_json::Value) -> anyhow::Result<()>;
+
+/// Registered snapshot migrations. The migration at index `i` upgrades a state of version
+/// `i + 1` to version `i + 2`, so a new schema version is introduced by appending its
+/// migration here.
+const SNAPSHOT_MIGRATIONS: &[SnapshotMigration] = &[migrate_snapshot_v1_to_v2];
+
+/// Version 2 added the cap on concurrently running generators.
+fn migrate_snapshot_v1_to_v2(state: &mut serde_json::Value) -> anyhow::Result<()> {
+    let state = state
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("snapshot state is not an object"))?;
+    state.insert("max_generators".into(), serde_json::Value::Null);
+    Ok(())
+}
+
+/// Serialized state of a [`TripleManager`], which lets a node hand it over to an upgraded
+/// binary. The triples themselves already live in [`TripleStorage`]; the snapshot carries
+/// the bookkeeping that otherwise only exists in memory. Ongoing generators can not be
+/// serialized and are not part of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripleManagerSnapshot {
+    pub version: u32,
+    pub state: serde_json::Value,
+}
+
+/// The state of a [`TripleManagerSnapshot`] at [`TRIPLE_MANAGER_SNAPSHOT_VERSION`].
+#[derive(Serialize, Deserialize)]
+struct SnapshotState {
+    me: Participant,
+    threshold: usize,
+    epoch: u64,
+    my_account_id: AccountId,
+    id_tombstone: HashSet<TripleId>,
+    gc: Vec<TripleId>,
+    max_generators: Option<usize>,
+}
+
+/// Deterministic SHA-256 hash over the sorted ids of `participants`, which lets nodes check
+/// that they run a triple protocol with the same participant set.
+pub fn participant_set_hash(participants: &[Participant]) -> [u8; 32] {
+    let mut ids: Vec<u32> = participants.iter().map(|p| u32::from(*p)).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let mut hasher = Sha256::new();
+    for id in ids {
+        hasher.update(id.to_be_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Health of the triple pool, derived from its load factor, or of the presignature pool,
+/// derived from the age of its oldest owned presignature. See
+/// [`TripleManager::record_load_factor`] and
+/// [`crate::protocol::presignature::PresignatureManager::record_age_of_oldest_mine`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolHealth {
+    #[default]
+    Healthy,
+    /// The triple load factor exceeds the alert threshold, so the pool is close to running
+    /// dry, or the oldest presignature exceeds the maximum age, so the pool is not consumed.
+    Degraded,
+}
+
+/// Entry of the triple audit trail, see [`TripleManager::audit_log`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// The triple finished generating. `timestamp` is in seconds since the UNIX epoch.
+    Generated {
+        id: TripleId,
+        epoch: u64,
+        timestamp: u64,
+        mine: bool,
+    },
+    /// The triple was used to generate the presignature `used_for`.
+    Consumed {
+        id: TripleId,
+        used_for: PresignatureId,
+    },
+    /// The triple will never be available, e.g. because its generation failed.
+    Expired { id: TripleId, reason: String },
+}
+
+impl AuditEvent {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("audit events always serialize")
+    }
+}
+
+/// Why [`TripleManager::take_two`] or [`TripleManager::take_two_mine`] could not take triples.
+/// Nothing is consumed when this is returned.
+#[derive(Debug, thiserror::Error)]
+pub enum TakeError {
+    #[error("triple {id} is missing")]
+    NotFound { id: TripleId },
+    #[error("triple {id} was already spent")]
+    AlreadySpent { id: TripleId },
+    #[error("triple {id} is generating")]
+    Generating { id: TripleId },
+    #[error("triple {id} is not valid for the current participants")]
+    Stale { id: TripleId },
+    #[error("not enough mine triples: have {have}, need {need}")]
+    InsufficientMine { have: usize, need: usize },
+    #[error("storage error: {0}")]
+    Storage(#[from] anyhow::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{me:?} is not one of the participants {participants:?}")]
+pub struct InvalidParticipant {
+    pub me: Participant,
+    pub participants: Vec<Participant>,
+}
+
 /// Abstracts how triples are generated by providing a way to request a new triple that will be
 /// complete some time in the future and a way to take an already generated triple.
 pub struct TripleManager {
@@ -100,10 +344,42 @@ pub struct TripleManager {
     /// triple timeout period just so messages are cycled through the system.
     pub gc: HashMap<TripleId, Instant>,
 
+    /// Ids of the triples taken since this manager was created, by the epoch embedded in them.
+    /// Unlike `gc` these are kept for the whole epoch, so that a spent id can not be generated
+    /// again once its gc entry expired. Ids of older epochs are refused anyway, so their
+    /// entries are dropped by `garbage_collect`.
+    id_tombstone: HashMap<u64, HashSet<TripleId>>,
+
     pub me: Participant,
     pub threshold: usize,
     pub epoch: u64,
     pub my_account_id: AccountId,
+
+    /// Time from generation request to completion of the most recently completed triples.
+    completion_times: VecDeque<Duration>,
+    /// When mine triples were most recently taken.
+    mine_takes: VecDeque<Instant>,
+    /// The last generator poked by `poke_n`, where the next call continues from.
+    poke_cursor: Option<TripleId>,
+    /// Source of the random part of generated triple ids.
+    rng: Box<dyn RngCore + Send + Sync>,
+    /// Callers of `generate_async` waiting for their triple to be generated.
+    completion_waiters: HashMap<TripleId, oneshot::Sender<Triple>>,
+    /// Load factor and health of the pool, as of the last `record_load_factor`.
+    pool_load: Option<(f64, PoolHealth)>,
+    /// Announces the id of the most recently completed triple.
+    completions: watch::Sender<Option<TripleId>>,
+    /// Announces the id of the most recently completed triple that belongs to this node.
+    mine_completions: watch::Sender<Option<TripleId>>,
+    /// Upper bound on the number of generators running at once, if any.
+    max_generators: Option<usize>,
+    /// [`participant_set_hash`] of the participants last passed to
+    /// [`TripleManager::participants_changed`].
+    participants_hash: Option<[u8; 32]>,
+    /// Number of mine triples usable with the contract participants, and when it was counted.
+    mine_ready: Option<(Instant, usize)>,
+    /// When [`TripleManager::gc`] last checked the mine set for stale ids.
+    stale_mine_checked: Option<Instant>,
 }
 
 impl fmt::Debug for TripleManager {
@@ -129,6 +405,42 @@ impl TripleManager {
         epoch: u64,
         my_account_id: &AccountId,
         storage: &TripleStorage,
+    ) -> Self {
+        Self::with_rng(
+            me,
+            threshold,
+            epoch,
+            my_account_id,
+            storage,
+            StdRng::from_entropy(),
+        )
+    }
+
+    /// Same as [`TripleManager::new`], but fails if `me` is not one of `participants`, since
+    /// such a manager would start protocols it can not take part in.
+    pub fn for_participant(
+        me: Participant,
+        participants: Vec<Participant>,
+        threshold: usize,
+        epoch: u64,
+        my_account_id: &AccountId,
+        storage: &TripleStorage,
+    ) -> Result<Self, InvalidParticipant> {
+        if !participants.contains(&me) {
+            return Err(InvalidParticipant { me, participants });
+        }
+        Ok(Self::new(me, threshold, epoch, my_account_id, storage))
+    }
+
+    /// Creates a manager that draws triple ids from the given `rng`, so that a seeded rng
+    /// makes the generated ids reproducible in tests.
+    pub fn with_rng<R: RngCore + Send + Sync + 'static>(
+        me: Participant,
+        threshold: usize,
+        epoch: u64,
+        my_account_id: &AccountId,
+        storage: &TripleStorage,
+        rng: R,
     ) -> Self {
         Self {
             generators: HashMap::new(),
@@ -136,14 +448,111 @@ impl TripleManager {
             ongoing: HashSet::new(),
             introduced: HashSet::new(),
             gc: HashMap::new(),
+            id_tombstone: HashMap::new(),
             me,
             threshold,
             epoch,
             triple_storage: storage.clone(),
             my_account_id: my_account_id.clone(),
+            completion_times: VecDeque::new(),
+            mine_takes: VecDeque::new(),
+            poke_cursor: None,
+            rng: Box::new(rng),
+            completion_waiters: HashMap::new(),
+            pool_load: None,
+            completions: watch::channel(None).0,
+            mine_completions: watch::channel(None).0,
+            max_generators: None,
+            participants_hash: None,
+            mine_ready: None,
+            stale_mine_checked: None,
+        }
+    }
+
+    /// Caps the number of generators running at once, so that an overloaded node can not
+    /// start an unbounded number of protocols. Generating beyond the cap fails.
+    pub fn with_max_generators(mut self, max: usize) -> Self {
+        self.max_generators = Some(max);
+        self
+    }
+
+    /// Creates a manager whose threshold and epoch are taken from the on-chain running state.
+    /// Triple limits are read from the [`ProtocolConfig`] passed to each call, so they always
+    /// follow the contract config as well.
+    pub fn from_contract_state(
+        state: &RunningContractState,
+        me: Participant,
+        my_account_id: &AccountId,
+        storage: &TripleStorage,
+    ) -> Self {
+        Self::new(me, state.threshold, state.epoch, my_account_id, storage)
+    }
+
+    /// Captures the state of this manager that is not kept in storage, see
+    /// [`TripleManagerSnapshot`].
+    pub fn snapshot(&self) -> TripleManagerSnapshot {
+        let state = SnapshotState {
+            me: self.me,
+            threshold: self.threshold,
+            epoch: self.epoch,
+            my_account_id: self.my_account_id.clone(),
+            id_tombstone: self.id_tombstone.values().flatten().copied().collect(),
+            gc: self.gc.keys().copied().collect(),
+            max_generators: self.max_generators,
+        };
+        TripleManagerSnapshot {
+            version: TRIPLE_MANAGER_SNAPSHOT_VERSION,
+            state: serde_json::to_value(state).expect("snapshot state is serializable"),
+        }
+    }
+
+    /// Rebuilds a manager from a snapshot taken by [`TripleManager::snapshot`], possibly by an
+    /// older version of the node, on top of `storage`. Older snapshots are upgraded through
+    /// the registered migrations first. Restored gc entries start their timeout over.
+    pub fn from_snapshot_with_storage(
+        snapshot: TripleManagerSnapshot,
+        storage: TripleStorage,
+    ) -> anyhow::Result<Self> {
+        let TripleManagerSnapshot { version, mut state } = snapshot;
+        if version == 0 || version > TRIPLE_MANAGER_SNAPSHOT_VERSION {
+            anyhow::bail!(
+                "unsupported triple manager snapshot version {version}, expected at most {TRIPLE_MANAGER_SNAPSHOT_VERSION}"
+            );
+        }
+        for (from, migrate) in SNAPSHOT_MIGRATIONS
+            .iter()
+            .enumerate()
+            .skip(version as usize - 1)
+        {
+            migrate(&mut state).map_err(|err| {
+                anyhow::anyhow!(
+                    "failed to migrate snapshot from version {}: {err}",
+                    from + 1
+                )
+            })?;
+        }
+        let state: SnapshotState = serde_json::from_value(state)?;
+
+        let mut manager = Self::new(
+            state.me,
+            state.threshold,
+            state.epoch,
+            &state.my_account_id,
+            &storage,
+        );
+        for id in state.id_tombstone {
+            manager.tombstone(id);
         }
+        manager.prune_tombstone();
+        let now = Instant::now();
+        manager.gc = state.gc.into_iter().map(|id| (id, now)).collect();
+        manager.max_generators = state.max_generators;
+        Ok(manager)
     }
 
+    /// Stores a triple this node took part in generating. The triple holds this node's own
+    /// secret share, so triples only ever come from the local protocol output and are never
+    /// accepted from or sent to other participants.
     pub async fn insert(&mut self, triple: Triple) {
         tracing::debug!(id = triple.id, "inserting triple");
         self.gc.remove(&triple.id);
@@ -152,6 +561,9 @@ impl TripleManager {
         }
     }
 
+    /// Stores a triple this node owns. Ownership is not tracked in memory: the id is added
+    /// to the storage's mine set in the same write as the triple itself, so the mine queue
+    /// survives a restart without a separate flush.
     pub async fn insert_mine(&mut self, triple: Triple) {
         tracing::debug!(id = triple.id, "inserting mine triple");
         self.gc.remove(&triple.id);
@@ -160,6 +572,50 @@ impl TripleManager {
         }
     }
 
+    /// Records that the given triples were consumed by the presignature `used_for`, so that
+    /// a triple being used twice can be detected.
+    pub async fn log_used(&self, ids: &[TripleId], used_for: PresignatureId) {
+        if let Err(e) = self.triple_storage.used_log().record(ids).await {
+            tracing::warn!(?e, ?ids, "failed to log used triples");
+        }
+        let events: Vec<_> = ids
+            .iter()
+            .map(|id| AuditEvent::Consumed { id: *id, used_for })
+            .collect();
+        self.audit(&events).await;
+    }
+
+    /// Returns the audit trail of every triple this node generated, consumed or lost, oldest
+    /// first.
+    pub async fn audit_log(&self) -> Vec<AuditEvent> {
+        self.triple_storage
+            .audit_log()
+            .events()
+            .await
+            .map_err(|e| tracing::warn!(?e, "failed to read triple audit log"))
+            .unwrap_or_default()
+    }
+
+    async fn audit(&self, events: &[AuditEvent]) {
+        if let Err(e) = self.triple_storage.audit_log().append(events).await {
+            tracing::warn!(?e, ?events, "failed to append to triple audit log");
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Position of this node within the ordered set of `participants`, or `None` if this
+    /// node is not part of it.
+    pub fn participant_index(&self, participants: &Participants) -> Option<usize> {
+        let index = participants.keys().position(|p| p == &self.me);
+        if index.is_none() {
+            tracing::error!(me = ?self.me, "node is not in the participant set");
+        }
+        index
+    }
+
     pub async fn contains(&self, id: &TripleId) -> bool {
         self.triple_storage
             .contains(id)
@@ -176,33 +632,58 @@ impl TripleManager {
             .unwrap_or(false)
     }
 
+    /// Whether this node is taking part in the triple `id`, either as one of the
+    /// generators currently running or as a completed triple in storage.
+    pub async fn participating_in(&self, id: TripleId) -> bool {
+        self.generators.contains_key(&id) || self.contains(&id).await
+    }
+
+    /// Whether this node is only observing the triple `id`: the triple is known, but
+    /// `me` is not one of the participants that generated it. Unknown triples are not
+    /// considered observed.
+    pub async fn is_observer(&self, id: TripleId) -> bool {
+        if let Some(generator) = self.generators.get(&id) {
+            return !generator.participants.contains(&self.me);
+        }
+        match self.triple_storage.get(&id).await {
+            Ok(Some(triple)) => !triple.public.participants.contains(&self.me),
+            Ok(None) => false,
+            Err(e) => {
+                tracing::warn!(?e, id, "failed to check if triple is observed");
+                false
+            }
+        }
+    }
+
     /// Take two unspent triple by theirs id with no way to return it. Only takes
-    /// if both of them are present.
+    /// if both of them are present and valid for the `current` contract participants.
+    /// Triples with participants that left can never be used again, so they are removed.
     /// It is very important to NOT reuse the same triple twice for two different
     /// protocols.
     pub async fn take_two(
         &mut self,
         id0: TripleId,
         id1: TripleId,
-    ) -> Result<(Triple, Triple), GenerationError> {
+        current: &[Participant],
+    ) -> Result<(Triple, Triple), TakeError> {
         let triples = &self.triple_storage;
         let triple_0 = match triples.take(&id0).await {
             Ok(Some(triple)) => triple,
             Ok(None) => {
                 if self.generators.contains_key(&id0) {
                     tracing::warn!(id0, "triple is generating");
-                    return Err(GenerationError::TripleIsGenerating(id0));
-                } else if self.gc.contains_key(&id0) {
-                    tracing::warn!(id0, "triple is garbage collected");
-                    return Err(GenerationError::TripleIsGarbageCollected(id0));
+                    return Err(TakeError::Generating { id: id0 });
+                } else if self.is_tombstoned(&id0) {
+                    tracing::warn!(id0, "triple was already spent");
+                    return Err(TakeError::AlreadySpent { id: id0 });
                 } else {
                     tracing::warn!(id0, "triple is missing");
-                    return Err(GenerationError::TripleIsMissing(id0));
+                    return Err(TakeError::NotFound { id: id0 });
                 }
             }
             Err(e) => {
                 tracing::warn!(id0, ?e, "failed to take triple");
-                return Err(GenerationError::TripleIsMissing(id0));
+                return Err(TakeError::Storage(e));
             }
         };
 
@@ -214,13 +695,13 @@ impl TripleManager {
                 }
                 if self.generators.contains_key(&id1) {
                     tracing::warn!(id1, "triple is generating");
-                    return Err(GenerationError::TripleIsGenerating(id1));
-                } else if self.gc.contains_key(&id1) {
-                    tracing::warn!(id1, "triple is garbage collected");
-                    return Err(GenerationError::TripleIsGarbageCollected(id1));
+                    return Err(TakeError::Generating { id: id1 });
+                } else if self.is_tombstoned(&id1) {
+                    tracing::warn!(id1, "triple was already spent");
+                    return Err(TakeError::AlreadySpent { id: id1 });
                 } else {
                     tracing::warn!(id1, "triple is missing");
-                    return Err(GenerationError::TripleIsMissing(id1));
+                    return Err(TakeError::NotFound { id: id1 });
                 }
             }
             Err(e) => {
@@ -228,12 +709,35 @@ impl TripleManager {
                 if let Err(e) = triples.insert(triple_0).await {
                     tracing::warn!(id0, ?e, "failed to insert triple back");
                 }
-                return Err(GenerationError::TripleIsMissing(id1));
+                return Err(TakeError::Storage(e));
             }
         };
 
-        self.gc.insert(id0, Instant::now());
-        self.gc.insert(id1, Instant::now());
+        // Stale triples are removed instead of being consumed by a presignature generation
+        // that is bound to fail. The other triple is still good and goes back.
+        let stale = [&triple_0, &triple_1]
+            .into_iter()
+            .find(|triple| !triple.public_participants_valid(current))
+            .map(|triple| triple.id);
+        if let Some(stale) = stale {
+            tracing::warn!(
+                stale,
+                "removing triple that is not valid for the participants"
+            );
+            for triple in [triple_0, triple_1] {
+                let id = triple.id;
+                if !triple.public_participants_valid(current) {
+                    continue;
+                }
+                if let Err(e) = triples.insert(triple).await {
+                    tracing::warn!(id, ?e, "failed to insert triple back");
+                }
+            }
+            return Err(TakeError::Stale { id: stale });
+        }
+
+        self.mark_spent(id0);
+        self.mark_spent(id1);
 
         tracing::debug!(id0, id1, "took two triples");
 
@@ -241,50 +745,186 @@ impl TripleManager {
     }
 
     /// Take two random unspent triple generated by this node. Either takes both or none.
+    /// Triples that are not valid for the `current` contract participants are removed on
+    /// the way.
     /// It is very important to NOT reuse the same triple twice for two different
     /// protocols.
-    pub async fn take_two_mine(&mut self) -> Option<(Triple, Triple)> {
+    pub async fn take_two_mine(
+        &mut self,
+        current: &[Participant],
+    ) -> Result<(Triple, Triple), TakeError> {
         let triples = &self.triple_storage;
-        if triples.len_mine().await.unwrap_or(0) < 2 {
-            tracing::warn!("not enough mine triples");
-            return None;
+        let have = triples.len_mine().await.map_err(|e| {
+            tracing::warn!(?e, "failed to count mine triples");
+            e
+        })?;
+        if have < 2 {
+            tracing::warn!(have, "not enough mine triples");
+            return Err(TakeError::InsufficientMine { have, need: 2 });
         }
-        let triple_0 = match triples.take_mine().await {
+        let triple_0 = match self.take_mine_valid(current).await {
             Ok(Some(triple)) => triple,
             Ok(None) => {
-                tracing::warn!("no mine triple left");
-                return None;
+                // Stale triples were removed on the way, so what is left is the real count.
+                let have = triples.len_mine().await?;
+                tracing::warn!(have, "not enough valid mine triples");
+                return Err(TakeError::InsufficientMine { have, need: 2 });
             }
             Err(e) => {
                 tracing::warn!(?e, "failed to take mine triple");
-                return None;
+                return Err(TakeError::Storage(e));
             }
         };
 
-        let triple_1 = match triples.take_mine().await {
+        let triple_1 = match self.take_mine_valid(current).await {
             Ok(Some(triple)) => triple,
             Ok(None) => {
                 if let Err(e) = triples.insert_mine(triple_0).await {
                     tracing::warn!(?e, "failed to insert mine triple back");
                 }
-                tracing::warn!("no mine triple left");
-                return None;
+                let have = triples.len_mine().await?;
+                tracing::warn!(have, "not enough valid mine triples");
+                return Err(TakeError::InsufficientMine { have, need: 2 });
             }
             Err(e) => {
                 tracing::warn!(?e, "failed to take mine triple");
                 if let Err(e) = triples.insert_mine(triple_0).await {
                     tracing::warn!(?e, "failed to insert mine triple back");
                 }
-                return None;
+                return Err(TakeError::Storage(e));
             }
         };
 
-        self.gc.insert(triple_0.id, Instant::now());
-        self.gc.insert(triple_1.id, Instant::now());
+        self.mark_spent(triple_0.id);
+        self.mark_spent(triple_1.id);
+        self.record_mine_take();
 
         tracing::debug!(triple_0.id, triple_1.id, "took two mine triples");
 
-        Some((triple_0, triple_1))
+        Ok((triple_0, triple_1))
+    }
+
+    /// Take two specific unspent triples generated by this node. Either takes both or none,
+    /// and only if both are valid for the `current` contract participants. Stale triples
+    /// are removed.
+    /// It is very important to NOT reuse the same triple twice for two different
+    /// protocols.
+    pub async fn take_two_mine_by_id(
+        &mut self,
+        id0: TripleId,
+        id1: TripleId,
+        current: &[Participant],
+    ) -> Result<(Triple, Triple), TakeError> {
+        let triples = &self.triple_storage;
+        let triple_0 = self.take_mine_by_id(id0).await?;
+        let triple_1 = match self.take_mine_by_id(id1).await {
+            Ok(triple) => triple,
+            Err(err) => {
+                if let Err(e) = triples.insert_mine(triple_0).await {
+                    tracing::warn!(id0, ?e, "failed to insert mine triple back");
+                }
+                return Err(err);
+            }
+        };
+
+        let stale = [&triple_0, &triple_1]
+            .into_iter()
+            .find(|triple| !triple.public_participants_valid(current))
+            .map(|triple| triple.id);
+        if let Some(stale) = stale {
+            tracing::warn!(
+                stale,
+                "removing mine triple that is not valid for the participants"
+            );
+            for triple in [triple_0, triple_1] {
+                let id = triple.id;
+                if !triple.public_participants_valid(current) {
+                    continue;
+                }
+                if let Err(e) = triples.insert_mine(triple).await {
+                    tracing::warn!(id, ?e, "failed to insert mine triple back");
+                }
+            }
+            return Err(TakeError::Stale { id: stale });
+        }
+
+        self.mark_spent(id0);
+        self.mark_spent(id1);
+        self.record_mine_take();
+
+        tracing::debug!(id0, id1, "took two mine triples by id");
+
+        Ok((triple_0, triple_1))
+    }
+
+    async fn take_mine_by_id(&self, id: TripleId) -> Result<Triple, TakeError> {
+        match self.triple_storage.take_mine_by_id(&id).await {
+            Ok(Some(triple)) => Ok(triple),
+            Ok(None) if self.generators.contains_key(&id) => {
+                tracing::warn!(id, "mine triple is generating");
+                Err(TakeError::Generating { id })
+            }
+            Ok(None) if self.is_tombstoned(&id) => {
+                tracing::warn!(id, "mine triple was already spent");
+                Err(TakeError::AlreadySpent { id })
+            }
+            Ok(None) => {
+                tracing::warn!(id, "mine triple is missing");
+                Err(TakeError::NotFound { id })
+            }
+            Err(e) => {
+                tracing::warn!(id, ?e, "failed to take mine triple");
+                Err(TakeError::Storage(e))
+            }
+        }
+    }
+
+    fn mark_spent(&mut self, id: TripleId) {
+        self.gc.insert(id, Instant::now());
+        self.tombstone(id);
+    }
+
+    fn is_spent(&self, id: &TripleId) -> bool {
+        self.gc.contains_key(id) || self.is_tombstoned(id)
+    }
+
+    fn tombstone(&mut self, id: TripleId) {
+        self.id_tombstone
+            .entry(Triple::epoch_from_id(id))
+            .or_default()
+            .insert(id);
+    }
+
+    /// Whether `id` was taken in the current or a later epoch since this manager was created.
+    pub fn is_tombstoned(&self, id: &TripleId) -> bool {
+        self.id_tombstone
+            .get(&Triple::epoch_from_id(*id))
+            .is_some_and(|ids| ids.contains(id))
+    }
+
+    /// Drops the tombstones of epochs before the current one.
+    fn prune_tombstone(&mut self) {
+        let epoch = self.epoch;
+        self.id_tombstone.retain(|id_epoch, _| *id_epoch >= epoch);
+    }
+
+    fn record_mine_take(&mut self) {
+        if self.mine_takes.len() == THROUGHPUT_HISTORY_LEN {
+            self.mine_takes.pop_front();
+        }
+        self.mine_takes.push_back(Instant::now());
+    }
+
+    /// Takes a random mine triple that is valid for the `current` contract participants.
+    /// Triples with participants that left are dropped, so each one is only seen once.
+    async fn take_mine_valid(&self, current: &[Participant]) -> anyhow::Result<Option<Triple>> {
+        while let Some(triple) = self.triple_storage.take_mine().await? {
+            if triple.public_participants_valid(current) {
+                return Ok(Some(triple));
+            }
+            tracing::warn!(triple.id, "removed mine triple with stale participants");
+        }
+        Ok(None)
     }
 
     /// Returns the number of unspent triples available in the manager.
@@ -297,6 +937,59 @@ impl TripleManager {
         self.triple_storage.len_mine().await.unwrap_or(0)
     }
 
+    /// Ids of the triples this node owns, in ascending order. Mine triples are stored as a
+    /// set, so the order does not reflect which ones will be taken next.
+    pub async fn get_mine_ids(&self) -> Vec<TripleId> {
+        self.get_all_mine()
+            .await
+            .map_err(|e| tracing::warn!(?e, "failed to fetch mine triple ids"))
+            .unwrap_or_default()
+    }
+
+    /// Same as [`TripleManager::get_mine_ids`], but reports storage failures instead of
+    /// treating them as an empty inventory.
+    pub async fn get_all_mine(&self) -> anyhow::Result<Vec<TripleId>> {
+        self.triple_storage.list_ids(true).await
+    }
+
+    /// Returns the number of unspent triples assigned to this node that can be used right now,
+    /// i.e. whose generation participants are all in `online_participants`.
+    pub async fn len_mine_ready(&self, online_participants: &[Participant]) -> usize {
+        match self.triple_storage.mine().await {
+            Ok(triples) => triples
+                .iter()
+                .filter(|triple| triple.public_participants_valid(online_participants))
+                .count(),
+            Err(e) => {
+                tracing::warn!(?e, "failed to fetch mine triples");
+                0
+            }
+        }
+    }
+
+    /// Whether there are at least two mine triples that every one of `presig_participants`
+    /// took part in generating, i.e. enough to start a presignature with them.
+    pub async fn has_sufficient_for_presignature(
+        &self,
+        presig_participants: &[Participant],
+    ) -> bool {
+        match self.triple_storage.mine().await {
+            Ok(triples) => triples
+                .iter()
+                .filter(|triple| {
+                    presig_participants
+                        .iter()
+                        .all(|p| triple.public.participants.contains(p))
+                })
+                .nth(1)
+                .is_some(),
+            Err(e) => {
+                tracing::warn!(?e, "failed to fetch mine triples");
+                false
+            }
+        }
+    }
+
     /// Returns if there's any unspent triple in the manager.
     pub async fn is_empty(&self) -> bool {
         self.len_generated().await == 0
@@ -308,6 +1001,64 @@ impl TripleManager {
         self.len_generated().await + self.generators.len()
     }
 
+    /// Returns a view of the manager that can only inspect the triple pool.
+    pub fn read_only(&self) -> ReadOnlyTripleManager<'_> {
+        ReadOnlyTripleManager { manager: self }
+    }
+
+    /// Returns how empty the triple pool is, as `1 - generated / max_triples`. Values close
+    /// to 1 mean the pool is nearly empty and generation falls behind.
+    pub async fn load_factor(&self, cfg: &ProtocolConfig) -> f64 {
+        let max_triples = cfg.triple.max_triples.max(1) as f64;
+        (1.0 - self.len_generated().await as f64 / max_triples).clamp(0.0, 1.0)
+    }
+
+    /// Computes the current [`TripleManager::load_factor`] and keeps it, together with the
+    /// resulting pool health, for [`ReadOnlyTripleManager::pool_load`]. The pool is degraded
+    /// while the load factor exceeds `alert_threshold`.
+    pub async fn record_load_factor(&mut self, cfg: &ProtocolConfig, alert_threshold: f64) -> f64 {
+        let load_factor = self.load_factor(cfg).await;
+        let health = if load_factor > alert_threshold {
+            PoolHealth::Degraded
+        } else {
+            PoolHealth::Healthy
+        };
+        let previous = self.pool_load.replace((load_factor, health));
+        if health == PoolHealth::Degraded
+            && previous.map_or(true, |(_, previous)| previous != health)
+        {
+            tracing::warn!(load_factor, alert_threshold, "triple pool is degraded");
+        }
+        crate::metrics::TRIPLE_POOL_LOAD_FACTOR
+            .with_label_values(&[self.my_account_id.as_str()])
+            .set(load_factor);
+        load_factor
+    }
+
+    /// Returns the number of triple generation protocols that have not completed yet.
+    pub fn len_active_generators(&self) -> usize {
+        self.generators.len()
+    }
+
+    /// Abandons all ongoing triple generation protocols, e.g. because they were started with
+    /// a participant set that is about to change. Already generated triples are kept. The
+    /// cancelled ids are garbage collected so late messages for them are ignored, and are
+    /// returned for logging.
+    pub fn cancel_all_generators(&mut self) -> Vec<TripleId> {
+        let cancelled: Vec<TripleId> = self.generators.drain().map(|(id, _)| id).collect();
+        self.queued.clear();
+        self.ongoing.clear();
+        self.introduced.clear();
+        self.completion_waiters.clear();
+        for id in &cancelled {
+            self.gc.insert(*id, Instant::now());
+        }
+        if !cancelled.is_empty() {
+            tracing::info!(?cancelled, "cancelled ongoing triple generators");
+        }
+        cancelled
+    }
+
     pub async fn has_min_triples(&self, cfg: &ProtocolConfig) -> bool {
         self.len_mine().await >= cfg.triple.min_triples as usize
     }
@@ -322,6 +1073,60 @@ impl TripleManager {
         if garbage_collected > 0 {
             tracing::debug!("garbage collected {} triples", garbage_collected);
         }
+        self.prune_tombstone();
+    }
+
+    /// Checks that the stored triples and the ongoing generators are consistent with each other:
+    /// every mine id has a stored triple and no stored triple is still being generated. Every
+    /// violation is logged and `false` is returned if there was any. This loads all ids from
+    /// storage and races concurrent writes, so it is only meant for tests and debugging, not
+    /// for the protocol loop.
+    #[cfg(any(test, feature = "debug-tools"))]
+    pub async fn check_invariants(&self) -> bool {
+        let (ids, mine_ids) = match (
+            self.triple_storage.ids().await,
+            self.triple_storage.mine_ids().await,
+        ) {
+            (Ok(ids), Ok(mine_ids)) => (ids, mine_ids),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::warn!(?e, "failed to fetch triple ids to check invariants");
+                return true;
+            }
+        };
+        let ids: HashSet<TripleId> = ids.into_iter().collect();
+        let mut consistent = true;
+        for id in mine_ids.iter().filter(|id| !ids.contains(id)) {
+            tracing::error!(id, "mine triple is not stored");
+            consistent = false;
+        }
+        for id in self.generators.keys().filter(|id| ids.contains(id)) {
+            tracing::error!(id, "triple is both stored and being generated");
+            consistent = false;
+        }
+        consistent
+    }
+
+    /// Purges ids from the mine set that no longer have a stored triple, which would otherwise
+    /// cause taking mine triples to run into stale entries. Only checks once per
+    /// [`STALE_MINE_GC_INTERVAL`]. Returns the number of ids removed.
+    pub async fn gc(&mut self) -> usize {
+        if self
+            .stale_mine_checked
+            .is_some_and(|checked| checked.elapsed() < STALE_MINE_GC_INTERVAL)
+        {
+            return 0;
+        }
+        self.stale_mine_checked = Some(Instant::now());
+        let removed = self
+            .triple_storage
+            .remove_stale_mine()
+            .await
+            .map_err(|e| tracing::warn!(?e, "failed to remove stale mine triple ids"))
+            .unwrap_or(0);
+        if removed > 0 {
+            tracing::warn!(removed, "removed stale mine triple ids");
+        }
+        removed
     }
 
     /// Refresh item in the garbage collection. If it is present, return true and update internally
@@ -331,26 +1136,191 @@ impl TripleManager {
         matches!(entry, Entry::Occupied(_))
     }
 
-    /// Starts a new Beaver triple generation protocol.
+    /// Dumps the state of this manager as JSON for operators debugging it at runtime: the stored
+    /// and mine triples, the generators with their participants, the queue, the garbage
+    /// collected ids, epoch and threshold. Every section has the full count, but lists at most
+    /// [`DEBUG_DUMP_LIMIT`] entries so the dump stays cheap however large the pool is. Secret
+    /// shares are never included, so the dump is safe to hand out. All id lists are sorted.
+    pub async fn debug_dump(&self) -> serde_json::Value {
+        let triples = self.triple_storage.len_generated().await.unwrap_or(0);
+        let triple_ids = self
+            .triple_storage
+            .sample_ids(false, DEBUG_DUMP_LIMIT)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!(?e, "failed to list triple ids for debug dump");
+                Vec::new()
+            });
+        let mine = self.triple_storage.len_mine().await.unwrap_or(0);
+        let mine_ids = self
+            .triple_storage
+            .sample_ids(true, DEBUG_DUMP_LIMIT)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!(?e, "failed to list mine triple ids for debug dump");
+                Vec::new()
+            });
+
+        let mut generators: Vec<&TripleGenerator> = self.generators.values().collect();
+        generators.sort_unstable_by_key(|generator| generator.id);
+        let generator_list: Vec<serde_json::Value> = generators
+            .iter()
+            .take(DEBUG_DUMP_LIMIT)
+            .map(|generator| {
+                serde_json::json!({
+                    "id": generator.id,
+                    "participants": generator.participants,
+                    "ongoing": self.ongoing.contains(&generator.id),
+                    "introduced": self.introduced.contains(&generator.id),
+                    "elapsed_ms": generator.created.elapsed().as_millis() as u64,
+                })
+            })
+            .collect();
+        let mut queued: Vec<TripleId> = self.queued.iter().copied().collect();
+        queued.sort_unstable();
+        queued.truncate(DEBUG_DUMP_LIMIT);
+        let mut gc: Vec<TripleId> = self.gc.keys().copied().collect();
+        gc.sort_unstable();
+        gc.truncate(DEBUG_DUMP_LIMIT);
+
+        serde_json::json!({
+            "me": self.me,
+            "account_id": self.my_account_id,
+            "epoch": self.epoch,
+            "threshold": self.threshold,
+            "max_generators": self.max_generators,
+            "triples": { "count": triples, "ids": triple_ids },
+            "mine": { "count": mine, "ids": mine_ids },
+            "generators": { "count": generators.len(), "list": generator_list },
+            "queued": { "count": self.queued.len(), "ids": queued },
+            "gc": { "count": self.gc.len(), "ids": gc },
+        })
+    }
+
+    /// Renders the in-progress generators as a Graphviz DOT graph. Every generator is a
+    /// subgraph of its participants with edges pointing to the recipients of the last message
+    /// we sent, which helps spotting the participant a stuck protocol is waiting on.
+    #[cfg(any(test, feature = "debug-tools"))]
+    pub fn to_dot_graph(&self) -> String {
+        use std::fmt::Write;
+
+        let me = u32::from(self.me);
+        let mut ids: Vec<_> = self.generators.keys().copied().collect();
+        ids.sort_unstable();
+
+        let mut dot = String::from("digraph triples {\n");
+        for id in ids {
+            let generator = &self.generators[&id];
+            let _ = writeln!(dot, "  subgraph cluster_{id} {{");
+            let _ = writeln!(dot, "    label=\"triple {id}\";");
+            for p in &generator.participants {
+                let p = u32::from(*p);
+                let _ = writeln!(dot, "    \"{id}_{p}\" [label=\"{p}\"];");
+            }
+            for p in &generator.last_sent_to {
+                let p = u32::from(*p);
+                let _ = writeln!(dot, "    \"{id}_{me}\" -> \"{id}_{p}\";");
+            }
+            dot.push_str("  }\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Starts a new Beaver triple generation protocol and returns the id of its triple.
     pub async fn generate(
         &mut self,
         participants: &Participants,
         timeout: u64,
-    ) -> Result<(), InitializationError> {
-        let id = rand::random();
+    ) -> Result<TripleId, InitializationError> {
+        if self.participant_index(participants).is_none() {
+            return Err(InitializationError::BadParameters(format!(
+                "{:?} is not in the participant set",
+                self.me
+            )));
+        }
+        if self.epoch > u32::MAX as u64 {
+            return Err(InitializationError::BadParameters(format!(
+                "epoch {} does not fit into a triple id",
+                self.epoch
+            )));
+        }
+        if participants.len() < self.threshold {
+            return Err(InitializationError::BadParameters(format!(
+                "{} participants are not enough to reach the threshold of {}",
+                participants.len(),
+                self.threshold
+            )));
+        }
+        if let Some(max) = self.max_generators {
+            if self.generators.len() >= max {
+                return Err(InitializationError::BadParameters(format!(
+                    "max generators reached: {} of {max} are running",
+                    self.generators.len()
+                )));
+            }
+        }
+        // The whole index is random, which keeps collisions unlikely until tens of thousands of
+        // triples were generated within an epoch. A local collision is caught below. Two nodes
+        // drawing the same id at once only make that generation fail, after which both move on.
+        let id = Triple::id_from_epoch_and_index(self.epoch, self.rng.next_u32());
 
         // Check if the `id` is already in the system. Error out and have the next cycle try again.
-        if self.generators.contains_key(&id)
-            || self.contains(&id).await
-            || self.gc.contains_key(&id)
-        {
+        if self.generators.contains_key(&id) || self.contains(&id).await || self.is_spent(&id) {
             tracing::warn!(id, "triple id collision");
             return Err(InitializationError::BadParameters(format!(
                 "id collision: triple_id={id}"
             )));
         }
 
-        tracing::debug!(id, "starting protocol to generate a new triple");
+        self.generate_with_id(id, participants, timeout).await?;
+        Ok(id)
+    }
+
+    /// Same as [`TripleManager::generate`], but also returns a receiver that resolves to the
+    /// triple once it is generated. The generator is driven by the regular calls to `poke`, so
+    /// that its messages still reach the other participants. The receiver fails if the
+    /// generation fails, times out or is cancelled.
+    pub async fn generate_async(
+        &mut self,
+        participants: &Participants,
+        timeout: u64,
+    ) -> Result<oneshot::Receiver<Triple>, InitializationError> {
+        let id = self.generate(participants, timeout).await?;
+        let (sender, receiver) = oneshot::channel();
+        self.completion_waiters.insert(id, sender);
+        Ok(receiver)
+    }
+
+    /// Subscribes to triple completions, so that callers can react to a new triple instead
+    /// of polling. The receiver is notified with the id of each triple once it is stored.
+    /// Like any watch channel it only keeps the latest id, so a slow receiver may observe
+    /// only the last of several triples completed by the same poke.
+    pub fn watch_completions(&self) -> watch::Receiver<Option<TripleId>> {
+        self.completions.subscribe()
+    }
+
+    /// Same as [`TripleManager::watch_completions`], but only for triples that belong to
+    /// this node.
+    pub fn watch_mine_completions(&self) -> watch::Receiver<Option<TripleId>> {
+        self.mine_completions.subscribe()
+    }
+
+    /// Starts a protocol to generate a new triple with the given `id` instead of a random one,
+    /// which makes it possible to reproduce scenarios in tests. Unlike `generate`, the id is
+    /// not checked against stored or garbage collected triples.
+    pub async fn generate_with_id(
+        &mut self,
+        id: TripleId,
+        participants: &Participants,
+        timeout: u64,
+    ) -> Result<(), InitializationError> {
+        debug_assert!(
+            !self.generators.contains_key(&id),
+            "triple {id} is already being generated"
+        );
+
+        tracing::debug!(id, "starting protocol to generate a new triple");
         let participants: Vec<_> = participants.keys().cloned().collect();
         let protocol: TripleProtocol = Box::new(cait_sith::triples::generate_triple::<Secp256k1>(
             &participants,
@@ -371,33 +1341,147 @@ impl TripleManager {
 
     /// Stockpile triples if the amount of unspent triples is below the minimum
     /// and the maximum number of all ongoing generation protocols is below the maximum.
+    /// New triples are generated with `participants`, while mine triples only count towards
+    /// the minimum if they can still be used with `contract_participants`.
     pub async fn stockpile(
         &mut self,
         participants: &Participants,
+        contract_participants: &Participants,
         cfg: &ProtocolConfig,
     ) -> Result<(), InitializationError> {
+        let max_triples = cfg.triple.max_triples as usize;
+        let len_potential = self.len_potential().await;
         let not_enough_triples = {
             // Stopgap to prevent too many triples in the system. This should be around min_triple*nodes*2
             // for good measure so that we have enough triples to do presig generation while also maintain
             // the minimum number of triples where a single node can't flood the system.
-            if self.len_potential().await >= cfg.triple.max_triples as usize {
+            if len_potential >= max_triples {
                 false
             } else {
-                // We will always try to generate a new triple if we have less than the minimum
-                self.len_mine().await < cfg.triple.min_triples as usize
+                // We will always try to generate a new triple if we have less than the minimum.
+                // Mine triples with participants that left can not be used, so they do not count
+                // towards it. Counting those loads the triples, so the cheap count goes first.
+                let min_triples = cfg.triple.min_triples as usize;
+                (self.len_mine().await < min_triples
+                    || self.len_mine_ready_cached(contract_participants).await < min_triples)
                     && self.introduced.len() < cfg.max_concurrent_introduction as usize
                     && self.generators.len() < cfg.max_concurrent_generation as usize
             }
         };
 
         if not_enough_triples {
-            tracing::debug!("not enough triples, generating");
-            self.generate(participants, cfg.triple.generation_timeout)
-                .await?;
+            // A batch must not take the pool past the stopgap above either.
+            let mut batch_size = self
+                .generation_batch_size(cfg)
+                .await
+                .min(max_triples - len_potential);
+            if let Some(max) = self.max_generators {
+                batch_size = batch_size.min(max.saturating_sub(self.generators.len()));
+            }
+            tracing::debug!(batch_size, "not enough triples, generating");
+            for _ in 0..batch_size {
+                self.generate(participants, cfg.triple.generation_timeout)
+                    .await?;
+            }
         }
         Ok(())
     }
 
+    /// [`TripleManager::len_mine_ready`] for `participants`, recounted at most once per
+    /// [`MINE_READY_REFRESH_INTERVAL`].
+    async fn len_mine_ready_cached(&mut self, participants: &Participants) -> usize {
+        match self.mine_ready {
+            Some((counted_at, count)) if counted_at.elapsed() < MINE_READY_REFRESH_INTERVAL => {
+                count
+            }
+            _ => {
+                let count = self.len_mine_ready(&participants.keys_vec()).await;
+                self.mine_ready = Some((Instant::now(), count));
+                count
+            }
+        }
+    }
+
+    /// Starts as many generators as needed to approach the given targets and returns how
+    /// many were started. The deficit of the pool is `target_total` minus the potential
+    /// triples, and the deficit of this node is `target_mine` minus the mine triples and the
+    /// generators introduced by this node. Like `stockpile`, only as many generators are
+    /// started as both deficits allow, so that a node short on its own triples can not grow
+    /// the pool beyond `target_total`.
+    pub async fn warm_up_if_needed(
+        &mut self,
+        participants: &Participants,
+        target_total: usize,
+        target_mine: usize,
+        timeout: u64,
+    ) -> Result<usize, InitializationError> {
+        let total_deficit = target_total.saturating_sub(self.len_potential().await);
+        let mine_deficit =
+            target_mine.saturating_sub(self.len_mine().await + self.introduced.len());
+        let mut needed = total_deficit.min(mine_deficit);
+        if let Some(max) = self.max_generators {
+            needed = needed.min(max.saturating_sub(self.generators.len()));
+        }
+        for _ in 0..needed {
+            self.generate(participants, timeout).await?;
+        }
+        if needed > 0 {
+            tracing::debug!(needed, target_total, target_mine, "warming up triples");
+        }
+        Ok(needed)
+    }
+
+    /// Average time from requesting a triple to its completion over the most recently
+    /// completed triples, or `None` if fewer than 3 triples have completed so far.
+    pub fn expected_completion_time(&self) -> Option<Duration> {
+        if self.completion_times.len() < 3 {
+            return None;
+        }
+        let total: Duration = self.completion_times.iter().sum();
+        Some(total / self.completion_times.len() as u32)
+    }
+
+    fn record_completion(&mut self, elapsed: Duration) {
+        if self.completion_times.len() == THROUGHPUT_HISTORY_LEN {
+            self.completion_times.pop_front();
+        }
+        self.completion_times.push_back(elapsed);
+    }
+
+    /// Average time between two takes of mine triples, or `None` if not enough triples
+    /// have been taken yet. The time since the last take counts as well, so the interval
+    /// grows once takes stop instead of keeping the rate of the last burst.
+    fn mine_take_interval(&self) -> Option<Duration> {
+        if self.mine_takes.len() < 3 {
+            return None;
+        }
+        let first = self.mine_takes.front()?;
+        Some(first.elapsed() / (self.mine_takes.len() - 1) as u32)
+    }
+
+    /// How many triples to start generating at once. Normally this is one, but if our
+    /// mine triples are expected to run out before a new triple completes, start enough
+    /// generators to cover the missing triples, within the concurrency limits.
+    async fn generation_batch_size(&self, cfg: &ProtocolConfig) -> usize {
+        let (Some(completion), Some(take_interval)) =
+            (self.expected_completion_time(), self.mine_take_interval())
+        else {
+            return 1;
+        };
+        let len_mine = self.len_mine().await;
+        // Each take consumes two triples.
+        let consumed = 2 * completion.as_nanos() / take_interval.as_nanos().max(1);
+        if consumed < len_mine as u128 {
+            return 1;
+        }
+        let missing = (cfg.triple.min_triples as usize).saturating_sub(len_mine);
+        let introduce_room =
+            (cfg.max_concurrent_introduction as usize).saturating_sub(self.introduced.len());
+        let generate_room =
+            (cfg.max_concurrent_generation as usize).saturating_sub(self.generators.len());
+        missing.min(introduce_room).min(generate_room).max(1)
+    }
+
     /// Ensures that the triple with the given id is either:
     /// 1) Already generated in which case returns `None`, or
     /// 2) Is currently being generated by `protocol` in which case returns `Some(protocol)`, or
@@ -411,6 +1495,19 @@ impl TripleManager {
     ) -> Result<Option<&mut TripleProtocol>, CryptographicError> {
         if self.contains(&id).await || self.gc.contains_key(&id) {
             Ok(None)
+        } else if !participants.contains_key(&self.me) {
+            // We are only an observer of this triple, so there is no protocol for us to join.
+            tracing::debug!(id, me = ?self.me, "ignoring triple generation we are not part of");
+            Ok(None)
+        } else if self.is_tombstoned(&id) || Triple::epoch_from_id(id) < self.epoch {
+            // The gc entry of the spent triple expired, or the id belongs to an older epoch whose
+            // tombstones were pruned. Starting a new protocol under the same id would mix up its
+            // messages with the ones of the spent triple.
+            tracing::warn!(
+                id,
+                "refusing to generate a triple with a spent or outdated id"
+            );
+            Err(CryptographicError::IdConflict(id))
         } else {
             let potential_len = self.len_potential().await;
             match self.generators.entry(id) {
@@ -450,22 +1547,172 @@ impl TripleManager {
     ///
     /// An empty vector means we cannot progress until we receive a new message.
     pub async fn poke(&mut self, cfg: &ProtocolConfig) -> Vec<(Participant, TripleMessage)> {
-        // Add more protocols to the ongoing pool if there is space.
+        self.poke_detailed(cfg).await.0
+    }
+
+    /// Same as [`TripleManager::poke`], but also returns the ids of the triples that completed
+    /// during this call, both the ones owned by this node and the ones owned by others. The
+    /// triples are already in storage when this returns.
+    pub async fn poke_detailed(
+        &mut self,
+        cfg: &ProtocolConfig,
+    ) -> (Vec<(Participant, TripleMessage)>, Vec<TripleId>) {
+        let (messages, completed, _, _) = self.poke_protocols(cfg, None, None).await;
+        (messages, completed)
+    }
+
+    /// Drives triple generation until this node owns at least `target` triples and returns
+    /// the ids of its triples at that point. Each round tops up the generators through
+    /// [`TripleManager::warm_up_if_needed`], pokes the protocols and hands the resulting
+    /// messages to `send`. The lock is only held within a round, so that messages of the other
+    /// participants can still be delivered to the manager in between. Runs for as long as the
+    /// target is not reached, so callers should bound it with a timeout.
+    pub async fn generate_until(
+        manager: &RwLock<TripleManager>,
+        participants: &Participants,
+        target: usize,
+        cfg: &ProtocolConfig,
+        send: impl Fn(Participant, TripleMessage),
+    ) -> Vec<TripleId> {
+        loop {
+            let messages = {
+                let mut manager = manager.write().await;
+                if manager.len_mine().await >= target {
+                    return manager.get_mine_ids().await;
+                }
+                if let Err(err) = manager
+                    .warm_up_if_needed(
+                        participants,
+                        cfg.triple.max_triples as usize,
+                        target,
+                        cfg.triple.generation_timeout,
+                    )
+                    .await
+                {
+                    tracing::warn!(?err, "failed to start triple generation");
+                }
+                manager.poke(cfg).await
+            };
+            for (to, message) in messages {
+                send(to, message);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Same as [`TripleManager::poke`], but gives up with [`PokeError::Timeout`] if poking
+    /// takes longer than `timeout`. The deadline is checked between generators, so a single
+    /// long running protocol step still runs to completion, and the generators left over are
+    /// poked next time. Triples that completed before the deadline are stored either way.
+    ///
+    /// Failed generators are dropped just like in `poke`. [`PokeError::Protocol`] is only
+    /// returned if generators failed and no generator is left ongoing or has anything to send.
+    pub async fn poke_with_timeout(
+        &mut self,
+        cfg: &ProtocolConfig,
+        timeout: Duration,
+    ) -> Result<Vec<(Participant, TripleMessage)>, PokeError> {
+        let deadline = Instant::now() + timeout;
+        let (messages, _, mut errors, timed_out) =
+            self.poke_protocols(cfg, None, Some(deadline)).await;
+        if timed_out {
+            return Err(PokeError::Timeout { timeout, messages });
+        }
+        if messages.is_empty() && !errors.is_empty() && self.ongoing.is_empty() {
+            return Err(PokeError::Protocol(errors.swap_remove(0)));
+        }
+        Ok(messages)
+    }
+
+    /// Same as [`TripleManager::poke`], but only advances `n` ongoing generators, or all of them
+    /// if fewer are ongoing. The generators are picked round-robin, so every generator makes
+    /// progress over time.
+    ///
+    /// Failed generators are dropped just like in `poke`. An error is only returned if every
+    /// generator poked in this call failed.
+    pub async fn poke_n(
+        &mut self,
+        cfg: &ProtocolConfig,
+        n: usize,
+    ) -> Result<Vec<(Participant, TripleMessage)>, ProtocolError> {
+        self.fill_ongoing(cfg);
+        let selected = self.next_round_robin(n);
+        let poked = selected.len();
+        let (messages, _, mut errors, _) = self.poke_protocols(cfg, Some(selected), None).await;
+        if poked > 0 && errors.len() == poked {
+            return Err(errors.swap_remove(0));
+        }
+        Ok(messages)
+    }
+
+    /// Picks the next `n` ongoing generators after the round-robin cursor, wrapping around.
+    fn next_round_robin(&mut self, n: usize) -> HashSet<TripleId> {
+        let mut ids: Vec<TripleId> = self.ongoing.iter().copied().collect();
+        ids.sort_unstable();
+        let start = match self.poke_cursor {
+            Some(cursor) => ids.partition_point(|id| *id <= cursor),
+            None => 0,
+        };
+        let selected: Vec<TripleId> = ids
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(n.min(ids.len()))
+            .copied()
+            .collect();
+        if let Some(last) = selected.last() {
+            self.poke_cursor = Some(*last);
+        }
+        selected.into_iter().collect()
+    }
+
+    /// Adds more protocols to the ongoing pool if there is space.
+    fn fill_ongoing(&mut self, cfg: &ProtocolConfig) {
         let to_generate_len = cfg.max_concurrent_generation as usize - self.ongoing.len();
         if !self.queued.is_empty() && to_generate_len > 0 {
             for _ in 0..to_generate_len {
                 self.queued.pop_front().map(|id| self.ongoing.insert(id));
             }
         }
+    }
+
+    /// Pokes the ongoing generators, or only the `selected` ones if given. Generators are not
+    /// poked anymore once the `deadline` passed. Returns the outgoing messages, the ids of the
+    /// triples that completed, the errors of the generators that failed and whether the
+    /// deadline cut the poke short.
+    async fn poke_protocols(
+        &mut self,
+        cfg: &ProtocolConfig,
+        selected: Option<HashSet<TripleId>>,
+        deadline: Option<Instant>,
+    ) -> (
+        Vec<(Participant, TripleMessage)>,
+        Vec<TripleId>,
+        Vec<ProtocolError>,
+        bool,
+    ) {
+        self.fill_ongoing(cfg);
 
+        let mut timed_out = false;
         let mut messages = Vec::new();
         let mut errors = Vec::new();
+        let mut expired = Vec::new();
         let mut new_triples = Vec::new();
         let mut new_mine_triples = Vec::new();
+        let mut completion_times = Vec::new();
         self.generators.retain(|id, generator| {
-            if !self.ongoing.contains(id) {
-                // If the protocol is not ongoing, we should retain it for the next time
-                // it is in the ongoing pool.
+            if !self.ongoing.contains(id)
+                || selected
+                    .as_ref()
+                    .is_some_and(|selected| !selected.contains(id))
+            {
+                // If the protocol is not ongoing or not selected, we should retain it for the
+                // next time it is poked.
+                return true;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                // Out of time, the protocol is poked next time.
+                timed_out = true;
                 return true;
             }
 
@@ -473,6 +1720,10 @@ impl TripleManager {
                 let action = match generator.poke() {
                     Ok(action) => action,
                     Err(e) => {
+                        expired.push(AuditEvent::Expired {
+                            id: *id,
+                            reason: e.to_string(),
+                        });
                         errors.push(e);
                         crate::metrics::TRIPLE_GENERATOR_FAILURES
                             .with_label_values(&[self.my_account_id.as_str()])
@@ -480,6 +1731,7 @@ impl TripleManager {
                         self.gc.insert(*id, Instant::now());
                         self.ongoing.remove(id);
                         self.introduced.remove(id);
+                        self.completion_waiters.remove(id);
                         tracing::warn!(
                             elapsed = ?generator.timestamp.unwrap().elapsed(),
                             "added {id} to failed triples"
@@ -495,6 +1747,8 @@ impl TripleManager {
                         break true;
                     }
                     Action::SendMany(data) => {
+                        generator.last_sent_to = generator.participants.clone();
+                        let hash = participant_set_hash(&generator.participants);
                         for p in &generator.participants {
                             messages.push((
                                 *p,
@@ -504,28 +1758,38 @@ impl TripleManager {
                                     from: self.me,
                                     data: data.clone(),
                                     timestamp: Utc::now().timestamp() as u64,
+                                    participant_set_hash: Some(hash),
                                 },
                             ))
                         }
                     }
-                    Action::SendPrivate(p, data) => messages.push((
-                        p,
-                        TripleMessage {
-                            id: *id,
-                            epoch: self.epoch,
-                            from: self.me,
-                            data,
-                            timestamp: Utc::now().timestamp() as u64,
-                        },
-                    )),
+                    Action::SendPrivate(p, data) => {
+                        generator.last_sent_to = vec![p];
+                        messages.push((
+                            p,
+                            TripleMessage {
+                                id: *id,
+                                epoch: self.epoch,
+                                from: self.me,
+                                data,
+                                timestamp: Utc::now().timestamp() as u64,
+                                participant_set_hash: Some(participant_set_hash(
+                                    &generator.participants,
+                                )),
+                            },
+                        ))
+                    }
                     Action::Return(output) => {
+                        let triple = Triple {
+                            id: *id,
+                            share: output.0,
+                            public: output.1,
+                        };
                         tracing::info!(
                             id,
                             me = ?self.me,
                             elapsed = ?generator.timestamp.unwrap().elapsed(),
-                            big_a = ?output.1.big_a.to_base58(),
-                            big_b = ?output.1.big_b.to_base58(),
-                            big_c = ?output.1.big_c.to_base58(),
+                            fingerprint = hex::encode(triple.public_key_fingerprint()),
                             "completed triple generation"
                         );
 
@@ -538,12 +1802,12 @@ impl TripleManager {
                         crate::metrics::NUM_TOTAL_HISTORICAL_TRIPLE_GENERATORS_SUCCESS
                             .with_label_values(&[self.my_account_id.as_str()])
                             .inc();
+                        completion_times.push(generator.created.elapsed());
 
-                        let triple = Triple {
-                            id: *id,
-                            share: output.0,
-                            public: output.1,
-                        };
+                        if let Some(waiter) = self.completion_waiters.remove(id) {
+                            // The caller may have stopped waiting, which is fine.
+                            let _ = waiter.send(triple.clone());
+                        }
 
                         // After creation the triple is assigned to a random node, which is NOT necessarily the one that initiated it's creation
                         let triple_is_mine = {
@@ -583,18 +1847,1208 @@ impl TripleManager {
             }
         });
 
+        for elapsed in completion_times {
+            self.record_completion(elapsed);
+        }
+
+        let timestamp = Utc::now().timestamp() as u64;
+        let generated = new_triples
+            .iter()
+            .map(|triple| (triple.id, false))
+            .chain(new_mine_triples.iter().map(|triple| (triple.id, true)))
+            .map(|(id, mine)| AuditEvent::Generated {
+                id,
+                epoch: self.epoch,
+                timestamp,
+                mine,
+            })
+            .collect::<Vec<_>>();
+        let completed = new_triples
+            .iter()
+            .chain(new_mine_triples.iter())
+            .map(|triple| triple.id)
+            .collect::<Vec<_>>();
+
         for triple in new_triples {
+            let id = triple.id;
             self.insert(triple).await;
+            self.completions.send_replace(Some(id));
         }
 
         for triple in new_mine_triples {
+            let id = triple.id;
             self.insert_mine(triple).await;
+            self.completions.send_replace(Some(id));
+            self.mine_completions.send_replace(Some(id));
         }
+        self.audit(&generated).await;
+        self.audit(&expired).await;
 
         if !errors.is_empty() {
-            tracing::warn!(?errors, "faled to generate some triples");
+            tracing::warn!(?errors, "failed to generate some triples");
+        }
+        (messages, completed, errors, timed_out)
+    }
+}
+
+/// A view of a [`TripleManager`] for diagnostic paths that only exposes queries, so it is not
+/// possible to take or generate triples through it.
+pub struct ReadOnlyTripleManager<'a> {
+    manager: &'a TripleManager,
+}
+
+impl ReadOnlyTripleManager<'_> {
+    pub async fn contains(&self, id: &TripleId) -> bool {
+        self.manager.contains(id).await
+    }
+
+    pub async fn contains_mine(&self, id: &TripleId) -> bool {
+        self.manager.contains_mine(id).await
+    }
+
+    pub async fn len_generated(&self) -> usize {
+        self.manager.len_generated().await
+    }
+
+    pub async fn len_mine(&self) -> usize {
+        self.manager.len_mine().await
+    }
+
+    pub async fn len_potential(&self) -> usize {
+        self.manager.len_potential().await
+    }
+
+    pub async fn get_all_mine(&self) -> anyhow::Result<Vec<TripleId>> {
+        self.manager.get_all_mine().await
+    }
+
+    pub async fn debug_dump(&self) -> serde_json::Value {
+        self.manager.debug_dump().await
+    }
+
+    /// Load factor and health of the pool as last recorded by the protocol loop, if any.
+    pub fn pool_load(&self) -> Option<(f64, PoolHealth)> {
+        self.manager.pool_load
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        participant_set_hash, AuditEvent, CryptographicError, PokeError, PoolHealth, TakeError,
+        Triple, TripleGenerator, TripleId, TripleManager, TripleManagerSnapshot, TripleShareExt,
+        THROUGHPUT_HISTORY_LEN, TRIPLE_MANAGER_SNAPSHOT_VERSION,
+    };
+    use crate::protocol::contract::primitives::{ParticipantInfo, Participants};
+    use crate::storage::triple_storage::TripleStorage;
+
+    use cait_sith::protocol::{Action, MessageData, Participant, Protocol, ProtocolError};
+    use cait_sith::triples::{TripleGenerationOutput, TriplePub, TripleShare};
+    use k256::elliptic_curve::Field;
+    use k256::{AffinePoint, Scalar, Secp256k1};
+    use mpc_contract::config::ProtocolConfig;
+    use near_account_id::AccountId;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+    use std::collections::VecDeque;
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_to_dot_graph() {
+        let mut manager = setup();
+
+        manager
+            .generate_with_id(7, &participants(3), 1000)
+            .await
+            .unwrap();
+        manager.generators.get_mut(&7).unwrap().last_sent_to = vec![Participant::from(2)];
+
+        let dot = manager.to_dot_graph();
+        assert!(dot.starts_with("digraph triples {"));
+        assert!(dot.contains("subgraph cluster_7 {"));
+        assert!(dot.contains("label=\"triple 7\";"));
+        assert!(dot.contains("\"7_1\" [label=\"1\"];"));
+        assert!(dot.contains("\"7_0\" -> \"7_2\";"));
+    }
+
+    #[tokio::test]
+    async fn test_len_mine_ready() {
+        let mut manager = setup();
+        let online = participants(3);
+        let with_offline = vec![0, 1, 3].into_iter().map(Participant::from).collect();
+        manager.insert_mine(mock_triple(1, online.keys_vec())).await;
+        manager.insert_mine(mock_triple(2, online.keys_vec())).await;
+        manager.insert_mine(mock_triple(3, with_offline)).await;
+        manager.insert(mock_triple(4, online.keys_vec())).await;
+
+        assert_eq!(manager.len_mine().await, 3);
+        assert_eq!(manager.len_mine_ready(&online.keys_vec()).await, 2);
+        assert_eq!(manager.len_mine_ready(&participants(4).keys_vec()).await, 3);
+
+        // Only the usable triples count towards the minimum of the pool.
+        let mut cfg = ProtocolConfig::default();
+        cfg.triple.min_triples = 3;
+        manager.stockpile(&online, &online, &cfg).await.unwrap();
+        assert!(!manager.generators.is_empty());
+
+        // The count is cached, so triples becoming usable are only noticed once it expires.
+        manager.generators.clear();
+        manager.introduced.clear();
+        manager.queued.clear();
+        manager.insert_mine(mock_triple(5, online.keys_vec())).await;
+        manager.stockpile(&online, &online, &cfg).await.unwrap();
+        assert!(!manager.generators.is_empty());
+        manager.generators.clear();
+        manager.introduced.clear();
+        manager.queued.clear();
+        manager.mine_ready = None;
+        manager.stockpile(&online, &online, &cfg).await.unwrap();
+        assert!(manager.generators.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stockpile_batch_respects_max_triples() {
+        let mut manager = setup();
+        let participants = participants(3);
+        // Mine triples are taken faster than they complete, which asks for a large batch.
+        for _ in 0..3 {
+            manager.record_completion(Duration::from_secs(10));
+            manager.record_mine_take();
+        }
+        let mut cfg = ProtocolConfig::default();
+        cfg.triple.min_triples = 10;
+        cfg.triple.max_triples = 3;
+        cfg.max_concurrent_introduction = 10;
+        cfg.max_concurrent_generation = 10;
+        manager
+            .insert(mock_triple(1, participants.keys_vec()))
+            .await;
+
+        manager
+            .stockpile(&participants, &participants, &cfg)
+            .await
+            .unwrap();
+        assert_eq!(manager.len_potential().await, 3);
+    }
+
+    #[test]
+    fn test_participants_changed() {
+        let mut manager = setup();
+        let mut participants = participants(3).keys_vec();
+
+        assert!(!manager.participants_changed(&participants));
+        assert!(!manager.participants_changed(&participants));
+        participants.reverse();
+        assert!(!manager.participants_changed(&participants));
+
+        participants.push(Participant::from(3));
+        assert!(manager.participants_changed(&participants));
+        assert!(!manager.participants_changed(&participants));
+    }
+
+    #[tokio::test]
+    async fn test_debug_dump() {
+        let mut manager = setup();
+        let participants = participants(3);
+        manager
+            .insert(mock_triple(2, participants.keys_vec()))
+            .await;
+        manager
+            .insert_mine(mock_triple(1, participants.keys_vec()))
+            .await;
+        manager
+            .generate_with_id(7, &participants, 60_000)
+            .await
+            .unwrap();
+
+        let dump = manager.read_only().debug_dump().await;
+        let dump = dump.as_object().unwrap();
+        let mut keys: Vec<&str> = dump.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "account_id",
+                "epoch",
+                "gc",
+                "generators",
+                "max_generators",
+                "me",
+                "mine",
+                "queued",
+                "threshold",
+                "triples"
+            ]
+        );
+        assert_eq!(dump["me"], 0);
+        assert_eq!(dump["account_id"], "test.near");
+        assert!(dump["epoch"].is_u64());
+        assert!(dump["threshold"].is_u64());
+        assert!(dump["max_generators"].is_null());
+        assert_eq!(
+            dump["triples"],
+            serde_json::json!({ "count": 2, "ids": [1, 2] })
+        );
+        assert_eq!(dump["mine"], serde_json::json!({ "count": 1, "ids": [1] }));
+        assert_eq!(
+            dump["queued"],
+            serde_json::json!({ "count": 1, "ids": [7] })
+        );
+        assert_eq!(dump["gc"], serde_json::json!({ "count": 0, "ids": [] }));
+
+        assert_eq!(dump["generators"]["count"], 1);
+        let generators = dump["generators"]["list"].as_array().unwrap();
+        assert_eq!(generators.len(), 1);
+        assert_eq!(generators[0]["id"], 7);
+        assert_eq!(generators[0]["participants"], serde_json::json!([0, 1, 2]));
+        assert_eq!(generators[0]["ongoing"], false);
+        assert_eq!(generators[0]["introduced"], true);
+        assert!(generators[0]["elapsed_ms"].is_u64());
+
+        // Nothing about the secret shares may leak into the dump.
+        let rendered = serde_json::to_string(&dump).unwrap();
+        assert!(!rendered.contains("share"));
+        assert!(!rendered.contains("big_a"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_id() {
+        let mut manager = setup();
+
+        manager
+            .generate_with_id(42, &participants(3), 1000)
+            .await
+            .unwrap();
+        manager
+            .generate_with_id(43, &participants(3), 1000)
+            .await
+            .unwrap();
+
+        assert!(manager.generators.contains_key(&42));
+        assert!(manager.generators.contains_key(&43));
+        assert_eq!(manager.queued, [42, 43]);
+        assert!(manager.introduced.contains(&42));
+        assert_eq!(manager.generators[&42].participants.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_check_invariants() {
+        let mut manager = setup();
+        let participants = participants(3);
+
+        manager
+            .insert_mine(mock_triple(1, participants.keys_vec()))
+            .await;
+        manager
+            .generate_with_id(2, &participants, 60_000)
+            .await
+            .unwrap();
+        assert!(manager.check_invariants().await);
+
+        // A triple that is stored while its generator is still around is reported, not
+        // panicked on.
+        manager
+            .generate_with_id(1, &participants, 60_000)
+            .await
+            .unwrap();
+        assert!(!manager.check_invariants().await);
+    }
+
+    #[tokio::test]
+    async fn test_poke_n_round_robin() {
+        let mut manager = setup();
+        let cfg = ProtocolConfig::default();
+
+        for id in 0..10 {
+            manager
+                .generate_with_id(id, &participants(3), 60_000)
+                .await
+                .unwrap();
+        }
+        let poked = |manager: &TripleManager| {
+            manager
+                .generators
+                .values()
+                .filter(|generator| generator.timestamp.is_some())
+                .count()
+        };
+
+        // Each generator sends its first message and then waits for the other participants.
+        let messages = manager.poke_n(&cfg, 3).await.unwrap();
+        assert_eq!(poked(&manager), 3);
+        let senders: HashSet<_> = messages.iter().map(|(_, msg)| msg.id).collect();
+        assert_eq!(senders.len(), 3);
+
+        manager.poke_n(&cfg, 3).await.unwrap();
+        manager.poke_n(&cfg, 3).await.unwrap();
+        assert_eq!(poked(&manager), 9);
+
+        // The cursor wraps around after the last generator.
+        manager.poke_n(&cfg, 3).await.unwrap();
+        assert_eq!(poked(&manager), 10);
+        assert_eq!(manager.generators.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_with_rng_is_reproducible() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let storage = TripleStorage::in_memory(&account_id);
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let mut manager = manager(Participant::from(0), &account_id, &storage);
+            for _ in 0..3 {
+                manager.generate(&participants(3), 1000).await.unwrap();
+            }
+            ids.push(manager.queued.clone());
+        }
+        assert_eq!(ids[0].len(), 3);
+        assert_eq!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn test_for_participant() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let storage = TripleStorage::in_memory(&account_id);
+        let participants = vec![Participant::from(1), Participant::from(2)];
+
+        let manager = TripleManager::for_participant(
+            Participant::from(1),
+            participants.clone(),
+            2,
+            0,
+            &account_id,
+            &storage,
+        )
+        .unwrap();
+        assert_eq!(manager.me, Participant::from(1));
+
+        let err = TripleManager::for_participant(
+            Participant::from(0),
+            participants.clone(),
+            2,
+            0,
+            &account_id,
+            &storage,
+        )
+        .unwrap_err();
+        assert_eq!(err.me, Participant::from(0));
+        assert_eq!(err.participants, participants);
+    }
+
+    #[tokio::test]
+    async fn test_with_max_generators() {
+        let mut manager = setup().with_max_generators(2);
+        let participants = participants(3);
+
+        let mut started = 0;
+        for _ in 0..5 {
+            if manager.generate(&participants, 60_000).await.is_ok() {
+                started += 1;
+            }
+            assert!(manager.generators.len() <= 2);
+        }
+        assert_eq!(started, 2);
+
+        // Room frees up once a generator is done.
+        manager.cancel_all_generators();
+        manager.generate(&participants, 60_000).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_if_needed() {
+        let mut manager = setup();
+        let participants = participants(3);
+        let current = participants.keys_vec();
+        manager.insert(mock_triple(1, current.clone())).await;
+        manager.insert_mine(mock_triple(2, current)).await;
+
+        // The pool is short by 8, but this node only by 3.
+        assert_eq!(
+            manager
+                .warm_up_if_needed(&participants, 10, 4, 60_000)
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(manager.generators.len(), 3);
+
+        // Targets that are already met start nothing.
+        assert_eq!(
+            manager
+                .warm_up_if_needed(&participants, 10, 4, 60_000)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            manager
+                .warm_up_if_needed(&participants, 5, 10, 60_000)
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_has_sufficient_for_presignature() {
+        let mut manager = setup();
+        let p = Participant::from;
+        manager
+            .insert_mine(mock_triple(1, vec![p(0), p(1), p(2)]))
+            .await;
+        manager.insert_mine(mock_triple(2, vec![p(2), p(0)])).await;
+        manager.insert(mock_triple(3, vec![p(0), p(2)])).await;
+
+        assert!(manager.has_sufficient_for_presignature(&[p(0), p(2)]).await);
+        assert!(manager.has_sufficient_for_presignature(&[p(2)]).await);
+        // Only one mine triple includes participant 1, and the other triple is not mine.
+        assert!(!manager.has_sufficient_for_presignature(&[p(0), p(1)]).await);
+        assert!(!manager.has_sufficient_for_presignature(&[p(3)]).await);
+    }
+
+    #[tokio::test]
+    async fn test_generate_below_threshold() {
+        let mut manager = setup();
+        assert_eq!(manager.threshold(), 2);
+
+        assert!(manager.generate(&participants(1), 1000).await.is_err());
+        assert!(manager.generators.is_empty());
+        manager.generate(&participants(2), 1000).await.unwrap();
+        assert_eq!(manager.generators.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_generate_id_keeps_epoch() {
+        let mut manager = setup();
+
+        manager.epoch = u32::MAX as u64;
+        let id = manager.generate(&participants(3), 1000).await.unwrap();
+        assert_eq!(Triple::epoch_from_id(id), u32::MAX as u64);
+
+        // Epochs that do not fit into the upper half of an id are refused, not truncated.
+        manager.epoch = u32::MAX as u64 + 1;
+        assert!(manager.generate(&participants(3), 1000).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_factor() {
+        let mut manager = setup();
+        let mut cfg = ProtocolConfig::default();
+        cfg.triple.max_triples = 4;
+        assert_eq!(manager.read_only().pool_load(), None);
+        assert_eq!(manager.record_load_factor(&cfg, 0.8).await, 1.0);
+        assert_eq!(
+            manager.read_only().pool_load(),
+            Some((1.0, PoolHealth::Degraded))
+        );
+
+        let participants = participants(3).keys_vec();
+        for id in 1..=3 {
+            manager.insert(mock_triple(id, participants.clone())).await;
+        }
+        assert_eq!(manager.record_load_factor(&cfg, 0.8).await, 0.25);
+        assert_eq!(
+            manager.read_only().pool_load(),
+            Some((0.25, PoolHealth::Healthy))
+        );
+    }
+
+    #[test]
+    fn test_public_key_fingerprint() {
+        let participants = participants(3).keys_vec();
+        let triple = mock_triple(1, participants.clone());
+        // The fingerprint only depends on the public points, not on the id or the share.
+        let mut other = mock_triple(2, participants);
+        other.share.a = Scalar::ZERO;
+        assert_eq!(
+            triple.public_key_fingerprint(),
+            other.public_key_fingerprint()
+        );
+
+        other.public.big_c = AffinePoint::IDENTITY;
+        assert_ne!(
+            triple.public_key_fingerprint(),
+            other.public_key_fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_is_zero() {
+        let participants = participants(3).keys_vec();
+        let mut triple = mock_triple(1, participants);
+        assert!(!triple.is_zero());
+
+        triple.share.a = Scalar::ZERO;
+        triple.share.b = Scalar::ZERO;
+        assert!(!triple.is_zero());
+        triple.share.c = Scalar::ZERO;
+        assert!(triple.is_zero());
+    }
+
+    #[test]
+    fn test_participant_set_hash() {
+        let a = participants(3).keys_vec();
+        let mut reversed = a.clone();
+        reversed.reverse();
+        assert_eq!(participant_set_hash(&a), participant_set_hash(&reversed));
+
+        let b = participants(2).keys_vec();
+        assert_ne!(participant_set_hash(&a), participant_set_hash(&b));
+    }
+
+    #[test]
+    fn test_triple_share_scalar_encoding() {
+        // Triple shares rely on scalars being encoded from their constant-time byte form.
+        let scalar = Scalar::from(0x1234_5678_9abc_def0u64);
+        assert_eq!(
+            serde_json::to_value(scalar).unwrap(),
+            serde_json::Value::String(hex::encode_upper(scalar.to_bytes()))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_triple_share_bytes_round_trip(seed in any::<u64>()) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let share = TripleShare::<Secp256k1> {
+                a: Scalar::random(&mut rng),
+                b: Scalar::random(&mut rng),
+                c: Scalar::random(&mut rng),
+            };
+            let decoded = TripleShare::<Secp256k1>::from_bytes(share.to_bytes()).unwrap();
+            prop_assert_eq!(decoded.a, share.a);
+            prop_assert_eq!(decoded.b, share.b);
+            prop_assert_eq!(decoded.c, share.c);
+        }
+    }
+
+    #[test]
+    fn test_triple_share_from_bytes_rejects_overflow() {
+        let mut bytes = mock_triple_output(participants(3).keys_vec()).0.to_bytes();
+        assert!(TripleShare::<Secp256k1>::from_bytes(bytes).is_some());
+        bytes[64..].fill(0xFF);
+        assert!(TripleShare::<Secp256k1>::from_bytes(bytes).is_none());
+    }
+
+    /// Stands in for a cait-sith triple protocol by returning preset poke results in order,
+    /// and waiting once they run out.
+    #[derive(Default)]
+    struct MockTripleProtocol {
+        actions: VecDeque<Result<Action<TripleGenerationOutput<Secp256k1>>, ProtocolError>>,
+    }
+
+    impl MockTripleProtocol {
+        fn expect_action(mut self, action: Action<TripleGenerationOutput<Secp256k1>>) -> Self {
+            self.actions.push_back(Ok(action));
+            self
+        }
+
+        fn expect_error(mut self, error: ProtocolError) -> Self {
+            self.actions.push_back(Err(error));
+            self
+        }
+    }
+
+    impl Protocol for MockTripleProtocol {
+        type Output = TripleGenerationOutput<Secp256k1>;
+
+        fn poke(&mut self) -> Result<Action<Self::Output>, ProtocolError> {
+            self.actions.pop_front().unwrap_or(Ok(Action::Wait))
         }
 
-        messages
+        fn message(&mut self, _from: Participant, _data: MessageData) {}
+    }
+
+    /// Adds a generator driven by `protocol` to the manager, queued like a generated one.
+    fn insert_mock_generator(
+        manager: &mut TripleManager,
+        id: TripleId,
+        participants: Vec<Participant>,
+        protocol: MockTripleProtocol,
+    ) {
+        manager.generators.insert(
+            id,
+            TripleGenerator::new(id, participants, Box::new(protocol), 60_000),
+        );
+        manager.queued.push_back(id);
+    }
+
+    fn mock_triple_output(participants: Vec<Participant>) -> TripleGenerationOutput<Secp256k1> {
+        let share = TripleShare {
+            a: Scalar::ONE,
+            b: Scalar::ONE,
+            c: Scalar::ONE,
+        };
+        let public = TriplePub {
+            big_a: AffinePoint::GENERATOR,
+            big_b: AffinePoint::GENERATOR,
+            big_c: AffinePoint::GENERATOR,
+            participants,
+            threshold: 2,
+        };
+        (share, public)
+    }
+
+    fn mock_triple(id: TripleId, participants: Vec<Participant>) -> Triple {
+        let (share, public) = mock_triple_output(participants);
+        Triple { id, share, public }
+    }
+
+    #[test]
+    fn test_public_participants_valid() {
+        let triple = mock_triple(1, participants(3).keys_vec());
+        assert!(triple.public_participants_valid(&participants(3).keys_vec()));
+        assert!(triple.public_participants_valid(&participants(4).keys_vec()));
+
+        // One of the participants of the triple left, e.g. during a reshare.
+        assert!(!triple.public_participants_valid(&participants(2).keys_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_take_two_skips_stale_triples() {
+        let mut manager = setup();
+        let current = participants(2).keys_vec();
+        manager.insert_mine(mock_triple(1, current.clone())).await;
+        manager
+            .insert_mine(mock_triple(2, participants(3).keys_vec()))
+            .await;
+        manager.insert_mine(mock_triple(3, current.clone())).await;
+
+        let (triple_0, triple_1) = manager.take_two_mine(&current).await.unwrap();
+        let mut taken = [triple_0.id, triple_1.id];
+        taken.sort_unstable();
+        assert_eq!(taken, [1, 3]);
+        // The stale triple can never be used again, so it is removed on the way.
+        assert!(!manager.contains_mine(&2).await);
+        manager.insert_mine(mock_triple(6, current.clone())).await;
+        manager
+            .insert_mine(mock_triple(7, participants(3).keys_vec()))
+            .await;
+        assert!(matches!(
+            manager.take_two_mine(&current).await,
+            Err(TakeError::InsufficientMine { have: 1, need: 2 })
+        ));
+        assert!(manager.contains_mine(&6).await);
+        assert!(!manager.contains_mine(&7).await);
+
+        manager.insert(mock_triple(4, current.clone())).await;
+        manager
+            .insert(mock_triple(5, participants(3).keys_vec()))
+            .await;
+        assert!(matches!(
+            manager.take_two(4, 5, &current).await,
+            Err(TakeError::Stale { id: 5 })
+        ));
+        assert!(manager.contains(&4).await);
+        assert!(!manager.contains(&5).await);
+    }
+
+    #[tokio::test]
+    async fn test_poke_send_many() {
+        let mut manager = setup();
+        let participants = participants(3).keys_vec();
+        let protocol = MockTripleProtocol::default().expect_action(Action::SendMany(vec![1, 2]));
+        insert_mock_generator(&mut manager, 1, participants.clone(), protocol);
+
+        let messages = manager.poke(&ProtocolConfig::default()).await;
+        let recipients: Vec<_> = messages.iter().map(|(p, _)| *p).collect();
+        assert_eq!(recipients, participants);
+        assert!(messages
+            .iter()
+            .all(|(_, msg)| msg.id == 1 && msg.data == vec![1, 2] && msg.from == manager.me));
+        assert_eq!(manager.generators[&1].last_sent_to, participants);
+        assert!(manager.ongoing.contains(&1));
+    }
+
+    #[tokio::test]
+    async fn test_poke_with_timeout() {
+        let mut manager = setup();
+        let participants = participants(3).keys_vec();
+        let protocol = MockTripleProtocol::default().expect_action(Action::SendMany(vec![1, 2]));
+        insert_mock_generator(&mut manager, 1, participants.clone(), protocol);
+        let cfg = ProtocolConfig::default();
+
+        // Nothing is poked once the deadline passed, and the generator is kept for next time.
+        assert!(matches!(
+            manager.poke_with_timeout(&cfg, Duration::ZERO).await,
+            Err(PokeError::Timeout { messages, .. }) if messages.is_empty()
+        ));
+        assert!(manager.ongoing.contains(&1));
+
+        let messages = manager
+            .poke_with_timeout(&cfg, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), participants.len());
+    }
+
+    #[tokio::test]
+    async fn test_poke_send_private() {
+        let mut manager = setup();
+        let to = Participant::from(2);
+        let protocol = MockTripleProtocol::default()
+            .expect_action(Action::SendPrivate(to, vec![3]))
+            .expect_action(Action::SendPrivate(to, vec![4]));
+        insert_mock_generator(&mut manager, 1, participants(3).keys_vec(), protocol);
+
+        let messages = manager.poke(&ProtocolConfig::default()).await;
+        let data: Vec<_> = messages
+            .iter()
+            .map(|(p, msg)| (*p, msg.data.clone()))
+            .collect();
+        assert_eq!(data, vec![(to, vec![3]), (to, vec![4])]);
+        assert_eq!(manager.generators[&1].last_sent_to, vec![to]);
+    }
+
+    #[tokio::test]
+    async fn test_poke_return() {
+        let mut manager = setup();
+        let participants = participants(3).keys_vec();
+        let protocol = MockTripleProtocol::default()
+            .expect_action(Action::Return(mock_triple_output(participants.clone())));
+        insert_mock_generator(&mut manager, 1, participants, protocol);
+
+        let messages = manager.poke(&ProtocolConfig::default()).await;
+        assert!(messages.is_empty());
+        assert!(manager.generators.is_empty());
+        assert!(manager.ongoing.is_empty());
+        assert!(!manager.gc.contains_key(&1));
+        assert_eq!(manager.completion_times.len(), 1);
+        assert_eq!(manager.triple_storage.len_generated().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_watch_completions() {
+        let mut manager = setup();
+        let participants = participants(3).keys_vec();
+        let mut completions = manager.watch_completions();
+        let mut mine_completions = manager.watch_mine_completions();
+        let protocol = MockTripleProtocol::default()
+            .expect_action(Action::Return(mock_triple_output(participants.clone())));
+        insert_mock_generator(&mut manager, 1, participants, protocol);
+
+        manager.poke(&ProtocolConfig::default()).await;
+        tokio::select! {
+            changed = completions.changed() => changed.unwrap(),
+            _ = tokio::time::sleep(Duration::from_secs(1)) => panic!("completion was not announced"),
+        }
+        assert_eq!(*completions.borrow_and_update(), Some(1));
+        assert!(manager.contains(&1).await);
+
+        let mine = manager.contains_mine(&1).await;
+        assert_eq!(mine_completions.has_changed().unwrap(), mine);
+    }
+
+    #[tokio::test]
+    async fn test_poke_detailed() {
+        let mut manager = setup();
+        let participants = participants(3).keys_vec();
+        let done = MockTripleProtocol::default()
+            .expect_action(Action::Return(mock_triple_output(participants.clone())));
+        let sending = MockTripleProtocol::default().expect_action(Action::SendMany(vec![1]));
+        insert_mock_generator(&mut manager, 1, participants.clone(), done);
+        insert_mock_generator(&mut manager, 2, participants.clone(), sending);
+
+        let (messages, completed) = manager.poke_detailed(&ProtocolConfig::default()).await;
+        assert_eq!(completed, vec![1]);
+        assert!(manager.contains(&1).await);
+        assert_eq!(messages.len(), participants.len());
+        assert!(messages.iter().all(|(_, message)| message.id == 2));
+
+        let (messages, completed) = manager.poke_detailed(&ProtocolConfig::default()).await;
+        assert!(messages.is_empty());
+        assert!(completed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_poke_error() {
+        let mut manager = setup();
+        let protocol = MockTripleProtocol::default()
+            .expect_error(ProtocolError::Other(anyhow::anyhow!("mock failure").into()));
+        insert_mock_generator(&mut manager, 1, participants(3).keys_vec(), protocol);
+
+        let messages = manager.poke(&ProtocolConfig::default()).await;
+        assert!(messages.is_empty());
+        assert!(manager.generators.is_empty());
+        assert!(manager.ongoing.is_empty());
+        assert!(manager.gc.contains_key(&1));
+        // A failed generation never produced a triple, so it was not spent either.
+        assert!(matches!(
+            manager.take_two(1, 2, &participants(3).keys_vec()).await,
+            Err(TakeError::NotFound { id: 1 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_spent_id_is_not_generated_again() {
+        let mut manager = setup();
+        let participants = participants(3);
+        let current = participants.keys_vec();
+        let cfg = ProtocolConfig::default();
+        manager.insert(mock_triple(1, current.clone())).await;
+        manager.insert(mock_triple(2, current.clone())).await;
+        manager.take_two(1, 2, &current).await.unwrap();
+
+        // Late messages for a freshly spent triple are ignored.
+        assert!(manager
+            .get_or_start_generation(1, &participants, &cfg)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Once the gc entry expired, the id is still refused instead of restarting a protocol.
+        manager.gc.clear();
+        assert!(matches!(
+            manager
+                .get_or_start_generation(1, &participants, &cfg)
+                .await,
+            Err(CryptographicError::IdConflict(1))
+        ));
+        assert!(manager.generators.is_empty());
+        assert!(matches!(
+            manager.take_two(1, 2, &current).await,
+            Err(TakeError::AlreadySpent { id: 1 })
+        ));
+
+        // Tombstones of older epochs are pruned, their ids are refused by epoch instead.
+        manager.epoch = 1;
+        manager.garbage_collect(&cfg);
+        assert!(!manager.is_tombstoned(&1));
+        assert!(matches!(
+            manager
+                .get_or_start_generation(1, &participants, &cfg)
+                .await,
+            Err(CryptographicError::IdConflict(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_observer_rejects_messages() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let storage = TripleStorage::in_memory(&account_id);
+        let observer = Participant::from(5);
+        let mut observer_manager = manager(observer, &account_id, &storage);
+        let participants = participants(3);
+        let cfg = ProtocolConfig::default();
+
+        // An observer does not join the generation of a triple it is not part of.
+        assert!(observer_manager
+            .get_or_start_generation(1, &participants, &cfg)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(observer_manager.generators.is_empty());
+        assert!(!observer_manager.participating_in(1).await);
+        assert!(!observer_manager.is_observer(1).await);
+
+        // Once the triple is known, the node is recognized as an observer of it.
+        observer_manager
+            .insert(mock_triple(1, participants.keys_vec()))
+            .await;
+        assert!(observer_manager.participating_in(1).await);
+        assert!(observer_manager.is_observer(1).await);
+
+        let mut participant = manager(Participant::from(0), &account_id, &storage);
+        insert_mock_generator(
+            &mut participant,
+            2,
+            participants.keys_vec(),
+            MockTripleProtocol::default(),
+        );
+        assert!(participant.participating_in(2).await);
+        assert!(!participant.is_observer(2).await);
+    }
+
+    #[tokio::test]
+    async fn test_get_mine_ids() {
+        let mut manager = setup();
+        let current = participants(3).keys_vec();
+        assert!(manager.get_mine_ids().await.is_empty());
+
+        for id in [3, 1, 2] {
+            manager.insert_mine(mock_triple(id, current.clone())).await;
+        }
+        manager.insert(mock_triple(4, current.clone())).await;
+        assert_eq!(manager.get_mine_ids().await, vec![1, 2, 3]);
+        assert_eq!(manager.get_all_mine().await.unwrap(), vec![1, 2, 3]);
+
+        let (triple0, triple1) = manager.take_two_mine(&current).await.unwrap();
+        let remaining = manager.get_mine_ids().await;
+        assert_eq!(remaining.len(), 1);
+        assert!(!remaining.contains(&triple0.id));
+        assert!(!remaining.contains(&triple1.id));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let mut manager = setup();
+        let participants = participants(3).keys_vec();
+        let protocol = MockTripleProtocol::default()
+            .expect_action(Action::Return(mock_triple_output(participants.clone())));
+        insert_mock_generator(&mut manager, 1, participants.clone(), protocol);
+        let protocol = MockTripleProtocol::default()
+            .expect_error(ProtocolError::Other(anyhow::anyhow!("mock failure").into()));
+        insert_mock_generator(&mut manager, 2, participants, protocol);
+
+        manager.poke(&ProtocolConfig::default()).await;
+        manager.log_used(&[1], 7).await;
+
+        let events = manager.audit_log().await;
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[0],
+            AuditEvent::Generated {
+                id: 1,
+                epoch: 0,
+                ..
+            }
+        ));
+        assert!(
+            matches!(&events[1], AuditEvent::Expired { id: 2, reason } if reason.contains("mock failure"))
+        );
+        assert_eq!(events[2], AuditEvent::Consumed { id: 1, used_for: 7 });
+        assert_eq!(
+            serde_json::from_str::<AuditEvent>(&events[2].to_json()).unwrap(),
+            events[2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_async() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let participants = participants(3);
+        let cfg = ProtocolConfig::default();
+        // Every node has its own storage, as it would in a real deployment.
+        let mut managers: Vec<_> = participants
+            .keys()
+            .map(|p| manager(*p, &account_id, &TripleStorage::in_memory(&account_id)))
+            .collect();
+
+        let mut receivers = Vec::new();
+        for _ in 0..5 {
+            receivers.push(
+                managers[0]
+                    .generate_async(&participants, 60_000)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let [r0, r1, r2, r3, r4]: [_; 5] = receivers.try_into().unwrap();
+
+        // Stands in for the mesh: pokes every manager and delivers the messages.
+        let drive = async {
+            loop {
+                for i in 0..managers.len() {
+                    let from = managers[i].me;
+                    for (to, msg) in managers[i].poke(&cfg).await {
+                        if to == from {
+                            continue;
+                        }
+                        let to = &mut managers[u32::from(to) as usize];
+                        if let Some(protocol) = to
+                            .get_or_start_generation(msg.id, &participants, &cfg)
+                            .await
+                            .unwrap()
+                        {
+                            protocol.message(msg.from, msg.data);
+                        }
+                    }
+                }
+                tokio::task::yield_now().await;
+            }
+        };
+        let triples = tokio::time::timeout(Duration::from_secs(120), async {
+            tokio::select! {
+                triples = async { tokio::join!(r0, r1, r2, r3, r4) } => triples,
+                _ = drive => unreachable!(),
+            }
+        })
+        .await
+        .expect("triples were not generated in time");
+
+        let ids: HashSet<_> = [triples.0, triples.1, triples.2, triples.3, triples.4]
+            .into_iter()
+            .map(|triple| triple.unwrap().id)
+            .collect();
+        assert_eq!(ids.len(), 5);
+    }
+
+    /// Manager with a fixed rng seed, so generated triple ids are the same on every run.
+    fn manager(me: Participant, account_id: &AccountId, storage: &TripleStorage) -> TripleManager {
+        TripleManager::with_rng(me, 2, 0, account_id, storage, StdRng::seed_from_u64(42))
+    }
+
+    /// A manager for participant 0 backed by its own in-memory storage.
+    fn setup() -> TripleManager {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        manager(
+            Participant::from(0),
+            &account_id,
+            &TripleStorage::in_memory(&account_id),
+        )
+    }
+
+    fn participants(n: u32) -> Participants {
+        let mut participants = Participants::default();
+        for id in 0..n {
+            participants.insert(&Participant::from(id), ParticipantInfo::new(id));
+        }
+        participants
+    }
+
+    #[tokio::test]
+    async fn test_generate_until() {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let participants = participants(3);
+        let cfg = ProtocolConfig::default();
+        let managers: Vec<_> = participants
+            .keys()
+            .map(|p| {
+                RwLock::new(manager(
+                    *p,
+                    &account_id,
+                    &TripleStorage::in_memory(&account_id),
+                ))
+            })
+            .collect();
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let forward = sender.clone();
+        let generate =
+            TripleManager::generate_until(&managers[0], &participants, 2, &cfg, move |to, msg| {
+                let _ = forward.send((to, msg));
+            });
+        // Stands in for the mesh and for the other nodes, which only join the generations.
+        let deliver = async {
+            loop {
+                for manager in &managers[1..] {
+                    for message in manager.write().await.poke(&cfg).await {
+                        let _ = sender.send(message);
+                    }
+                }
+                while let Ok((to, msg)) = receiver.try_recv() {
+                    if to == msg.from {
+                        continue;
+                    }
+                    let mut to = managers[u32::from(to) as usize].write().await;
+                    if let Some(protocol) = to
+                        .get_or_start_generation(msg.id, &participants, &cfg)
+                        .await
+                        .unwrap()
+                    {
+                        protocol.message(msg.from, msg.data);
+                    }
+                }
+                tokio::task::yield_now().await;
+            }
+        };
+        let ids = tokio::time::timeout(Duration::from_secs(120), async {
+            tokio::select! {
+                ids = generate => ids,
+                _ = deliver => unreachable!(),
+            }
+        })
+        .await
+        .expect("triples were not generated in time");
+
+        assert!(ids.len() >= 2);
+        assert_eq!(ids, managers[0].read().await.get_mine_ids().await);
+    }
+
+    #[test]
+    fn test_expected_completion_time() {
+        let mut manager = setup();
+
+        manager.record_completion(Duration::from_secs(1));
+        manager.record_completion(Duration::from_secs(2));
+        assert_eq!(manager.expected_completion_time(), None);
+
+        manager.record_completion(Duration::from_secs(6));
+        assert_eq!(
+            manager.expected_completion_time(),
+            Some(Duration::from_secs(3))
+        );
+
+        // Only the most recent completions are taken into account.
+        for _ in 0..THROUGHPUT_HISTORY_LEN {
+            manager.record_completion(Duration::from_millis(500));
+        }
+        assert_eq!(
+            manager.expected_completion_time(),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_migration() -> anyhow::Result<()> {
+        let account_id: AccountId = "test.near".parse().unwrap();
+        let triple_storage = TripleStorage::in_memory(&account_id);
+        triple_storage
+            .insert_mine(mock_triple(
+                Triple::id_from_epoch_and_index(123, 1),
+                participants(2).keys_vec(),
+            ))
+            .await?;
+        let spent = Triple::id_from_epoch_and_index(123, 7);
+        let outdated = Triple::id_from_epoch_and_index(122, 7);
+
+        // A snapshot written by a node running the version 1 schema, before the generator cap
+        // was added.
+        let v1 = serde_json::json!({
+            "version": 1,
+            "state": {
+                "me": 0,
+                "threshold": 2,
+                "epoch": 123,
+                "my_account_id": "test.near",
+                "id_tombstone": [spent, outdated],
+                "gc": [8],
+            },
+        })
+        .to_string();
+        let snapshot: TripleManagerSnapshot = serde_json::from_str(&v1)?;
+        let triple_manager =
+            TripleManager::from_snapshot_with_storage(snapshot, triple_storage.clone())?;
+        assert_eq!(triple_manager.me, Participant::from(0));
+        assert_eq!(triple_manager.threshold, 2);
+        assert_eq!(triple_manager.epoch, 123);
+        assert!(triple_manager.is_tombstoned(&spent));
+        // Tombstones of older epochs are dropped on restore.
+        assert!(!triple_manager.is_tombstoned(&outdated));
+        assert!(triple_manager.gc.contains_key(&8));
+        assert!(
+            triple_manager
+                .contains_mine(&Triple::id_from_epoch_and_index(123, 1))
+                .await
+        );
+
+        // The upgraded manager writes the current version, which restores without migrating.
+        let snapshot = triple_manager.snapshot();
+        assert_eq!(snapshot.version, TRIPLE_MANAGER_SNAPSHOT_VERSION);
+        assert_eq!(snapshot.state["max_generators"], serde_json::Value::Null);
+        let serialized = serde_json::to_string(&snapshot)?;
+        let restored = TripleManager::from_snapshot_with_storage(
+            serde_json::from_str(&serialized)?,
+            triple_storage,
+        )?;
+        assert_eq!(snapshot.state["id_tombstone"], serde_json::json!([spent]));
+        assert!(restored.is_tombstoned(&spent));
+        assert_eq!(restored.len_mine().await, 1);
+
+        let future = TripleManagerSnapshot {
+            version: TRIPLE_MANAGER_SNAPSHOT_VERSION + 1,
+            ..restored.snapshot()
+        };
+        assert!(TripleManager::from_snapshot_with_storage(
+            future,
+            TripleStorage::in_memory(&account_id)
+        )
+        .is_err());
+
+        Ok(())
     }
 }
@@ -3,6 +3,54 @@ pub mod presignature_storage;
 pub mod secret_storage;
 pub mod triple_storage;
 
+#[cfg(any(test, feature = "test-storage"))]
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Process-local state shared between the clones of an in-memory storage. Only built for
+/// tests, enabled for other crates by the `test-storage` feature.
+#[cfg(any(test, feature = "test-storage"))]
+pub(crate) struct MemoryBackend<T>(Arc<Mutex<T>>);
+
+#[cfg(any(test, feature = "test-storage"))]
+impl<T> MemoryBackend<T> {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(any(test, feature = "test-storage"))]
+impl<T: Default> Default for MemoryBackend<T> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+#[cfg(any(test, feature = "test-storage"))]
+impl<T> Clone for MemoryBackend<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// How long redis writes wait before being considered successful.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Durability {
+    /// Writes are acknowledged as soon as the primary accepts them.
+    #[default]
+    Eventual,
+    /// Writes wait for at least one replica to acknowledge them (`WAIT 1 100`).
+    Synchronous,
+}
+
+impl std::fmt::Display for Durability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Durability::Eventual => write!(f, "eventual"),
+            Durability::Synchronous => write!(f, "synchronous"),
+        }
+    }
+}
+
 /// Configures storage.
 #[derive(Debug, Clone, clap::Parser)]
 #[group(id = "storage_options")]
@@ -19,8 +67,17 @@ pub struct Options {
     /// Mostly for integration tests.
     #[arg(long, env("MPC_SK_SHARE_LOCAL_PATH"))]
     pub sk_share_local_path: Option<String>,
+    /// URL of a standalone redis instance. Redis Cluster is not supported: the storage talks
+    /// to a single server, and the keys of a node spread over several hash slots. For high
+    /// availability, run a primary with replicas and use `--durability synchronous` instead.
     #[arg(long, env("MPC_REDIS_URL"))]
     pub redis_url: String,
+    /// Durability mode for triple writes to redis.
+    #[arg(long, env("MPC_REDIS_DURABILITY"), value_enum, default_value_t = Durability::Eventual)]
+    pub durability: Durability,
+    /// Optional namespace prepended to all triple and presignature keys in redis.
+    #[arg(long, env("MPC_REDIS_KEY_PREFIX"))]
+    pub redis_key_prefix: Option<String>,
 }
 
 impl Options {
@@ -30,10 +87,15 @@ impl Options {
             self.env,
             "--gcp-project-id".to_string(),
             self.gcp_project_id,
+            "--durability".to_string(),
+            self.durability.to_string(),
         ];
         if let Some(sk_share_secret_id) = self.sk_share_secret_id {
             opts.extend(vec!["--sk-share-secret-id".to_string(), sk_share_secret_id]);
         }
+        if let Some(redis_key_prefix) = self.redis_key_prefix {
+            opts.extend(vec!["--redis-key-prefix".to_string(), redis_key_prefix]);
+        }
         if let Some(sk_share_local_path) = self.sk_share_local_path {
             opts.extend(vec![
                 "--sk-share-local-path".to_string(),
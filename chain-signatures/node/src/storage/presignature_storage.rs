@@ -1,31 +1,281 @@
+// Without the in-memory test backend, `Backend` only has the redis variant.
+#![cfg_attr(
+    not(any(test, feature = "test-storage")),
+    allow(clippy::infallible_destructuring_match)
+)]
+
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Ok;
+use cait_sith::protocol::Participant;
 use deadpool_redis::Pool;
 use near_sdk::AccountId;
-use redis::{AsyncCommands, FromRedisValue, RedisWrite, ToRedisArgs};
+use once_cell::sync::Lazy;
+use redis::{AsyncCommands, FromRedisValue, RedisError, RedisWrite, ToRedisArgs};
 
 use crate::protocol::presignature::{Presignature, PresignatureId};
+#[cfg(any(test, feature = "test-storage"))]
+use crate::storage::MemoryBackend;
 
 type PresigResult<T> = std::result::Result<T, anyhow::Error>;
 
 // Can be used to "clear" redis storage in case of a breaking change
 const PRESIGNATURE_STORAGE_VERSION: &str = "v2";
 
+/// Takes the foreign presignature `ARGV[1]`. `KEYS` are the same as for [`TAKE_MINE_SCRIPT`].
+/// Returns the presignature data, nil if it is not stored, or 0 if it is mine or reserved.
+static TAKE_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+if redis.call('SISMEMBER', KEYS[1], ARGV[1]) == 1 or redis.call('HEXISTS', KEYS[5], ARGV[1]) == 1 then
+    return 0
+end
+local data = redis.call('HGET', KEYS[3], ARGV[1])
+redis.call('HDEL', KEYS[3], ARGV[1])
+redis.call('HDEL', KEYS[4], ARGV[1])
+return data
+",
+    )
+});
+
+/// Takes a random mine presignature. `KEYS` are the mine set, the mine age index, the
+/// presignatures, their epochs and the reserved mine presignatures. Returns the presignature
+/// data, or nil if there is none.
+static TAKE_MINE_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+local id = redis.call('SPOP', KEYS[1])
+if not id then
+    return false
+end
+redis.call('ZREM', KEYS[2], id)
+local data = redis.call('HGET', KEYS[3], id)
+redis.call('HDEL', KEYS[3], id)
+redis.call('HDEL', KEYS[4], id)
+return data
+",
+    )
+});
+
+/// Takes the oldest mine presignature, or the newest one if `ARGV[1]` is `newest`, by popping
+/// the mine age index. `KEYS` are the same as for [`TAKE_MINE_SCRIPT`]. Returns the
+/// presignature data, or nil if the index is empty.
+static TAKE_MINE_BY_AGE_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+local pop = ARGV[1] == 'newest' and 'ZPOPMAX' or 'ZPOPMIN'
+local popped = redis.call(pop, KEYS[2])
+while popped[1] do
+    local id = popped[1]
+    if redis.call('SREM', KEYS[1], id) == 1 then
+        local data = redis.call('HGET', KEYS[3], id)
+        redis.call('HDEL', KEYS[3], id)
+        redis.call('HDEL', KEYS[4], id)
+        if data then
+            return data
+        end
+    end
+    popped = redis.call(pop, KEYS[2])
+end
+return false
+",
+    )
+});
+
+/// Takes a mine presignature that participant `ARGV[1]` took part in generating. `KEYS` are the
+/// same as for [`TAKE_MINE_SCRIPT`]. The data only leaves redis for the presignature that is
+/// taken. Returns the presignature data, or nil if there is no such presignature.
+static TAKE_MINE_WITH_PARTICIPANT_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+local participant = tonumber(ARGV[1])
+for _, id in ipairs(redis.call('SMEMBERS', KEYS[1])) do
+    local data = redis.call('HGET', KEYS[3], id)
+    if data then
+        for _, p in ipairs(cjson.decode(data).participants) do
+            if p == participant then
+                redis.call('SREM', KEYS[1], id)
+                redis.call('ZREM', KEYS[2], id)
+                redis.call('HDEL', KEYS[3], id)
+                redis.call('HDEL', KEYS[4], id)
+                return data
+            end
+        end
+    end
+end
+return false
+",
+    )
+});
+
+/// Moves the mine presignature `ARGV[1]` from the mine set to the reserved ones, keeping its
+/// age to restore it on rollback. `KEYS` are the same as for [`TAKE_MINE_SCRIPT`]. Returns the
+/// presignature data, nil if it is not mine, or 0 if it was mine but has no data, in which
+/// case the dangling mine entry is dropped.
+static RESERVE_MINE_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+if redis.call('SREM', KEYS[1], ARGV[1]) == 0 then
+    return false
+end
+local created_at = redis.call('ZSCORE', KEYS[2], ARGV[1])
+redis.call('ZREM', KEYS[2], ARGV[1])
+local data = redis.call('HGET', KEYS[3], ARGV[1])
+if not data then
+    return 0
+end
+redis.call('HSET', KEYS[5], ARGV[1], created_at or '')
+return data
+",
+    )
+});
+
+/// Moves the reserved presignature `ARGV[1]` back to the mine set. `KEYS` are the same as for
+/// [`TAKE_MINE_SCRIPT`].
+static ROLLBACK_RESERVED_SCRIPT: Lazy<redis::Script> = Lazy::new(|| {
+    redis::Script::new(
+        r"
+local created_at = redis.call('HGET', KEYS[5], ARGV[1])
+if not created_at then
+    return 0
+end
+redis.call('HDEL', KEYS[5], ARGV[1])
+redis.call('SADD', KEYS[1], ARGV[1])
+if created_at ~= '' then
+    redis.call('ZADD', KEYS[2], created_at, ARGV[1])
+end
+return 1
+",
+    )
+});
+
 pub fn init(pool: &Pool, node_account_id: &AccountId) -> PresignatureStorage {
     PresignatureStorage {
-        redis_pool: pool.clone(),
+        backend: Backend::Redis(pool.clone()),
         node_account_id: node_account_id.clone(),
+        key_prefix: None,
+    }
+}
+
+/// Returned by [`PresignatureStorage::batch_insert`] when some presignatures could not be stored.
+/// The failed presignatures are handed back so that the caller can retry them.
+#[derive(thiserror::Error)]
+#[error("failed to store {} presignatures ({} succeeded)", failed.len(), succeeded)]
+pub struct BatchInsertError {
+    pub succeeded: usize,
+    pub failed: Vec<(Presignature, RedisError)>,
+}
+
+impl std::fmt::Debug for BatchInsertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchInsertError")
+            .field("succeeded", &self.succeeded)
+            .field(
+                "failed",
+                &self
+                    .failed
+                    .iter()
+                    .map(|(presignature, err)| (presignature.id, err))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Returned by [`PresignatureStorage::delete_batch`] when the batch could not be deleted. The
+/// batch runs as a single transaction, so none of the presignatures were deleted.
+#[derive(Debug, thiserror::Error)]
+#[error("failed to delete a batch of {} presignatures: {source}", ids.len())]
+pub struct BatchDeleteError {
+    pub ids: Vec<PresignatureId>,
+    pub source: RedisError,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Redis(Pool),
+    #[cfg(any(test, feature = "test-storage"))]
+    Memory(MemoryBackend<MemoryPresignatures>),
+}
+
+/// Contents of an in-memory storage, mirroring the redis keys. Presignatures are kept
+/// serialized, the same way they are stored in redis.
+#[cfg(any(test, feature = "test-storage"))]
+#[derive(Default)]
+struct MemoryPresignatures {
+    presignatures: HashMap<PresignatureId, String>,
+    mine: HashSet<PresignatureId>,
+    epochs: HashMap<PresignatureId, u64>,
+    reserved: HashSet<PresignatureId>,
+}
+
+#[cfg(any(test, feature = "test-storage"))]
+impl MemoryPresignatures {
+    fn insert(&mut self, presignature: &Presignature) -> PresigResult<()> {
+        let json = serde_json::to_string(presignature)?;
+        self.epochs
+            .insert(presignature.id, presignature.origin_epoch);
+        self.presignatures.insert(presignature.id, json);
+        Ok(())
+    }
+
+    fn get(&self, id: &PresignatureId) -> PresigResult<Option<Presignature>> {
+        match self.presignatures.get(id) {
+            Some(json) => Ok(Some(serde_json::from_str(json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn remove(&mut self, id: &PresignatureId) -> PresigResult<Option<Presignature>> {
+        let presignature = self.get(id)?;
+        self.presignatures.remove(id);
+        self.epochs.remove(id);
+        Ok(presignature)
     }
 }
 
 #[derive(Clone)]
 pub struct PresignatureStorage {
-    redis_pool: Pool,
+    backend: Backend,
     node_account_id: AccountId,
+    key_prefix: Option<String>,
 }
 
 impl PresignatureStorage {
+    /// Creates a storage that keeps presignatures in memory instead of redis. Nothing is
+    /// persisted, so this is only meant for tests and only built for them. Clones share the
+    /// same underlying storage.
+    #[cfg(any(test, feature = "test-storage"))]
+    pub fn in_memory(node_account_id: &AccountId) -> Self {
+        Self {
+            backend: Backend::Memory(MemoryBackend::default()),
+            node_account_id: node_account_id.clone(),
+            key_prefix: None,
+        }
+    }
+
+    /// Prefixes all keys with the given namespace, e.g. to isolate test runs sharing one redis.
+    pub fn with_key_prefix(mut self, key_prefix: Option<String>) -> Self {
+        self.key_prefix = key_prefix;
+        self
+    }
+
     pub async fn insert(&self, presignature: Presignature) -> PresigResult<()> {
-        let mut connection = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return memory.lock().insert(&presignature);
+            }
+        };
+        let mut connection = pool.get().await?;
+        connection
+            .hset::<&str, PresignatureId, u64, ()>(
+                &self.epoch_key(),
+                presignature.id,
+                presignature.origin_epoch,
+            )
+            .await?;
         connection
             .hset::<&str, PresignatureId, Presignature, ()>(
                 &self.presig_key(),
@@ -36,85 +286,827 @@ impl PresignatureStorage {
         Ok(())
     }
 
+    /// Stores all of the given presignatures in a single round-trip. Returns the number of
+    /// stored presignatures. Redis reports a failed pipeline as a whole, so on failure every
+    /// presignature of the batch is returned; inserting them again is safe.
+    pub async fn batch_insert(
+        &self,
+        presignatures: Vec<Presignature>,
+    ) -> std::result::Result<usize, BatchInsertError> {
+        if presignatures.is_empty() {
+            return std::result::Result::Ok(0);
+        }
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let mut failed = Vec::new();
+                let mut succeeded = 0;
+                for presignature in presignatures {
+                    match memory.insert(&presignature) {
+                        std::result::Result::Ok(()) => succeeded += 1,
+                        Err(err) => {
+                            let err = RedisError::from((
+                                redis::ErrorKind::TypeError,
+                                "failed to serialize presignature",
+                                err.to_string(),
+                            ));
+                            failed.push((presignature, err));
+                        }
+                    }
+                }
+                return if failed.is_empty() {
+                    std::result::Result::Ok(succeeded)
+                } else {
+                    Err(BatchInsertError { succeeded, failed })
+                };
+            }
+        };
+        let mut connection = match pool.get().await {
+            std::result::Result::Ok(connection) => connection,
+            Err(err) => {
+                let err = RedisError::from((
+                    redis::ErrorKind::IoError,
+                    "failed to get redis connection",
+                    err.to_string(),
+                ));
+                return Err(BatchInsertError::all_failed(presignatures, &err));
+            }
+        };
+
+        let mut pipe = redis::pipe();
+        for presignature in &presignatures {
+            pipe.hset(self.epoch_key(), presignature.id, presignature.origin_epoch)
+                .ignore()
+                .hset(self.presig_key(), presignature.id, presignature)
+                .ignore();
+        }
+        match pipe.query_async::<()>(&mut connection).await {
+            std::result::Result::Ok(()) => std::result::Result::Ok(presignatures.len()),
+            Err(err) => Err(BatchInsertError::all_failed(presignatures, &err)),
+        }
+    }
+
+    /// Deletes all of the given presignatures in a single MULTI/EXEC transaction. Returns the
+    /// number of deleted presignatures; ids that are not stored are skipped. The batch is all or
+    /// nothing: if it fails, none of the presignatures were deleted.
+    pub async fn delete_batch(
+        &self,
+        ids: &[PresignatureId],
+    ) -> std::result::Result<usize, BatchDeleteError> {
+        if ids.is_empty() {
+            return std::result::Result::Ok(0);
+        }
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let mut deleted = 0;
+                for id in ids {
+                    memory.mine.remove(id);
+                    memory.reserved.remove(id);
+                    memory.epochs.remove(id);
+                    if memory.presignatures.remove(id).is_some() {
+                        deleted += 1;
+                    }
+                }
+                return std::result::Result::Ok(deleted);
+            }
+        };
+        let failed = |source| BatchDeleteError {
+            ids: ids.to_vec(),
+            source,
+        };
+        let mut connection = match pool.get().await {
+            std::result::Result::Ok(connection) => connection,
+            Err(err) => {
+                return Err(failed(RedisError::from((
+                    redis::ErrorKind::IoError,
+                    "failed to get redis connection",
+                    err.to_string(),
+                ))));
+            }
+        };
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for id in ids {
+            pipe.srem(self.mine_key(), id)
+                .ignore()
+                .zrem(self.mine_age_key(), id)
+                .ignore()
+                .hdel(self.reserved_key(), id)
+                .ignore()
+                .hdel(self.epoch_key(), id)
+                .ignore()
+                .hdel(self.presig_key(), id);
+        }
+        match pipe.query_async::<Vec<usize>>(&mut connection).await {
+            std::result::Result::Ok(deleted) => std::result::Result::Ok(deleted.into_iter().sum()),
+            Err(err) => Err(failed(err)),
+        }
+    }
+
     pub async fn insert_mine(&self, presignature: Presignature) -> PresigResult<()> {
-        let mut connection = self.redis_pool.get().await?;
-        connection
-            .sadd::<&str, PresignatureId, ()>(&self.mine_key(), presignature.id)
-            .await?;
-        self.insert(presignature).await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                memory.mine.insert(presignature.id);
+                return memory.insert(&presignature);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hset(self.epoch_key(), presignature.id, presignature.origin_epoch)
+            .ignore()
+            .zadd(
+                self.mine_age_key(),
+                presignature.id,
+                presignature.created_at,
+            )
+            .ignore()
+            .sadd(self.mine_key(), presignature.id)
+            .ignore()
+            .hset(self.presig_key(), presignature.id, &presignature)
+            .ignore();
+        pipe.query_async::<()>(&mut connection).await?;
         Ok(())
     }
 
     pub async fn contains(&self, id: &PresignatureId) -> PresigResult<bool> {
-        let mut connection = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().presignatures.contains_key(id));
+            }
+        };
+        let mut connection = pool.get().await?;
         let result: bool = connection.hexists(self.presig_key(), id).await?;
         Ok(result)
     }
 
     pub async fn contains_mine(&self, id: &PresignatureId) -> PresigResult<bool> {
-        let mut connection = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().mine.contains(id));
+            }
+        };
+        let mut connection = pool.get().await?;
         let result: bool = connection.sismember(self.mine_key(), id).await?;
         Ok(result)
     }
 
+    /// Returns the epoch the stored presignature was generated in, if it is stored.
+    pub async fn origin_epoch(&self, id: &PresignatureId) -> PresigResult<Option<u64>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().epochs.get(id).copied());
+            }
+        };
+        let mut connection = pool.get().await?;
+        let result: Option<u64> = connection.hget(self.epoch_key(), id).await?;
+        Ok(result)
+    }
+
     pub async fn take(&self, id: &PresignatureId) -> PresigResult<Option<Presignature>> {
-        let mut connection = self.redis_pool.get().await?;
-        if self.contains_mine(id).await? {
-            tracing::error!("Can not take mine presignature as foreign: {:?}", id);
-            return Ok(None);
-        }
-        let result: Option<Presignature> = connection.hget(self.presig_key(), id).await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                if memory.mine.contains(id) || memory.reserved.contains(id) {
+                    tracing::error!("Can not take mine presignature as foreign: {:?}", id);
+                    return Ok(None);
+                }
+                return memory.remove(id);
+            }
+        };
+        let mut connection = pool.get().await?;
+        // Checking and taking in one script keeps a concurrent `reserve_mine` from handing
+        // out the same presignature.
+        let result: redis::Value = self
+            .mine_script(&TAKE_SCRIPT)
+            .arg(*id)
+            .invoke_async(&mut connection)
+            .await?;
         match result {
-            Some(presignature) => {
-                connection
-                    .hdel::<&str, PresignatureId, ()>(&self.presig_key(), *id)
-                    .await?;
-                Ok(Some(presignature))
+            redis::Value::Int(_) => {
+                tracing::error!("Can not take mine presignature as foreign: {:?}", id);
+                Ok(None)
             }
-            None => Ok(None),
+            redis::Value::Nil => Ok(None),
+            data => Ok(Some(Presignature::from_redis_value(&data)?)),
         }
     }
 
     pub async fn take_mine(&self) -> PresigResult<Option<Presignature>> {
-        let mut connection = self.redis_pool.get().await?;
-        let id: Option<PresignatureId> = connection.spop(self.mine_key()).await?;
-        match id {
-            Some(id) => self.take(&id).await,
-            None => Ok(None),
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let Some(id) = memory.mine.iter().next().copied() else {
+                    return Ok(None);
+                };
+                memory.mine.remove(&id);
+                return memory.remove(&id);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let result: Option<Presignature> = self
+            .mine_script(&TAKE_MINE_SCRIPT)
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(result)
+    }
+
+    /// Takes the mine presignature with the earliest `created_at`, if there is any.
+    pub async fn take_oldest_mine(&self) -> PresigResult<Option<Presignature>> {
+        self.take_mine_by_age(true).await
+    }
+
+    /// Takes the mine presignature with the latest `created_at`, if there is any.
+    pub async fn take_newest_mine(&self) -> PresigResult<Option<Presignature>> {
+        self.take_mine_by_age(false).await
+    }
+
+    /// Takes the oldest or the newest mine presignature. Ties in `created_at` are broken by id.
+    /// In redis this pops the mine age index in a single script, so it stays O(log N) and a
+    /// presignature is either fully taken or not at all. Mine presignatures stored before the
+    /// index existed are not in it until `backfill` ran, and are then taken in random order.
+    async fn take_mine_by_age(&self, oldest: bool) -> PresigResult<Option<Presignature>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let mut presignatures = Vec::with_capacity(memory.mine.len());
+                for id in &memory.mine {
+                    presignatures.extend(memory.get(id)?);
+                }
+                presignatures.sort_unstable_by_key(|presignature| {
+                    (presignature.created_at, presignature.id)
+                });
+                let found = if oldest {
+                    presignatures.first()
+                } else {
+                    presignatures.last()
+                };
+                let Some(id) = found.map(|presignature| presignature.id) else {
+                    return Ok(None);
+                };
+                memory.mine.remove(&id);
+                return memory.remove(&id);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let result: Option<Presignature> = self
+            .mine_script(&TAKE_MINE_BY_AGE_SCRIPT)
+            .arg(if oldest { "oldest" } else { "newest" })
+            .invoke_async(&mut connection)
+            .await?;
+        match result {
+            Some(presignature) => Ok(Some(presignature)),
+            None => {
+                drop(connection);
+                self.take_mine().await
+            }
         }
     }
 
+    /// Takes a mine presignature that `participant` took part in generating, if there is any.
+    /// In redis the search and the claim run in a single script, so two callers can never
+    /// take the same presignature.
+    pub async fn take_mine_with_participant(
+        &self,
+        participant: Participant,
+    ) -> PresigResult<Option<Presignature>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let mut found = None;
+                for id in &memory.mine {
+                    if let Some(presignature) = memory.get(id)? {
+                        if presignature.participants.contains(&participant) {
+                            found = Some(*id);
+                            break;
+                        }
+                    }
+                }
+                let Some(id) = found else {
+                    return Ok(None);
+                };
+                memory.mine.remove(&id);
+                return memory.remove(&id);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let result: Option<Presignature> = self
+            .mine_script(&TAKE_MINE_WITH_PARTICIPANT_SCRIPT)
+            .arg(u32::from(participant))
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(result)
+    }
+
+    /// Atomically moves the id from the mine set to the reserved ones and returns the
+    /// presignature data, which is kept in storage until the reservation is either committed
+    /// or rolled back. `take` refuses reserved presignatures, so they are only handed out once.
+    pub async fn reserve_mine(&self, id: &PresignatureId) -> PresigResult<Option<Presignature>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                if !memory.mine.remove(id) {
+                    return Ok(None);
+                }
+                let result = memory.get(id)?;
+                if result.is_none() {
+                    tracing::warn!(id, "reserved mine presignature has no data");
+                } else {
+                    memory.reserved.insert(*id);
+                }
+                return Ok(result);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let result: redis::Value = self
+            .mine_script(&RESERVE_MINE_SCRIPT)
+            .arg(*id)
+            .invoke_async(&mut connection)
+            .await?;
+        match result {
+            redis::Value::Int(_) => {
+                tracing::warn!(id, "reserved mine presignature has no data");
+                Ok(None)
+            }
+            redis::Value::Nil => Ok(None),
+            data => Ok(Some(Presignature::from_redis_value(&data)?)),
+        }
+    }
+
+    /// Deletes the data of a presignature previously reserved with `reserve_mine`.
+    pub async fn commit_reserved(&self, id: &PresignatureId) -> PresigResult<()> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                memory.reserved.remove(id);
+                memory.presignatures.remove(id);
+                memory.epochs.remove(id);
+                return Ok(());
+            }
+        };
+        let mut connection = pool.get().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hdel(self.reserved_key(), id)
+            .ignore()
+            .hdel(self.presig_key(), id)
+            .ignore()
+            .hdel(self.epoch_key(), id)
+            .ignore();
+        pipe.query_async::<()>(&mut connection).await?;
+        Ok(())
+    }
+
+    /// Puts a presignature previously reserved with `reserve_mine` back into the mine set.
+    /// Does nothing if it is not reserved, e.g. because the reservation was already committed.
+    pub async fn rollback_reserved(&self, id: &PresignatureId) -> PresigResult<()> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                if memory.reserved.remove(id) {
+                    memory.mine.insert(*id);
+                }
+                return Ok(());
+            }
+        };
+        let mut connection = pool.get().await?;
+        let _: () = self
+            .mine_script(&ROLLBACK_RESERVED_SCRIPT)
+            .arg(*id)
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes all stored presignatures generated with any of the given participants.
+    /// Returns the number of removed presignatures.
+    pub async fn remove_with_participants(&self, removed: &[Participant]) -> PresigResult<usize> {
+        self.remove_where(|presignature| {
+            presignature
+                .participants
+                .iter()
+                .any(|p| removed.contains(p))
+        })
+        .await
+    }
+
+    /// Removes all stored presignatures that need a participant outside of `online`.
+    /// Returns the number of removed presignatures.
+    pub async fn remove_not_within(&self, online: &[Participant]) -> PresigResult<usize> {
+        self.remove_where(|presignature| {
+            !presignature.participants.iter().all(|p| online.contains(p))
+        })
+        .await
+    }
+
+    async fn remove_where(&self, expired: impl Fn(&Presignature) -> bool) -> PresigResult<usize> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let mut removed = Vec::new();
+                for id in memory.presignatures.keys() {
+                    if let Some(presignature) = memory.get(id)? {
+                        if expired(&presignature) {
+                            removed.push(*id);
+                        }
+                    }
+                }
+                for id in &removed {
+                    memory.mine.remove(id);
+                    memory.remove(id)?;
+                }
+                return Ok(removed.len());
+            }
+        };
+        let mut connection = pool.get().await?;
+        let presignatures: HashMap<PresignatureId, Presignature> =
+            connection.hgetall(self.presig_key()).await?;
+        let removed: Vec<PresignatureId> = presignatures
+            .into_iter()
+            .filter(|(_, presignature)| expired(presignature))
+            .map(|(id, _)| id)
+            .collect();
+        drop(connection);
+        Ok(self.delete_batch(&removed).await?)
+    }
+
     pub async fn len_generated(&self) -> PresigResult<usize> {
-        let mut connection = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().presignatures.len());
+            }
+        };
+        let mut connection = pool.get().await?;
         let result: usize = connection.hlen(self.presig_key()).await?;
         Ok(result)
     }
 
     pub async fn len_mine(&self) -> PresigResult<usize> {
-        let mut connection = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().mine.len());
+            }
+        };
+        let mut connection = pool.get().await?;
         let result: usize = connection.scard(self.mine_key()).await?;
         Ok(result)
     }
 
+    /// Returns the generation time of the oldest mine presignature, if there is any. Read from
+    /// the mine age index, so no presignature is loaded. Presignatures stored before
+    /// `created_at` existed have it set to 0 and are skipped, as their age is unknown.
+    pub async fn oldest_mine_created_at(&self) -> PresigResult<Option<u64>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let memory = memory.lock();
+                let mut oldest: Option<u64> = None;
+                for id in &memory.mine {
+                    if let Some(presignature) = memory.get(id)? {
+                        let created_at = presignature.created_at;
+                        if created_at == 0 {
+                            continue;
+                        }
+                        oldest = Some(oldest.map_or(created_at, |oldest| oldest.min(created_at)));
+                    }
+                }
+                return Ok(oldest);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let oldest: Vec<(PresignatureId, u64)> = connection
+            .zrangebyscore_limit_withscores(self.mine_age_key(), "(0", "+inf", 0, 1)
+            .await?;
+        Ok(oldest.first().map(|(_, created_at)| *created_at))
+    }
+
+    pub async fn len_by_epoch(&self) -> PresigResult<HashMap<u64, usize>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut result = HashMap::new();
+                for epoch in memory.lock().epochs.values() {
+                    *result.entry(*epoch).or_default() += 1;
+                }
+                return Ok(result);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let epochs: Vec<u64> = connection.hvals(self.epoch_key()).await?;
+        let mut result = HashMap::new();
+        for epoch in epochs {
+            *result.entry(epoch).or_default() += 1;
+        }
+        Ok(result)
+    }
+
+    /// Removes entries that duplicate or no longer describe a stored presignature: ids kept
+    /// under other storage versions that are also stored under the current one, and mine or
+    /// epoch entries without presignature data. Returns the number of removed entries.
+    pub async fn compact(&self) -> PresigResult<usize> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                // Only the current storage version exists in memory, so there is nothing to
+                // deduplicate; just drop the dangling mine and epoch entries.
+                let mut memory = memory.lock();
+                let MemoryPresignatures {
+                    presignatures,
+                    mine,
+                    epochs,
+                    ..
+                } = &mut *memory;
+                let before = mine.len() + epochs.len();
+                mine.retain(|id| presignatures.contains_key(id));
+                epochs.retain(|id, _| presignatures.contains_key(id));
+                return Ok(before - mine.len() - epochs.len());
+            }
+        };
+        let mut connection = pool.get().await?;
+        let stored: HashSet<PresignatureId> = connection.hkeys(self.presig_key()).await?;
+        let current_keys = [
+            self.presig_key(),
+            self.mine_key(),
+            self.epoch_key(),
+            self.mine_age_key(),
+            self.reserved_key(),
+        ];
+        let mine_prefix = self.prefixed("presignatures_mine:".to_string());
+
+        let pattern = self.prefixed(format!("presignatures*:*:{}", self.node_account_id));
+        let mut keys = Vec::new();
+        {
+            let mut iter: redis::AsyncIter<String> = connection.scan_match(&pattern).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+
+        let mut removed = 0;
+        for key in keys.iter().filter(|key| !current_keys.contains(key)) {
+            if key.starts_with(&mine_prefix) {
+                let ids: Vec<PresignatureId> = connection.smembers(key).await?;
+                let duplicates: Vec<_> = ids.into_iter().filter(|id| stored.contains(id)).collect();
+                if !duplicates.is_empty() {
+                    removed += connection.srem::<_, _, usize>(key, &duplicates).await?;
+                }
+            } else {
+                let ids: Vec<PresignatureId> = connection.hkeys(key).await?;
+                let duplicates: Vec<_> = ids.into_iter().filter(|id| stored.contains(id)).collect();
+                if !duplicates.is_empty() {
+                    removed += connection.hdel::<_, _, usize>(key, &duplicates).await?;
+                }
+            }
+        }
+
+        let mine: Vec<PresignatureId> = connection.smembers(self.mine_key()).await?;
+        let dangling: Vec<_> = mine.into_iter().filter(|id| !stored.contains(id)).collect();
+        if !dangling.is_empty() {
+            removed += connection
+                .srem::<_, _, usize>(self.mine_key(), &dangling)
+                .await?;
+        }
+
+        let aged: Vec<PresignatureId> = connection.zrange(self.mine_age_key(), 0, -1).await?;
+        let dangling: Vec<_> = aged.into_iter().filter(|id| !stored.contains(id)).collect();
+        if !dangling.is_empty() {
+            removed += connection
+                .zrem::<_, _, usize>(self.mine_age_key(), &dangling)
+                .await?;
+        }
+
+        let epochs: Vec<PresignatureId> = connection.hkeys(self.epoch_key()).await?;
+        let dangling: Vec<_> = epochs
+            .into_iter()
+            .filter(|id| !stored.contains(id))
+            .collect();
+        if !dangling.is_empty() {
+            removed += connection
+                .hdel::<_, _, usize>(self.epoch_key(), &dangling)
+                .await?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Restores the epoch entries and mine age entries that presignatures stored by older
+    /// versions are missing. Only the presignatures lacking an entry are read, and their data
+    /// is left untouched. Returns the number of restored entries.
+    pub async fn backfill(&self) -> PresigResult<usize> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                // Epoch entries are always written together with the data in memory, and mine
+                // ages are read from the presignatures themselves.
+                let mut memory = memory.lock();
+                let missing: Vec<PresignatureId> = memory
+                    .presignatures
+                    .keys()
+                    .filter(|id| !memory.epochs.contains_key(id))
+                    .copied()
+                    .collect();
+                let mut restored = 0;
+                for id in missing {
+                    if let Some(presignature) = memory.get(&id)? {
+                        memory.epochs.insert(id, presignature.origin_epoch);
+                        restored += 1;
+                    }
+                }
+                return Ok(restored);
+            }
+        };
+        let mut connection = pool.get().await?;
+        let mut restored = 0;
+
+        let stored: Vec<PresignatureId> = connection.hkeys(self.presig_key()).await?;
+        let epochs: HashSet<PresignatureId> = connection.hkeys(self.epoch_key()).await?;
+        let missing: Vec<_> = stored
+            .into_iter()
+            .filter(|id| !epochs.contains(id))
+            .collect();
+        if !missing.is_empty() {
+            let presignatures: Vec<Option<Presignature>> = redis::cmd("HMGET")
+                .arg(self.presig_key())
+                .arg(&missing)
+                .query_async(&mut connection)
+                .await?;
+            let entries: Vec<(PresignatureId, u64)> = presignatures
+                .into_iter()
+                .flatten()
+                .map(|presignature| (presignature.id, presignature.origin_epoch))
+                .collect();
+            if !entries.is_empty() {
+                restored += redis::cmd("HSET")
+                    .arg(self.epoch_key())
+                    .arg(&entries)
+                    .query_async::<usize>(&mut connection)
+                    .await?;
+            }
+        }
+
+        let mine: Vec<PresignatureId> = connection.smembers(self.mine_key()).await?;
+        let aged: HashSet<PresignatureId> = connection.zrange(self.mine_age_key(), 0, -1).await?;
+        let missing: Vec<_> = mine.into_iter().filter(|id| !aged.contains(id)).collect();
+        if !missing.is_empty() {
+            let presignatures: Vec<Option<Presignature>> = redis::cmd("HMGET")
+                .arg(self.presig_key())
+                .arg(&missing)
+                .query_async(&mut connection)
+                .await?;
+            let entries: Vec<(u64, PresignatureId)> = presignatures
+                .into_iter()
+                .flatten()
+                .map(|presignature| (presignature.created_at, presignature.id))
+                .collect();
+            if !entries.is_empty() {
+                restored += redis::cmd("ZADD")
+                    .arg(self.mine_age_key())
+                    .arg("NX")
+                    .arg(&entries)
+                    .query_async::<usize>(&mut connection)
+                    .await?;
+            }
+        }
+
+        Ok(restored)
+    }
+
     pub async fn clear(&self) -> PresigResult<()> {
-        let mut connection = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                memory.presignatures.clear();
+                memory.mine.clear();
+                memory.epochs.clear();
+                memory.reserved.clear();
+                return Ok(());
+            }
+        };
+        let mut connection = pool.get().await?;
         connection.del::<&str, ()>(&self.presig_key()).await?;
         connection.del::<&str, ()>(&self.mine_key()).await?;
+        connection.del::<&str, ()>(&self.epoch_key()).await?;
+        connection.del::<&str, ()>(&self.mine_age_key()).await?;
+        connection.del::<&str, ()>(&self.reserved_key()).await?;
         Ok(())
     }
 
     fn presig_key(&self) -> String {
-        format!(
+        self.prefixed(format!(
             "presignatures:{}:{}",
             PRESIGNATURE_STORAGE_VERSION, self.node_account_id
-        )
+        ))
     }
 
     fn mine_key(&self) -> String {
-        format!(
+        self.prefixed(format!(
             "presignatures_mine:{}:{}",
             PRESIGNATURE_STORAGE_VERSION, self.node_account_id
-        )
+        ))
+    }
+
+    /// Maps each stored presignature id to the epoch it was generated in.
+    fn epoch_key(&self) -> String {
+        self.prefixed(format!(
+            "presignatures_epoch:{}:{}",
+            PRESIGNATURE_STORAGE_VERSION, self.node_account_id
+        ))
+    }
+
+    /// Sorted set of the mine presignature ids, scored by their `created_at`.
+    fn mine_age_key(&self) -> String {
+        self.prefixed(format!(
+            "presignatures_mine_age:{}:{}",
+            PRESIGNATURE_STORAGE_VERSION, self.node_account_id
+        ))
+    }
+
+    /// Maps each reserved mine presignature id to its `created_at`, see `reserve_mine`.
+    fn reserved_key(&self) -> String {
+        self.prefixed(format!(
+            "presignatures_reserved:{}:{}",
+            PRESIGNATURE_STORAGE_VERSION, self.node_account_id
+        ))
+    }
+
+    /// Prepares `script` with the keys shared by the scripts that take presignatures.
+    fn mine_script(&self, script: &'static redis::Script) -> redis::ScriptInvocation<'static> {
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(self.mine_key())
+            .key(self.mine_age_key())
+            .key(self.presig_key())
+            .key(self.epoch_key())
+            .key(self.reserved_key());
+        invocation
+    }
+
+    fn prefixed(&self, key: String) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{prefix}:{key}"),
+            None => key,
+        }
+    }
+}
+
+impl BatchInsertError {
+    fn all_failed(presignatures: Vec<Presignature>, err: &RedisError) -> Self {
+        let failed = presignatures
+            .into_iter()
+            .map(|presignature| {
+                let err = RedisError::from((
+                    err.kind(),
+                    "failed to store presignature batch",
+                    err.to_string(),
+                ));
+                (presignature, err)
+            })
+            .collect();
+        Self {
+            succeeded: 0,
+            failed,
+        }
     }
 }
 
@@ -1,58 +1,203 @@
-use crate::protocol::triple::{Triple, TripleId};
+// Without the in-memory test backend, `Backend` only has the redis variant.
+#![cfg_attr(
+    not(any(test, feature = "test-storage")),
+    allow(clippy::infallible_destructuring_match)
+)]
 
-use deadpool_redis::Pool;
+#[cfg(any(test, feature = "test-storage"))]
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::protocol::triple::{AuditEvent, Triple, TripleId};
+use crate::storage::Durability;
+#[cfg(any(test, feature = "test-storage"))]
+use crate::storage::MemoryBackend;
+
+use deadpool_redis::{Connection, Pool};
 use redis::{AsyncCommands, FromRedisValue, RedisWrite, ToRedisArgs};
 
 use near_account_id::AccountId;
 
+/// Removes the ids in the mine set `KEYS[1]` that have no triple in the hash `KEYS[2]` and
+/// returns how many were removed.
+const REMOVE_STALE_MINE_SCRIPT: &str = r"
+local removed = 0
+for _, id in ipairs(redis.call('SMEMBERS', KEYS[1])) do
+    if redis.call('HEXISTS', KEYS[2], id) == 0 then
+        removed = removed + redis.call('SREM', KEYS[1], id)
+    end
+end
+return removed
+";
+
 type TripleResult<T> = std::result::Result<T, anyhow::Error>;
 
 // Can be used to "clear" redis storage in case of a breaking change
 const TRIPLE_STORAGE_VERSION: &str = "v2";
 
+/// Number of most recent entries kept in the used triple log.
+const USED_TRIPLE_LOG_LEN: isize = 100_000;
+
+/// Number of most recent events kept in the triple audit log.
+const TRIPLE_AUDIT_LOG_LEN: isize = 100_000;
+
 pub fn init(pool: &Pool, account_id: &AccountId) -> TripleStorage {
     TripleStorage {
-        redis_pool: pool.clone(),
+        backend: Backend::Redis(pool.clone()),
         node_account_id: account_id.clone(),
+        durability: Durability::default(),
+        key_prefix: None,
     }
 }
 
+#[derive(Clone)]
+enum Backend {
+    Redis(Pool),
+    #[cfg(any(test, feature = "test-storage"))]
+    Memory(MemoryBackend<MemoryTriples>),
+}
+
+/// Contents of an in-memory storage, mirroring the redis keys.
+#[cfg(any(test, feature = "test-storage"))]
+#[derive(Default)]
+struct MemoryTriples {
+    triples: HashMap<TripleId, Triple>,
+    mine: HashSet<TripleId>,
+    used: Vec<TripleId>,
+    audit: Vec<AuditEvent>,
+}
+
 #[derive(Clone)]
 pub struct TripleStorage {
-    redis_pool: Pool,
+    backend: Backend,
     node_account_id: AccountId,
+    durability: Durability,
+    key_prefix: Option<String>,
 }
 
 impl TripleStorage {
+    /// Creates a storage that keeps triples in memory instead of redis. Nothing is persisted,
+    /// so this is only meant for tests and only built for them. Clones share the same
+    /// underlying storage.
+    #[cfg(any(test, feature = "test-storage"))]
+    pub fn in_memory(account_id: &AccountId) -> Self {
+        Self {
+            backend: Backend::Memory(MemoryBackend::default()),
+            node_account_id: account_id.clone(),
+            durability: Durability::default(),
+            key_prefix: None,
+        }
+    }
+
+    /// Sets the durability mode used for triple inserts.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Prefixes all keys with the given namespace, e.g. to isolate test runs sharing one redis.
+    pub fn with_key_prefix(mut self, key_prefix: Option<String>) -> Self {
+        self.key_prefix = key_prefix;
+        self
+    }
+
     pub async fn insert(&self, triple: Triple) -> TripleResult<()> {
-        let mut conn = self.redis_pool.get().await?;
-        conn.hset::<&str, TripleId, Triple, ()>(&self.triple_key(), triple.id, triple)
-            .await?;
-        Ok(())
+        reject_zero(&triple)?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                memory.lock().triples.insert(triple.id, triple);
+                return Ok(());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let mut pipe = redis::pipe();
+        pipe.hset(self.triple_key(), triple.id, triple).ignore();
+        self.write(&mut conn, pipe).await
     }
 
     pub async fn insert_mine(&self, triple: Triple) -> TripleResult<()> {
-        let mut conn = self.redis_pool.get().await?;
-        conn.sadd::<&str, TripleId, ()>(&self.mine_key(), triple.id)
-            .await?;
-        self.insert(triple).await?;
+        reject_zero(&triple)?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                memory.mine.insert(triple.id);
+                memory.triples.insert(triple.id, triple);
+                return Ok(());
+            }
+        };
+        let mut conn = pool.get().await?;
+        // The triple is stored before its id joins the mine set, so that an id in the mine set
+        // without a triple is always stale and `remove_stale_mine` can not race this insert.
+        let mut pipe = redis::pipe();
+        pipe.hset(self.triple_key(), triple.id, triple)
+            .ignore()
+            .sadd(self.mine_key(), triple.id)
+            .ignore();
+        self.write(&mut conn, pipe).await
+    }
+
+    /// Executes the write pipeline, waiting for a replica to acknowledge it when
+    /// synchronous durability is enabled.
+    async fn write(&self, conn: &mut Connection, mut pipe: redis::Pipeline) -> TripleResult<()> {
+        match self.durability {
+            Durability::Eventual => {
+                let _: () = pipe.query_async(conn).await?;
+            }
+            Durability::Synchronous => {
+                pipe.cmd("WAIT").arg(1).arg(100);
+                let (acknowledged,): (usize,) = pipe.query_async(conn).await?;
+                if acknowledged < 1 {
+                    anyhow::bail!("triple write was not acknowledged by any replica");
+                }
+            }
+        }
         Ok(())
     }
 
     pub async fn contains(&self, id: &TripleId) -> TripleResult<bool> {
-        let mut conn = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().triples.contains_key(id));
+            }
+        };
+        let mut conn = pool.get().await?;
         let result: bool = conn.hexists(self.triple_key(), id).await?;
         Ok(result)
     }
 
     pub async fn contains_mine(&self, id: &TripleId) -> TripleResult<bool> {
-        let mut conn = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().mine.contains(id));
+            }
+        };
+        let mut conn = pool.get().await?;
         let result: bool = conn.sismember(self.mine_key(), id).await?;
         Ok(result)
     }
 
     pub async fn take(&self, id: &TripleId) -> TripleResult<Option<Triple>> {
-        let mut conn = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                if memory.mine.contains(id) {
+                    tracing::error!("Can not take mine triple as foreign: {:?}", id);
+                    return Ok(None);
+                }
+                return Ok(memory.triples.remove(id));
+            }
+        };
+        let mut conn = pool.get().await?;
         if self.contains_mine(id).await? {
             tracing::error!("Can not take mine triple as foreign: {:?}", id);
             return Ok(None);
@@ -68,8 +213,34 @@ impl TripleStorage {
         }
     }
 
+    /// Returns a copy of the stored triple without removing it.
+    pub async fn get(&self, id: &TripleId) -> TripleResult<Option<Triple>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().triples.get(id).cloned());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let result: Option<Triple> = conn.hget(self.triple_key(), id).await?;
+        Ok(result)
+    }
+
     pub async fn take_mine(&self) -> TripleResult<Option<Triple>> {
-        let mut conn = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let Some(id) = memory.mine.iter().next().copied() else {
+                    return Ok(None);
+                };
+                memory.mine.remove(&id);
+                return Ok(memory.triples.remove(&id));
+            }
+        };
+        let mut conn = pool.get().await?;
         let id: Option<TripleId> = conn.spop(self.mine_key()).await?;
         match id {
             Some(id) => self.take(&id).await,
@@ -77,37 +248,416 @@ impl TripleStorage {
         }
     }
 
+    /// Takes the mine triple with the given id. Returns `None` if it is not one of mine.
+    pub async fn take_mine_by_id(&self, id: &TripleId) -> TripleResult<Option<Triple>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                if !memory.mine.remove(id) {
+                    return Ok(None);
+                }
+                return Ok(memory.triples.remove(id));
+            }
+        };
+        let mut conn = pool.get().await?;
+        let removed: usize = conn.srem(self.mine_key(), id).await?;
+        if removed == 0 {
+            return Ok(None);
+        }
+        self.take(id).await
+    }
+
     pub async fn len_generated(&self) -> TripleResult<usize> {
-        let mut conn = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().triples.len());
+            }
+        };
+        let mut conn = pool.get().await?;
         let result: usize = conn.hlen(self.triple_key()).await?;
         Ok(result)
     }
 
     pub async fn len_mine(&self) -> TripleResult<usize> {
-        let mut conn = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().mine.len());
+            }
+        };
+        let mut conn = pool.get().await?;
         let result: usize = conn.scard(self.mine_key()).await?;
         Ok(result)
     }
 
+    /// Returns the ids of all stored triples.
+    pub async fn ids(&self) -> TripleResult<Vec<TripleId>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().triples.keys().copied().collect());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let result: Vec<TripleId> = conn.hkeys(self.triple_key()).await?;
+        Ok(result)
+    }
+
+    /// Returns the ids of all stored triples that belong to this node.
+    pub async fn mine_ids(&self) -> TripleResult<Vec<TripleId>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().mine.iter().copied().collect());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let result: Vec<TripleId> = conn.smembers(self.mine_key()).await?;
+        Ok(result)
+    }
+
+    /// Lists the ids of the stored triples, or only of the ones that belong to this node,
+    /// without loading the triples themselves. The ids are sorted.
+    pub async fn list_ids(&self, mine_only: bool) -> TripleResult<Vec<TripleId>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let memory = memory.lock();
+                let mut ids: Vec<TripleId> = if mine_only {
+                    memory.mine.iter().copied().collect()
+                } else {
+                    memory.triples.keys().copied().collect()
+                };
+                ids.sort_unstable();
+                return Ok(ids);
+            }
+        };
+        let mut conn = pool.get().await?;
+        let mut ids: Vec<TripleId> = if mine_only {
+            let mut ids = Vec::new();
+            let mut iter: redis::AsyncIter<TripleId> = conn.sscan(self.mine_key()).await?;
+            while let Some(id) = iter.next_item().await {
+                ids.push(id);
+            }
+            ids
+        } else {
+            // HSCAN would return every triple along with its id, so only fetch the keys.
+            conn.hkeys(self.triple_key()).await?
+        };
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Returns up to `limit` ids of the stored triples, or only of the ones that belong to this
+    /// node, sorted. Unlike [`TripleStorage::list_ids`], the work done does not grow with the
+    /// number of stored triples. Which ids are returned is unspecified.
+    pub async fn sample_ids(&self, mine_only: bool, limit: usize) -> TripleResult<Vec<TripleId>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let memory = memory.lock();
+                let mut ids: Vec<TripleId> = if mine_only {
+                    memory.mine.iter().copied().collect()
+                } else {
+                    memory.triples.keys().copied().collect()
+                };
+                ids.sort_unstable();
+                ids.truncate(limit);
+                return Ok(ids);
+            }
+        };
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let mut conn = pool.get().await?;
+        // A positive count makes both commands return distinct ids.
+        let mut ids: Vec<TripleId> = if mine_only {
+            redis::cmd("SRANDMEMBER")
+                .arg(self.mine_key())
+                .arg(limit)
+                .query_async(&mut conn)
+                .await?
+        } else {
+            redis::cmd("HRANDFIELD")
+                .arg(self.triple_key())
+                .arg(limit)
+                .query_async(&mut conn)
+                .await?
+        };
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Returns copies of all stored triples that belong to this node.
+    pub async fn mine(&self) -> TripleResult<Vec<Triple>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let memory = memory.lock();
+                return Ok(memory
+                    .mine
+                    .iter()
+                    .filter_map(|id| memory.triples.get(id).cloned())
+                    .collect());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let mine_ids: Vec<TripleId> = conn.smembers(self.mine_key()).await?;
+        let mut result = Vec::with_capacity(mine_ids.len());
+        for id in mine_ids {
+            if let Some(triple) = conn.hget(self.triple_key(), id).await? {
+                result.push(triple);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Removes ids from the mine set that no longer have a triple stored for them, in a
+    /// single atomic script. Returns the number of stale ids removed.
+    pub async fn remove_stale_mine(&self) -> TripleResult<usize> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                let before = memory.mine.len();
+                let MemoryTriples { triples, mine, .. } = &mut *memory;
+                mine.retain(|id| triples.contains_key(id));
+                return Ok(before - memory.mine.len());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let removed: usize = redis::Script::new(REMOVE_STALE_MINE_SCRIPT)
+            .key(self.mine_key())
+            .key(self.triple_key())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(removed)
+    }
+
     pub async fn clear(&self) -> TripleResult<()> {
-        let mut conn = self.redis_pool.get().await?;
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                memory.triples.clear();
+                memory.mine.clear();
+                return Ok(());
+            }
+        };
+        let mut conn = pool.get().await?;
         conn.del::<&str, ()>(&self.triple_key()).await?;
         conn.del::<&str, ()>(&self.mine_key()).await?;
         Ok(())
     }
 
+    /// Returns the log of triples consumed by presignatures of this node.
+    pub fn used_log(&self) -> UsedTripleLog {
+        UsedTripleLog {
+            backend: self.backend.clone(),
+            key: self.prefixed(format!(
+                "triples_used:{}:{}",
+                TRIPLE_STORAGE_VERSION, self.node_account_id
+            )),
+        }
+    }
+
+    /// Returns the audit trail of the triples of this node.
+    pub fn audit_log(&self) -> TripleAuditLog {
+        TripleAuditLog {
+            backend: self.backend.clone(),
+            key: self.prefixed(format!(
+                "triples_audit:{}:{}",
+                TRIPLE_STORAGE_VERSION, self.node_account_id
+            )),
+        }
+    }
+
     fn triple_key(&self) -> String {
-        format!(
+        self.prefixed(format!(
             "triples:{}:{}",
             TRIPLE_STORAGE_VERSION, self.node_account_id
-        )
+        ))
     }
 
     fn mine_key(&self) -> String {
-        format!(
+        self.prefixed(format!(
             "triples_mine:{}:{}",
             TRIPLE_STORAGE_VERSION, self.node_account_id
-        )
+        ))
+    }
+
+    fn prefixed(&self, key: String) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{prefix}:{key}"),
+            None => key,
+        }
+    }
+}
+
+/// Refuses to store triples with all-zero shares, which can only come from test fixtures
+/// leaking into a real deployment. Tests opt out with the `allow-zero-triples` feature.
+fn reject_zero(triple: &Triple) -> TripleResult<()> {
+    if !cfg!(feature = "allow-zero-triples") && triple.is_zero() {
+        anyhow::bail!("refusing to store triple {} with zero shares", triple.id);
+    }
+    Ok(())
+}
+
+/// Append-only log of the triples consumed by presignature generation on a node. A triple
+/// must never be consumed twice, so any id that shows up more than once in the log is a bug.
+/// Only the most recent `USED_TRIPLE_LOG_LEN` entries are kept.
+#[derive(Clone)]
+pub struct UsedTripleLog {
+    backend: Backend,
+    key: String,
+}
+
+impl UsedTripleLog {
+    pub async fn record(&self, ids: &[TripleId]) -> TripleResult<()> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                memory.used.extend_from_slice(ids);
+                let excess = memory
+                    .used
+                    .len()
+                    .saturating_sub(USED_TRIPLE_LOG_LEN as usize);
+                memory.used.drain(..excess);
+                return Ok(());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let mut pipe = redis::pipe();
+        pipe.rpush(&self.key, ids)
+            .ignore()
+            .ltrim(&self.key, -USED_TRIPLE_LOG_LEN, -1)
+            .ignore();
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    pub async fn entries(&self) -> TripleResult<Vec<TripleId>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().used.clone());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let entries: Vec<TripleId> = conn.lrange(&self.key, 0, -1).await?;
+        Ok(entries)
+    }
+
+    /// Returns the ids that were consumed more than once, sorted.
+    pub async fn reused(&self) -> TripleResult<Vec<TripleId>> {
+        let mut seen = HashSet::new();
+        let mut reused: Vec<TripleId> = self
+            .entries()
+            .await?
+            .into_iter()
+            .filter(|id| !seen.insert(*id))
+            .collect();
+        reused.sort_unstable();
+        reused.dedup();
+        Ok(reused)
+    }
+
+    pub async fn clear(&self) -> TripleResult<()> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                memory.lock().used.clear();
+                return Ok(());
+            }
+        };
+        let mut conn = pool.get().await?;
+        conn.del::<&str, ()>(&self.key).await?;
+        Ok(())
+    }
+}
+
+/// Append-only audit trail of every triple this node generated, consumed or lost, in the
+/// order the events happened. Only the most recent `TRIPLE_AUDIT_LOG_LEN` events are kept.
+#[derive(Clone)]
+pub struct TripleAuditLog {
+    backend: Backend,
+    key: String,
+}
+
+impl TripleAuditLog {
+    pub async fn append(&self, events: &[AuditEvent]) -> TripleResult<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                let mut memory = memory.lock();
+                memory.audit.extend_from_slice(events);
+                let excess = memory
+                    .audit
+                    .len()
+                    .saturating_sub(TRIPLE_AUDIT_LOG_LEN as usize);
+                memory.audit.drain(..excess);
+                return Ok(());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let events: Vec<String> = events.iter().map(AuditEvent::to_json).collect();
+        let mut pipe = redis::pipe();
+        pipe.rpush(&self.key, events)
+            .ignore()
+            .ltrim(&self.key, -TRIPLE_AUDIT_LOG_LEN, -1)
+            .ignore();
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    pub async fn events(&self) -> TripleResult<Vec<AuditEvent>> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                return Ok(memory.lock().audit.clone());
+            }
+        };
+        let mut conn = pool.get().await?;
+        let events: Vec<String> = conn.lrange(&self.key, 0, -1).await?;
+        events
+            .iter()
+            .map(|event| Ok(serde_json::from_str(event)?))
+            .collect()
+    }
+
+    pub async fn clear(&self) -> TripleResult<()> {
+        let pool = match &self.backend {
+            Backend::Redis(pool) => pool,
+            #[cfg(any(test, feature = "test-storage"))]
+            Backend::Memory(memory) => {
+                memory.lock().audit.clear();
+                return Ok(());
+            }
+        };
+        let mut conn = pool.get().await?;
+        conn.del::<&str, ()>(&self.key).await?;
+        Ok(())
     }
 }
 
@@ -42,6 +42,7 @@ impl NearPublicKeyExt for near_crypto::PublicKey {
 pub trait AffinePointExt {
     fn into_near_public_key(self) -> near_crypto::PublicKey;
     fn to_base58(&self) -> String;
+    fn to_near_public_key(&self) -> near_sdk::PublicKey;
 }
 
 impl AffinePointExt for AffinePoint {
@@ -61,6 +62,16 @@ impl AffinePointExt for AffinePoint {
         .unwrap();
         format!("{:?}", key)
     }
+
+    /// Inverse of [`NearPublicKeyExt::into_affine_point`]. NEAR only supports uncompressed
+    /// secp256k1 keys, so the point is stored as its 64 byte `x || y` form.
+    fn to_near_public_key(&self) -> near_sdk::PublicKey {
+        near_sdk::PublicKey::from_parts(
+            near_sdk::CurveType::SECP256K1,
+            self.to_encoded_point(false).as_bytes()[1..65].to_vec(),
+        )
+        .unwrap()
+    }
 }
 
 pub fn is_elapsed_longer_than_timeout(timestamp_sec: u64, timeout: u64) -> bool {
@@ -76,3 +87,28 @@ pub fn is_elapsed_longer_than_timeout(timestamp_sec: u64, timeout: u64) -> bool
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AffinePointExt, NearPublicKeyExt};
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+    use k256::SecretKey;
+
+    #[test]
+    fn test_near_public_key_roundtrip() {
+        let point = SecretKey::random(&mut rand::thread_rng())
+            .public_key()
+            .as_affine()
+            .to_owned();
+        let near_public_key = near_sdk::PublicKey::from_parts(
+            near_sdk::CurveType::SECP256K1,
+            point.to_encoded_point(false).as_bytes()[1..].to_vec(),
+        )
+        .unwrap();
+
+        let affine = near_public_key.clone().into_affine_point();
+        assert_eq!(affine, point);
+        let roundtrip = affine.to_near_public_key();
+        assert_eq!(roundtrip.as_bytes(), near_public_key.as_bytes());
+    }
+}
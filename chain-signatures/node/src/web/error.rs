@@ -20,6 +20,10 @@ pub enum Error {
     Message(#[from] SendError<MpcMessage>),
     #[error(transparent)]
     Rpc(#[from] near_fetch::Error),
+    #[error("node is not running")]
+    NotRunning,
+    #[error("storage error: {0}")]
+    Storage(anyhow::Error),
 }
 
 impl Error {
@@ -30,6 +34,8 @@ impl Error {
             Error::Cryptography(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Message(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Rpc(_) => StatusCode::BAD_REQUEST,
+            Error::NotRunning => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
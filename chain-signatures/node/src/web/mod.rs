@@ -3,10 +3,13 @@ mod error;
 use self::error::Error;
 use crate::indexer::Indexer;
 use crate::protocol::message::SignedMessage;
+use crate::protocol::triple::{PoolHealth, TripleId};
 use crate::protocol::{MpcMessage, NodeState};
 use crate::web::error::Result;
 use anyhow::Context;
+use axum::extract::Query;
 use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use axum_extra::extract::WithRejection;
@@ -15,6 +18,7 @@ use mpc_keys::hpke::{self, Ciphered};
 use near_primitives::types::BlockHeight;
 use prometheus::{Encoder, TextEncoder};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::{net::SocketAddr, sync::Arc};
 use tokio::sync::{mpsc::Sender, RwLock};
 
@@ -42,15 +46,10 @@ pub async fn run(
 
     let app = Router::new()
         // healthcheck endpoint
-        .route(
-            "/",
-            get(|| async move {
-                tracing::info!("node is ready to accept connections");
-                StatusCode::OK
-            }),
-        )
+        .route("/", get(health))
         .route("/msg", post(msg))
         .route("/state", get(state))
+        .route("/triples/mine", get(mine_triples))
         .route("/metrics", get(metrics))
         .layer(Extension(Arc::new(axum_state)));
 
@@ -64,6 +63,64 @@ pub async fn run(
     Ok(())
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct HealthQuery {
+    /// Kept as a string so that a malformed value does not fail the healthcheck.
+    #[serde(default)]
+    verbose: Option<String>,
+}
+
+impl HealthQuery {
+    fn is_verbose(&self) -> bool {
+        matches!(self.verbose.as_deref(), Some("true" | "1"))
+    }
+}
+
+/// Healthcheck. With `?verbose=true`, a running node also reports its participants, the
+/// age of its oldest presignature, a bounded summary of its triple manager, see
+/// [`crate::protocol::triple::TripleManager::debug_dump`], and a `status` that is `degraded`
+/// while either its triple or its presignature pool is degraded.
+async fn health(
+    Extension(state): Extension<Arc<AxumState>>,
+    Query(query): Query<HealthQuery>,
+) -> Response {
+    tracing::info!("node is ready to accept connections");
+    if !query.is_verbose() {
+        return StatusCode::OK.into_response();
+    }
+
+    let protocol_state = state.protocol_state.read().await;
+    let NodeState::Running(state) = &*protocol_state else {
+        return Json(serde_json::json!({ "state": "not_running" })).into_response();
+    };
+    let triple_manager_read = state.triple_manager.read().await;
+    let triple_manager = triple_manager_read.read_only();
+    let triple_pool_health = triple_manager
+        .pool_load()
+        .map(|(_, health)| health)
+        .unwrap_or_default();
+    let triples = triple_manager.debug_dump().await;
+    let presignature_pool_age = state.presignature_manager.read().await.pool_age();
+    let presignature_pool_health = presignature_pool_age
+        .map(|(_, health)| health)
+        .unwrap_or_default();
+    let status = if triple_pool_health == PoolHealth::Degraded
+        || presignature_pool_health == PoolHealth::Degraded
+    {
+        PoolHealth::Degraded
+    } else {
+        PoolHealth::Healthy
+    };
+    Json(serde_json::json!({
+        "state": "running",
+        "status": status,
+        "participants": state.participants.keys_vec(),
+        "presignature_oldest_mine_age_secs": presignature_pool_age.map(|(age, _)| age.as_secs()),
+        "triples": triples,
+    }))
+    .into_response()
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MsgRequest {
     pub from: Participant,
@@ -111,6 +168,18 @@ pub enum StateView {
         presignature_count: usize,
         presignature_mine_count: usize,
         presignature_potential_count: usize,
+        presignature_count_by_epoch: HashMap<u64, usize>,
+        /// Seconds since the oldest presignature owned by this node was generated.
+        #[serde(default)]
+        presignature_oldest_mine_age_secs: Option<u64>,
+        #[serde(default)]
+        presignature_pool_health: PoolHealth,
+        /// How empty the triple pool is, from 0 (full) to 1 (empty).
+        #[serde(default)]
+        triple_load_factor: Option<f64>,
+        #[serde(default)]
+        triple_pool_health: PoolHealth,
+        epoch: u64,
         latest_block_height: BlockHeight,
         is_stable: bool,
     },
@@ -138,13 +207,24 @@ async fn state(Extension(state): Extension<Arc<AxumState>>) -> Result<Json<State
     match &*protocol_state {
         NodeState::Running(state) => {
             let triple_manager_read = state.triple_manager.read().await;
-            let triple_potential_count = triple_manager_read.len_potential().await;
-            let triple_count = triple_manager_read.len_generated().await;
-            let triple_mine_count = triple_manager_read.len_mine().await;
+            let triples = triple_manager_read.read_only();
+            let triple_potential_count = triples.len_potential().await;
+            let triple_count = triples.len_generated().await;
+            let triple_mine_count = triples.len_mine().await;
+            let (triple_load_factor, triple_pool_health) = match triples.pool_load() {
+                Some((load_factor, health)) => (Some(load_factor), health),
+                None => (None, PoolHealth::default()),
+            };
             let presignature_read = state.presignature_manager.read().await;
             let presignature_count = presignature_read.len_generated().await;
             let presignature_mine_count = presignature_read.len_mine().await;
             let presignature_potential_count = presignature_read.len_potential().await;
+            let presignature_count_by_epoch = presignature_read.len_by_epoch().await;
+            let (presignature_oldest_mine_age_secs, presignature_pool_health) =
+                match presignature_read.pool_age() {
+                    Some((age, health)) => (Some(age.as_secs()), health),
+                    None => (None, PoolHealth::default()),
+                };
             let participants = state.participants.keys_vec();
 
             Ok(Json(StateView::Running {
@@ -155,6 +235,12 @@ async fn state(Extension(state): Extension<Arc<AxumState>>) -> Result<Json<State
                 presignature_count,
                 presignature_mine_count,
                 presignature_potential_count,
+                presignature_count_by_epoch,
+                presignature_oldest_mine_age_secs,
+                presignature_pool_health,
+                triple_load_factor,
+                triple_pool_health,
+                epoch: state.epoch,
                 latest_block_height,
                 is_stable,
             }))
@@ -183,6 +269,26 @@ async fn state(Extension(state): Extension<Arc<AxumState>>) -> Result<Json<State
     }
 }
 
+/// Lists the ids of all triples this node owns, so that operators can inspect the inventory
+/// without access to redis.
+#[tracing::instrument(level = "debug", skip_all)]
+async fn mine_triples(Extension(state): Extension<Arc<AxumState>>) -> Result<Json<Vec<TripleId>>> {
+    let protocol_state = state.protocol_state.read().await;
+    let NodeState::Running(state) = &*protocol_state else {
+        return Err(Error::NotRunning);
+    };
+    let triple_manager = state.triple_manager.read().await;
+    let ids = triple_manager
+        .read_only()
+        .get_all_mine()
+        .await
+        .map_err(|err| {
+            tracing::warn!(?err, "failed to list mine triples");
+            Error::Storage(err)
+        })?;
+    Ok(Json(ids))
+}
+
 #[tracing::instrument(level = "debug", skip_all)]
 async fn metrics() -> (StatusCode, String) {
     let grab_metrics = || {
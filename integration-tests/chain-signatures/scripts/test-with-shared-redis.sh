@@ -0,0 +1,21 @@
+#!/usr/bin/env bash
+# Starts a single Redis instance and runs all chain-signatures integration tests against it
+# instead of starting a new Redis container per test. Extra arguments are passed to `cargo test`.
+set -euo pipefail
+
+REDIS_CONTAINER="${REDIS_CONTAINER:-mpc-it-shared-redis}"
+REDIS_PORT="${REDIS_PORT:-6379}"
+
+cleanup() {
+    docker rm -f "$REDIS_CONTAINER" >/dev/null 2>&1 || true
+}
+trap cleanup EXIT
+
+cleanup
+docker run -d --name "$REDIS_CONTAINER" -p "$REDIS_PORT:6379" redis:7.0.15 >/dev/null
+until docker exec "$REDIS_CONTAINER" redis-cli ping >/dev/null 2>&1; do
+    sleep 1
+done
+
+cd "$(dirname "$0")/.."
+MPC_IT_REDIS_URL="redis://127.0.0.1:$REDIS_PORT" cargo test "$@"
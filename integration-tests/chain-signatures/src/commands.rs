@@ -110,7 +110,7 @@ pub fn proposed_updates_command(
 }
 
 pub fn init_command(contract_id: &AccountId, caller_id: &AccountId) -> anyhow::Result<String> {
-    let threshold: usize = 1;
+    let threshold: usize = 2;
     let candidates: Candidates = dummy_candidates();
 
     let init_json = format!(
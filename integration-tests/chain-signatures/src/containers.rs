@@ -1,4 +1,5 @@
 use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use super::{local::NodeConfig, utils, MultichainConfig};
 use anyhow::{anyhow, Context};
@@ -78,6 +79,10 @@ impl<'a> Node<'a> {
         .await
     }
 
+    pub async fn get_triple_stats(&self) -> anyhow::Result<crate::TripleStats> {
+        crate::fetch_triple_stats(&self.address).await
+    }
+
     pub fn kill(self) -> NodeConfig {
         self.container.stop();
         NodeConfig {
@@ -111,6 +116,9 @@ impl<'a> Node<'a> {
             indexer_options: indexer_options.clone(),
             my_address: None,
             storage_options: ctx.storage_options.clone(),
+            triple_pool_alert_threshold: mpc_node::config::DEFAULT_TRIPLE_POOL_ALERT_THRESHOLD,
+            max_presignature_age_secs: mpc_node::config::DEFAULT_MAX_PRESIGNATURE_AGE_SECS,
+            max_triple_generators: config.cfg.max_concurrent_triple_gen,
             sign_sk: Some(config.sign_sk.clone()),
             override_config: Some(OverrideConfig::new(serde_json::to_value(
                 config.cfg.protocol.clone(),
@@ -242,6 +250,66 @@ impl<'a> LocalStack<'a> {
     }
 }
 
+/// State of a proxy as reported by a toxiproxy server.
+#[derive(Debug, Clone)]
+pub struct ProxyStatus {
+    pub name: String,
+    pub enabled: bool,
+    pub upstream: String,
+    pub toxics: Vec<Toxic>,
+}
+
+/// A toxic active on a toxiproxy proxy. `attributes` holds the toxic specific parameters,
+/// e.g. `latency` and `jitter` for a latency toxic.
+#[derive(Debug, Clone)]
+pub struct Toxic {
+    pub name: String,
+    pub kind: String,
+    pub stream: String,
+    pub toxicity: f64,
+    pub attributes: serde_json::Value,
+}
+
+impl ProxyStatus {
+    fn from_json(proxy: &serde_json::Value) -> anyhow::Result<Self> {
+        let toxics = proxy["toxics"]
+            .as_array()
+            .ok_or_else(|| anyhow!("proxy has no toxics list: {proxy}"))?
+            .iter()
+            .map(Toxic::from_json)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            name: json_str(proxy, "name")?,
+            enabled: proxy["enabled"]
+                .as_bool()
+                .ok_or_else(|| anyhow!("proxy has no enabled flag: {proxy}"))?,
+            upstream: json_str(proxy, "upstream")?,
+            toxics,
+        })
+    }
+}
+
+impl Toxic {
+    fn from_json(toxic: &serde_json::Value) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: json_str(toxic, "name")?,
+            kind: json_str(toxic, "type")?,
+            stream: json_str(toxic, "stream")?,
+            toxicity: toxic["toxicity"]
+                .as_f64()
+                .ok_or_else(|| anyhow!("toxic has no toxicity: {toxic}"))?,
+            attributes: toxic["attributes"].clone(),
+        })
+    }
+}
+
+fn json_str(value: &serde_json::Value, field: &str) -> anyhow::Result<String> {
+    value[field]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("missing string field `{field}` in {value}"))
+}
+
 pub struct LakeIndexer<'a> {
     pub container: Container<'a, GenericImage>,
     pub bucket_name: String,
@@ -344,6 +412,27 @@ impl<'a> LakeIndexer<'a> {
         Ok(())
     }
 
+    // Lists the proxies of the toxi proxy server along with their active toxics, so tests can
+    // check that the toxics they added were actually applied.
+    pub async fn list_proxies(host: bool) -> anyhow::Result<Vec<ProxyStatus>> {
+        let toxi_server_address = if host {
+            Self::TOXI_SERVER_PROCESS_ADDRESS
+        } else {
+            Self::TOXI_SERVER_EXPOSE_ADDRESS
+        };
+        let proxies: serde_json::Value = reqwest::get(format!("{}/proxies", toxi_server_address))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        proxies
+            .as_object()
+            .ok_or_else(|| anyhow!("unexpected toxiproxy response: {proxies}"))?
+            .values()
+            .map(ProxyStatus::from_json)
+            .collect()
+    }
+
     pub async fn run(
         docker_client: &'a DockerClient,
         network: &str,
@@ -497,6 +586,33 @@ impl DockerClient {
         Ok(())
     }
 
+    /// Removes the networks whose name starts with `prefix` and that no container is attached
+    /// to anymore, e.g. the ones left behind by failed test runs. Returns how many networks
+    /// were removed.
+    pub async fn cleanup_all_networks(&self, prefix: &str) -> anyhow::Result<usize> {
+        let _lock = &NETWORK_MUTEX.lock().await;
+        let mut removed = 0;
+        for network in self.docker.list_networks::<&str>(None).await? {
+            let Some(name) = network.name.filter(|name| name.starts_with(prefix)) else {
+                continue;
+            };
+            // Listing networks does not report the attached containers, only inspecting does.
+            let network = self.docker.inspect_network::<&str>(&name, None).await?;
+            if network
+                .containers
+                .is_some_and(|containers| !containers.is_empty())
+            {
+                tracing::info!(name, "network is still in use, not removing it");
+                continue;
+            }
+            self.docker.remove_network(&name).await?;
+            tracing::info!(name, "removed network");
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+
     pub async fn continuously_print_logs(&self, id: &str) -> anyhow::Result<()> {
         let mut output = self.docker.logs::<String>(
             id,
@@ -545,6 +661,43 @@ impl DockerClient {
 
         Ok(())
     }
+
+    /// Follows the logs of the container `id` for `duration` and returns the first line
+    /// containing `pattern`, as soon as it shows up. Only output written after this call is
+    /// searched.
+    pub async fn find_in_logs(
+        &self,
+        id: &str,
+        pattern: &str,
+        duration: Duration,
+    ) -> anyhow::Result<Option<String>> {
+        let since = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let mut output = self.docker.logs::<String>(
+            id,
+            Some(LogsOptions {
+                follow: true,
+                stdout: true,
+                stderr: true,
+                since,
+                ..Default::default()
+            }),
+        );
+
+        let search = async {
+            while let Some(output) = output.next().await {
+                let output = output?.into_bytes();
+                let output = String::from_utf8_lossy(&output);
+                if let Some(line) = output.lines().find(|line| line.contains(pattern)) {
+                    return Ok(Some(line.to_string()));
+                }
+            }
+            Ok(None)
+        };
+        match tokio::time::timeout(duration, search).await {
+            Ok(found) => found,
+            Err(_) => Ok(None),
+        }
+    }
 }
 
 impl Default for DockerClient {
@@ -566,6 +719,8 @@ pub struct Redis<'a> {
     pub container: Container<'a, GenericImage>,
     pub internal_address: String,
     pub external_address: String,
+    /// Password clients have to authenticate with, if any. It is part of both addresses.
+    pub password: Option<String>,
 }
 
 impl<'a> Redis<'a> {
@@ -577,16 +732,84 @@ impl<'a> Redis<'a> {
             .with_exposed_port(Self::DEFAULT_REDIS_PORT)
             .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"));
         let image: RunnableImage<GenericImage> = image.into();
+        Self::start(docker_client, network, image, None).await
+    }
+
+    /// Runs Redis with `--requirepass`, like production instances, so that clients have to
+    /// authenticate with `password`. The returned addresses carry the password.
+    pub async fn with_auth(
+        docker_client: &'a DockerClient,
+        network: &str,
+        password: &str,
+    ) -> anyhow::Result<Redis<'a>> {
+        tracing::info!("Running Redis container with authentication...");
+        let image = GenericImage::new("redis", "7.0.15")
+            .with_exposed_port(Self::DEFAULT_REDIS_PORT)
+            .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"));
+        let args = ["redis-server", "--requirepass", password]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let image: RunnableImage<GenericImage> = (image, args).into();
+        Self::start(docker_client, network, image, Some(password.to_string())).await
+    }
+
+    /// Runs Redis with its data directory mounted from `rdb_path` on the host and snapshots
+    /// configured with `save 1 1`, so the data survives container restarts.
+    pub async fn with_persistence(
+        docker_client: &'a DockerClient,
+        network: &str,
+        rdb_path: &Path,
+    ) -> anyhow::Result<Redis<'a>> {
+        tracing::info!(?rdb_path, "Running Redis container with persistence...");
+        let image = GenericImage::new("redis", "7.0.15")
+            .with_exposed_port(Self::DEFAULT_REDIS_PORT)
+            .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"))
+            .with_volume(rdb_path.to_string_lossy(), "/data");
+        let args = ["redis-server", "--save", "1", "1", "--dir", "/data"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let image: RunnableImage<GenericImage> = (image, args).into();
+        Self::start(docker_client, network, image, None).await
+    }
+
+    /// Stops and starts the container again, refreshing its addresses.
+    pub async fn restart(
+        &mut self,
+        docker_client: &DockerClient,
+        network: &str,
+    ) -> anyhow::Result<()> {
+        tracing::info!("Restarting Redis container...");
+        self.container.stop();
+        self.container.start();
+        let network_ip = docker_client
+            .get_network_ip_address(&self.container, network)
+            .await?;
+        let password = self.password.as_deref();
+        self.external_address = Self::address(&network_ip, Self::DEFAULT_REDIS_PORT, password)?;
+        let host_port = self.container.get_host_port_ipv4(Self::DEFAULT_REDIS_PORT);
+        self.internal_address = Self::address("127.0.0.1", host_port, password)?;
+        Ok(())
+    }
+
+    async fn start(
+        docker_client: &'a DockerClient,
+        network: &str,
+        image: RunnableImage<GenericImage>,
+        password: Option<String>,
+    ) -> anyhow::Result<Redis<'a>> {
         let image = image.with_network(network);
         let container = docker_client.cli.run(image);
         let network_ip = docker_client
             .get_network_ip_address(&container, network)
             .await?;
 
-        let external_address = format!("redis://{}:{}", network_ip, Self::DEFAULT_REDIS_PORT);
+        let external_address =
+            Self::address(&network_ip, Self::DEFAULT_REDIS_PORT, password.as_deref())?;
 
         let host_port = container.get_host_port_ipv4(Self::DEFAULT_REDIS_PORT);
-        let internal_address = format!("redis://127.0.0.1:{host_port}");
+        let internal_address = Self::address("127.0.0.1", host_port, password.as_deref())?;
 
         tracing::info!(
             "Redis container is running. External address: {}. Internal address: {}",
@@ -598,6 +821,17 @@ impl<'a> Redis<'a> {
             container,
             internal_address,
             external_address,
+            password,
         })
     }
+
+    /// Builds a `redis://` URL, percent-encoding the password if there is one.
+    fn address(host: &str, port: u16, password: Option<&str>) -> anyhow::Result<String> {
+        let mut url = url::Url::parse(&format!("redis://{host}:{port}"))?;
+        if password.is_some() {
+            url.set_password(password)
+                .map_err(|_| anyhow!("failed to set the password of {url}"))?;
+        }
+        Ok(url.to_string())
+    }
 }
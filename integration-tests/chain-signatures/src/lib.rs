@@ -26,6 +26,7 @@ use near_workspaces::types::{KeyType, SecretKey};
 use near_workspaces::{Account, AccountId, Contract, Worker};
 use serde_json::json;
 use testcontainers::{Container, GenericImage};
+use url::Url;
 
 const NETWORK: &str = "mpc_it_network";
 
@@ -34,6 +35,23 @@ pub struct MultichainConfig {
     pub nodes: usize,
     pub threshold: usize,
     pub protocol: ProtocolConfig,
+    /// Docker network the environment is set up in. Test runs that happen one after
+    /// another should use different networks so they don't interfere with each other.
+    pub docker_network: String,
+    /// Redis instance to use instead of starting a container for it. Defaults to the value of
+    /// `MPC_IT_REDIS_URL` if set.
+    pub redis_url: Option<Url>,
+    /// Maximum number of triple generators each node runs at once. Unbounded if not set.
+    pub max_concurrent_triple_gen: Option<usize>,
+}
+
+impl MultichainConfig {
+    /// Uses an already running redis instead of starting a new container for it. Keys are
+    /// namespaced per run so that runs sharing the same redis do not interfere.
+    pub fn with_redis_url(mut self, url: Url) -> Self {
+        self.redis_url = Some(url);
+        self
+    }
 }
 
 impl Default for MultichainConfig {
@@ -54,10 +72,53 @@ impl Default for MultichainConfig {
                 },
                 ..Default::default()
             },
+            docker_network: NETWORK.to_string(),
+            redis_url: std::env::var("MPC_IT_REDIS_URL")
+                .ok()
+                .map(|url| Url::parse(&url).expect("MPC_IT_REDIS_URL is not a valid url")),
+            max_concurrent_triple_gen: None,
         }
     }
 }
 
+/// Triple counts reported by a node through its `/state` endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TripleStats {
+    pub count: usize,
+    pub mine_count: usize,
+    pub potential_count: usize,
+    /// Presignatures the node holds for the running epoch, which are made from its triples.
+    pub presignature_count: usize,
+}
+
+/// Fetches the triple counts of the node listening on `address`. Fails if the node is not
+/// in the running state, since only then it manages triples.
+pub async fn fetch_triple_stats(address: &str) -> anyhow::Result<TripleStats> {
+    let state_view: mpc_node::web::StateView = reqwest::get(Url::parse(address)?.join("/state")?)
+        .await?
+        .json()
+        .await?;
+    match state_view {
+        mpc_node::web::StateView::Running {
+            triple_count,
+            triple_mine_count,
+            triple_potential_count,
+            presignature_count_by_epoch,
+            epoch,
+            ..
+        } => Ok(TripleStats {
+            count: triple_count,
+            mine_count: triple_mine_count,
+            potential_count: triple_potential_count,
+            presignature_count: presignature_count_by_epoch
+                .get(&epoch)
+                .copied()
+                .unwrap_or(0),
+        }),
+        state => anyhow::bail!("node at {address} is not running: {state:?}"),
+    }
+}
+
 pub enum Nodes<'a> {
     Local {
         ctx: Context<'a>,
@@ -95,6 +156,26 @@ impl Nodes<'_> {
         }
     }
 
+    /// Ids of the node containers, or `None` if the nodes run as host processes.
+    pub fn container_ids(&self) -> Option<Vec<String>> {
+        match self {
+            Nodes::Local { .. } => None,
+            Nodes::Docker { nodes, .. } => Some(
+                nodes
+                    .iter()
+                    .map(|node| node.container.id().to_string())
+                    .collect(),
+            ),
+        }
+    }
+
+    pub async fn triple_stats(&self, id: usize) -> anyhow::Result<TripleStats> {
+        match self {
+            Nodes::Local { nodes, .. } => nodes[id].get_triple_stats().await,
+            Nodes::Docker { nodes, .. } => nodes[id].get_triple_stats().await,
+        }
+    }
+
     pub fn near_accounts(&self) -> Vec<&Account> {
         match self {
             Nodes::Local { nodes, .. } => nodes.iter().map(|node| &node.account).collect(),
@@ -158,6 +239,7 @@ impl Nodes<'_> {
 
     pub async fn triple_storage(&self, redis_pool: &Pool, account_id: &AccountId) -> TripleStorage {
         storage::triple_storage::init(redis_pool, account_id)
+            .with_key_prefix(self.ctx().storage_options.redis_key_prefix.clone())
     }
 
     pub async fn gcp_services(&self) -> anyhow::Result<Vec<GcpService>> {
@@ -200,15 +282,19 @@ pub struct Context<'a> {
     pub lake_indexer: crate::containers::LakeIndexer<'a>,
     pub worker: Worker<Sandbox>,
     pub mpc_contract: Contract,
-    pub redis: crate::containers::Redis<'a>,
+    /// Not set when an external redis is used.
+    pub redis: Option<crate::containers::Redis<'a>>,
     pub storage_options: storage::Options,
     pub mesh_options: mesh::Options,
     pub message_options: http_client::Options,
 }
 
-pub async fn setup(docker_client: &DockerClient) -> anyhow::Result<Context<'_>> {
+pub async fn setup<'a>(
+    docker_client: &'a DockerClient,
+    cfg: &MultichainConfig,
+) -> anyhow::Result<Context<'a>> {
     let release = true;
-    let docker_network = NETWORK;
+    let docker_network = cfg.docker_network.as_str();
     docker_client.create_network(docker_network).await?;
 
     let LakeIndexerCtx {
@@ -226,8 +312,18 @@ pub async fn setup(docker_client: &DockerClient) -> anyhow::Result<Context<'_>>
         .await?;
     tracing::info!(contract_id = %mpc_contract.id(), "deployed mpc contract");
 
-    let redis = crate::containers::Redis::run(docker_client, docker_network).await?;
-    let redis_url = redis.internal_address.clone();
+    let (redis, redis_url, redis_key_prefix) = match &cfg.redis_url {
+        Some(redis_url) => {
+            let redis_key_prefix = format!("it-{:016x}", rand::random::<u64>());
+            tracing::info!(%redis_url, %redis_key_prefix, "using external redis");
+            (None, redis_url.to_string(), Some(redis_key_prefix))
+        }
+        None => {
+            let redis = crate::containers::Redis::run(docker_client, docker_network).await?;
+            let redis_url = redis.internal_address.clone();
+            (Some(redis), redis_url, None)
+        }
+    };
 
     let sk_share_local_path = "multichain-integration-secret-manager".to_string();
     let storage_options = mpc_node::storage::Options {
@@ -236,6 +332,8 @@ pub async fn setup(docker_client: &DockerClient) -> anyhow::Result<Context<'_>>
         sk_share_secret_id: None,
         sk_share_local_path: Some(sk_share_local_path),
         redis_url,
+        durability: storage::Durability::Eventual,
+        redis_key_prefix,
     };
 
     let mesh_options = mpc_node::mesh::Options {
@@ -261,7 +359,7 @@ pub async fn setup(docker_client: &DockerClient) -> anyhow::Result<Context<'_>>
 }
 
 pub async fn docker(cfg: MultichainConfig, docker_client: &DockerClient) -> anyhow::Result<Nodes> {
-    let ctx = setup(docker_client).await?;
+    let ctx = setup(docker_client, &cfg).await?;
 
     let accounts =
         futures::future::join_all((0..cfg.nodes).map(|_| ctx.worker.dev_create_account()))
@@ -310,7 +408,7 @@ pub async fn dry_host(
     cfg: MultichainConfig,
     docker_client: &DockerClient,
 ) -> anyhow::Result<Context> {
-    let ctx = setup(docker_client).await?;
+    let ctx = setup(docker_client, &cfg).await?;
 
     let accounts =
         futures::future::join_all((0..cfg.nodes).map(|_| ctx.worker.dev_create_account()))
@@ -363,7 +461,7 @@ pub async fn dry_host(
 }
 
 pub async fn host(cfg: MultichainConfig, docker_client: &DockerClient) -> anyhow::Result<Nodes> {
-    let ctx = setup(docker_client).await?;
+    let ctx = setup(docker_client, &cfg).await?;
 
     let accounts =
         futures::future::join_all((0..cfg.nodes).map(|_| ctx.worker.dev_create_account()))
@@ -69,6 +69,9 @@ impl Node {
             indexer_options,
             my_address: None,
             storage_options: ctx.storage_options.clone(),
+            triple_pool_alert_threshold: mpc_node::config::DEFAULT_TRIPLE_POOL_ALERT_THRESHOLD,
+            max_presignature_age_secs: mpc_node::config::DEFAULT_MAX_PRESIGNATURE_AGE_SECS,
+            max_triple_generators: cfg.max_concurrent_triple_gen,
             override_config: Some(OverrideConfig::new(serde_json::to_value(
                 cfg.protocol.clone(),
             )?)),
@@ -161,6 +164,9 @@ impl Node {
             indexer_options,
             my_address: None,
             storage_options: ctx.storage_options.clone(),
+            triple_pool_alert_threshold: mpc_node::config::DEFAULT_TRIPLE_POOL_ALERT_THRESHOLD,
+            max_presignature_age_secs: mpc_node::config::DEFAULT_MAX_PRESIGNATURE_AGE_SECS,
+            max_triple_generators: config.cfg.max_concurrent_triple_gen,
             override_config: Some(OverrideConfig::new(serde_json::to_value(
                 config.cfg.protocol.clone(),
             )?)),
@@ -189,6 +195,10 @@ impl Node {
         })
     }
 
+    pub async fn get_triple_stats(&self) -> anyhow::Result<crate::TripleStats> {
+        crate::fetch_triple_stats(&self.address).await
+    }
+
     pub fn kill(self) -> NodeConfig {
         // NOTE: process gets killed after this function completes via the drop, due to taking ownership of self.
 
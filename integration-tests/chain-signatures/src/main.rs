@@ -27,6 +27,11 @@ enum Cli {
     DepServices,
     /// Generate example commands to interact with the contract
     ContractCommands,
+    /// Remove unused docker networks left behind by test runs
+    CleanupNetworks {
+        #[arg(short, long, default_value = "test-")]
+        prefix: String,
+    },
 }
 
 #[tokio::main]
@@ -61,7 +66,7 @@ async fn main() -> anyhow::Result<()> {
 
             println!("\nExternal services:");
             println!("  lake_indexer:  {}", ctx.lake_indexer.rpc_host_address);
-            println!("  redis:  {}", ctx.redis.internal_address);
+            println!("  redis:  {}", ctx.storage_options.redis_url);
 
             println!("\nNodes:");
             for i in 0..urls.len() {
@@ -90,6 +95,10 @@ async fn main() -> anyhow::Result<()> {
             println!("Received Ctrl-C");
             println!("Stopped dependency services");
         }
+        Cli::CleanupNetworks { prefix } => {
+            let removed = docker_client.cleanup_all_networks(&prefix).await?;
+            println!("Removed {removed} networks starting with '{prefix}'");
+        }
         Cli::ContractCommands => {
             println!("Building a doc with example commands");
             let path = "../../chain-signatures/contract/EXAMPLE.md";
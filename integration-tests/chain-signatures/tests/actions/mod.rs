@@ -29,8 +29,12 @@ use rand::Rng;
 use secp256k1::XOnlyPublicKey;
 use wait_for::{SignatureError, WaitForError};
 
+use std::str::FromStr;
 use std::time::Duration;
 
+use anyhow::Context;
+use backon::{ConstantBuilder, Retryable};
+
 const CHAIN_ID_ETH: u64 = 31337;
 
 use integration_tests_chain_signatures::containers::LakeIndexer;
@@ -42,9 +46,28 @@ use serde_json::json;
 
 pub async fn request_sign(
     ctx: &MultichainTestContext<'_>,
+) -> anyhow::Result<([u8; 32], [u8; 32], Account, AsyncTransactionStatus)> {
+    request_sign_with_path(ctx, "test").await
+}
+
+/// Same as [`request_sign`], but the signature is requested for the given derivation path.
+pub async fn request_sign_with_path(
+    ctx: &MultichainTestContext<'_>,
+    hd_path: &str,
 ) -> anyhow::Result<([u8; 32], [u8; 32], Account, AsyncTransactionStatus)> {
     let worker = &ctx.nodes.ctx().worker;
     let account = worker.dev_create_account().await?;
+    let (payload, payload_hashed, status) = request_sign_as(ctx, &account, hd_path).await?;
+    Ok((payload, payload_hashed, account, status))
+}
+
+/// Requests a signature of a random payload for the given derivation path, signed by an
+/// existing `account`.
+pub async fn request_sign_as(
+    ctx: &MultichainTestContext<'_>,
+    account: &Account,
+    hd_path: &str,
+) -> anyhow::Result<([u8; 32], [u8; 32], AsyncTransactionStatus)> {
     let payload: [u8; 32] = rand::thread_rng().gen();
     let payload_hashed = web3::signing::keccak256(&payload);
 
@@ -56,7 +79,7 @@ pub async fn request_sign(
 
     let request = SignRequest {
         payload: payload_hashed,
-        path: "test".to_string(),
+        path: hd_path.to_string(),
         key_version: 0,
     };
     let status = ctx
@@ -70,7 +93,7 @@ pub async fn request_sign(
         .transact_async()
         .await?;
     tokio::time::sleep(Duration::from_secs(1)).await;
-    Ok((payload, payload_hashed, account, status))
+    Ok((payload, payload_hashed, status))
 }
 
 pub async fn request_batch_random_sign(
@@ -109,6 +132,29 @@ pub async fn request_batch_random_sign(
     Ok((payloads, account, status))
 }
 
+/// Requests `n` signatures one after another, only submitting the next request once the
+/// previous one was responded to. Checks that every response is a valid signature for its own
+/// request and returns the signatures in request order.
+pub async fn request_sign_batch_sequential(
+    ctx: &MultichainTestContext<'_>,
+    state: &RunningContractState,
+    n: usize,
+) -> anyhow::Result<Vec<FullSignature<Secp256k1>>> {
+    let mut mpc_pk_bytes = vec![0x04];
+    mpc_pk_bytes.extend_from_slice(&state.public_key.as_bytes()[1..]);
+
+    let mut signatures = Vec::with_capacity(n);
+    for i in 0..n {
+        let (_, payload_hash, account, status) = request_sign(ctx).await?;
+        let signature = wait_for::signature_responded(status)
+            .await
+            .with_context(|| format!("sequential signature request {i} was not responded"))?;
+        assert_signature(account.id(), &mpc_pk_bytes, payload_hash, &signature).await;
+        signatures.push(signature);
+    }
+    Ok(signatures)
+}
+
 pub async fn request_batch_duplicate_sign(
     ctx: &MultichainTestContext<'_>,
 ) -> anyhow::Result<([u8; 32], u32, Account, AsyncTransactionStatus)> {
@@ -157,6 +203,182 @@ pub async fn assert_signature(
     assert!(signature.verify(&user_pk, &Scalar::from_bytes(payload).unwrap(),));
 }
 
+/// Requests a signature as `account1` for `path1` and one as `account2` for `path2`. Each has
+/// to verify under the key derived for its own account and path, and neither may verify under
+/// the key derived for the other pair. The two pairs must differ in account, path or both.
+pub async fn sign_and_verify_with_custom_hd_path(
+    ctx: &MultichainTestContext<'_>,
+    state: &RunningContractState,
+    account1: &Account,
+    path1: &str,
+    account2: &Account,
+    path2: &str,
+) -> anyhow::Result<()> {
+    let mut mpc_pk_bytes = vec![0x04];
+    mpc_pk_bytes.extend_from_slice(&state.public_key.as_bytes()[1..]);
+    let mpc_point = EncodedPoint::from_bytes(mpc_pk_bytes).unwrap();
+    let mpc_pk = AffinePoint::from_encoded_point(&mpc_point).unwrap();
+
+    let (_, payload1, status1) = request_sign_as(ctx, account1, path1).await?;
+    let (_, payload2, status2) = request_sign_as(ctx, account2, path2).await?;
+    let signature1 = wait_for::signature_responded(status1).await?;
+    let signature2 = wait_for::signature_responded(status2).await?;
+    let payload1 = Scalar::from_bytes(payload1).unwrap();
+    let payload2 = Scalar::from_bytes(payload2).unwrap();
+
+    let user_pk1 = derive_key(mpc_pk, derive_epsilon(account1.id(), path1));
+    let user_pk2 = derive_key(mpc_pk, derive_epsilon(account2.id(), path2));
+    assert_ne!(
+        user_pk1,
+        user_pk2,
+        "({}, {path1:?}) and ({}, {path2:?}) derived the same key",
+        account1.id(),
+        account2.id()
+    );
+
+    assert!(
+        signature1.verify(&user_pk1, &payload1),
+        "signature for ({}, {path1:?}) does not verify under its own key",
+        account1.id()
+    );
+    assert!(
+        signature2.verify(&user_pk2, &payload2),
+        "signature for ({}, {path2:?}) does not verify under its own key",
+        account2.id()
+    );
+    assert!(
+        !signature1.verify(&user_pk2, &payload1),
+        "signature for ({}, {path1:?}) verifies under the key of ({}, {path2:?})",
+        account1.id(),
+        account2.id()
+    );
+    assert!(
+        !signature2.verify(&user_pk1, &payload2),
+        "signature for ({}, {path2:?}) verifies under the key of ({}, {path1:?})",
+        account2.id(),
+        account1.id()
+    );
+
+    Ok(())
+}
+
+/// Checks that all nodes roughly agree on the triple pool: every node stores a share of every
+/// triple and every triple is mine for exactly one node. Triples keep being generated and
+/// consumed while the nodes are queried, and a protocol does not finish on all nodes at the same
+/// moment, so the counts may differ by up to the number of protocols running at once.
+pub async fn assert_triple_counts_consistent(
+    ctx: &MultichainTestContext<'_>,
+) -> anyhow::Result<()> {
+    let tolerance = ctx.cfg.protocol.max_concurrent_generation as usize;
+    let is_consistent = || async {
+        let mut counts = Vec::new();
+        for id in 0..ctx.nodes.len() {
+            let stats = ctx.nodes.triple_stats(id).await?;
+            counts.push((stats.count, stats.mine_count));
+        }
+
+        let min_total = counts.iter().map(|(count, _)| *count).min().unwrap_or(0);
+        let max_total = counts.iter().map(|(count, _)| *count).max().unwrap_or(0);
+        let mine_sum: usize = counts.iter().map(|(_, mine)| mine).sum();
+        let consistent = max_total - min_total <= tolerance
+            && counts.iter().all(|&(count, mine)| mine <= count)
+            && mine_sum.abs_diff(max_total) <= tolerance;
+        if !consistent {
+            let stats = counts
+                .iter()
+                .enumerate()
+                .map(|(id, (count, mine))| format!("node {id}: total={count} mine={mine}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            anyhow::bail!(
+                "inconsistent triple counts beyond tolerance {tolerance} \
+                 (sum of mine={mine_sum}): {stats}"
+            );
+        }
+        Ok(())
+    };
+
+    is_consistent
+        .retry(
+            &ConstantBuilder::default()
+                .with_delay(Duration::from_secs(1))
+                .with_max_times(10),
+        )
+        .await
+}
+
+/// Checks that no node consumed the same triple for more than one presignature, according to
+/// the used triple log each node keeps in redis.
+pub async fn verify_no_triple_reuse(ctx: &MultichainTestContext<'_>) -> anyhow::Result<()> {
+    let redis_pool =
+        deadpool_redis::Config::from_url(ctx.nodes.ctx().storage_options.redis_url.clone())
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
+    for account in ctx.nodes.near_accounts() {
+        let storage = ctx.nodes.triple_storage(&redis_pool, account.id()).await;
+        let reused = storage.used_log().reused().await?;
+        if !reused.is_empty() {
+            anyhow::bail!("node {} reused triples: {reused:?}", account.id());
+        }
+    }
+    Ok(())
+}
+
+/// Adds a participant and removes one of the original participants right after, without
+/// waiting for the network to refill its triples and presignatures in between. The contract
+/// accepts a new vote only once the previous reshare finished, so the removal is voted on as
+/// soon as the network is running in the new epoch. Ends with a signature in the final epoch.
+pub async fn double_reshare(ctx: &mut MultichainTestContext<'_>) -> anyhow::Result<()> {
+    let state = wait_for::running_mpc(ctx, None).await?;
+    let kick = near_workspaces::AccountId::from_str(
+        state
+            .participants
+            .keys()
+            .next()
+            .context("no participants in running state")?
+            .as_ref(),
+    )?;
+
+    tracing::info!("adding a participant");
+    ctx.add_participant(None).await?;
+    tracing::info!(%kick, "removing a participant right after the join");
+    ctx.remove_participant(Some(&kick)).await?;
+
+    let state = wait_for::running_mpc(ctx, Some(state.epoch + 2)).await?;
+    assert_eq!(state.participants.len(), ctx.cfg.nodes);
+    assert!(state
+        .participants
+        .keys()
+        .all(|account_id| account_id.as_str() != kick.as_str()));
+    wait_for::has_at_least_triples(ctx, 2).await?;
+    wait_for::has_at_least_presignatures(ctx, 2).await?;
+    single_signature_production(ctx, &state).await
+}
+
+/// Kills every node so that the whole network is down at the same time, then restarts all of
+/// them with their previous configuration. The nodes come back with the triples and
+/// presignatures persisted in Redis.
+pub async fn kill_and_restart_all_nodes(ctx: &mut MultichainTestContext<'_>) -> anyhow::Result<()> {
+    let account_ids: Vec<near_workspaces::AccountId> = ctx
+        .nodes
+        .near_accounts()
+        .into_iter()
+        .map(|account| account.id().clone())
+        .collect();
+
+    let mut configs = Vec::with_capacity(account_ids.len());
+    for account_id in &account_ids {
+        tracing::info!(%account_id, "killing node");
+        configs.push(ctx.nodes.kill_node(account_id).await);
+    }
+
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    for config in configs {
+        ctx.nodes.restart_node(config).await?;
+    }
+    Ok(())
+}
+
 // A normal signature, but we try to insert a bad response which fails and the signature is generated
 pub async fn single_signature_rogue_responder(
     ctx: &MultichainTestContext<'_>,
@@ -363,6 +585,30 @@ pub async fn add_latency(
     .await
 }
 
+// Check that a latency toxic with the given parameters is active on the proxy, so tests do not
+// pass just because adding the toxic silently failed.
+pub async fn assert_latency_applied(
+    proxy: &str,
+    host: bool,
+    latency: u32,
+    jitter: u32,
+) -> anyhow::Result<()> {
+    let proxies = LakeIndexer::list_proxies(host).await?;
+    let status = proxies
+        .iter()
+        .find(|status| status.name == proxy)
+        .with_context(|| format!("proxy {proxy} does not exist"))?;
+    assert!(status.enabled, "proxy {proxy} is disabled");
+    assert!(
+        status.toxics.iter().any(|toxic| toxic.kind == "latency"
+            && toxic.attributes["latency"] == latency
+            && toxic.attributes["jitter"] == jitter),
+        "proxy {proxy} has no {latency}+-{jitter}ms latency toxic: {:?}",
+        status.toxics
+    );
+    Ok(())
+}
+
 // clear all toxics. Does not need to be called between tests since each test will drop toxiproxy-server
 // Only need if you want to clear all toxics in middle of a test
 #[allow(dead_code)]
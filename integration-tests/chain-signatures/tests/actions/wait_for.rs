@@ -7,11 +7,14 @@ use crate::MultichainTestContext;
 use anyhow::Context;
 use backon::Retryable;
 use backon::{ConstantBuilder, ExponentialBuilder};
+use cait_sith::protocol::Participant;
 use cait_sith::FullSignature;
 use crypto_shared::SignatureResponse;
+use deadpool_redis::Runtime;
 use k256::Secp256k1;
 use mpc_contract::ProtocolContractState;
 use mpc_contract::RunningContractState;
+use mpc_node::protocol::triple::TripleManager;
 use mpc_node::web::StateView;
 use near_fetch::ops::AsyncTransactionStatus;
 use near_lake_primitives::CryptoHash;
@@ -19,7 +22,7 @@ use near_primitives::errors::ActionErrorKind;
 use near_primitives::views::ExecutionOutcomeWithIdView;
 use near_primitives::views::ExecutionStatusView;
 use near_primitives::views::FinalExecutionStatus;
-use near_workspaces::Account;
+use near_workspaces::{Account, AccountId};
 use std::collections::HashMap;
 use url::Url;
 
@@ -60,6 +63,56 @@ pub async fn running_mpc<'a>(
         .with_context(|| err_msg)
 }
 
+/// Waits until at least `min_version` updates have been applied to the contract, as reported
+/// by its `update_version` view.
+pub async fn contract_at_version<'a>(
+    ctx: &MultichainTestContext<'a>,
+    min_version: u64,
+) -> anyhow::Result<()> {
+    let is_at_version = || async {
+        let version: u64 = ctx
+            .rpc_client
+            .view(ctx.contract().id(), "update_version")
+            .await
+            .map_err(|err| anyhow::anyhow!("could not view update_version {err:?}"))?
+            .json()?;
+        if version < min_version {
+            anyhow::bail!("contract is at update version {version}");
+        }
+        Ok(())
+    };
+    is_at_version
+        .retry(&ExponentialBuilder::default().with_max_times(6))
+        .await
+        .with_context(|| format!("contract did not reach update version {min_version} in time"))
+}
+
+/// Follows the logs of all nodes for `duration` and fails as soon as one of them logs an
+/// `ERROR`. Nodes running as host processes write straight to the test output, so their logs
+/// can not be followed and this returns right away.
+pub async fn no_errors_in_logs<'a>(
+    ctx: &MultichainTestContext<'a>,
+    duration: Duration,
+) -> anyhow::Result<()> {
+    let Some(container_ids) = ctx.nodes.container_ids() else {
+        tracing::warn!("node logs can only be followed for docker nodes, skipping log check");
+        return Ok(());
+    };
+
+    let docker_client = ctx.nodes.ctx().docker_client;
+    let checks = container_ids
+        .iter()
+        .enumerate()
+        .map(|(node, id)| async move {
+            match docker_client.find_in_logs(id, "ERROR", duration).await? {
+                Some(line) => anyhow::bail!("node {node} logged an error: {line}"),
+                None => Ok(()),
+            }
+        });
+    futures::future::try_join_all(checks).await?;
+    Ok(())
+}
+
 pub async fn has_at_least_triples<'a>(
     ctx: &MultichainTestContext<'a>,
     expected_triple_count: usize,
@@ -150,6 +203,50 @@ pub async fn has_at_least_mine_triples<'a>(
     Ok(state_views)
 }
 
+/// Waits until the nodes that are still online together own at least `expected_count` mine
+/// triples that were generated by online nodes only, and can thus be used for presignatures.
+pub async fn has_at_least_mine_ready_triples<'a>(
+    ctx: &MultichainTestContext<'a>,
+    state: &RunningContractState,
+    offline: &[&AccountId],
+    expected_count: usize,
+) -> anyhow::Result<usize> {
+    let online: Vec<(&AccountId, Participant)> = state
+        .participants
+        .account_to_participant_id
+        .iter()
+        .filter(|(account_id, _)| !offline.contains(account_id))
+        .map(|(account_id, id)| (account_id, Participant::from(*id)))
+        .collect();
+    let online_participants: Vec<Participant> = online.iter().map(|(_, p)| *p).collect();
+    let redis_pool =
+        deadpool_redis::Config::from_url(ctx.nodes.ctx().storage_options.redis_url.clone())
+            .create_pool(Some(Runtime::Tokio1))?;
+
+    let is_enough_ready_triples = || async {
+        let mut ready = 0;
+        for (account_id, me) in &online {
+            let storage = ctx.nodes.triple_storage(&redis_pool, account_id).await;
+            let triple_manager =
+                TripleManager::new(*me, state.threshold, state.epoch, account_id, &storage);
+            ready += triple_manager.len_mine_ready(&online_participants).await;
+        }
+        if ready < expected_count {
+            anyhow::bail!("online nodes only have {ready} ready mine triples");
+        }
+        Ok(ready)
+    };
+
+    is_enough_ready_triples
+        .retry(&ExponentialBuilder::default().with_max_times(6))
+        .await
+        .with_context(|| {
+            format!(
+                "online nodes failed to have '{expected_count}' ready mine triples before deadline"
+            )
+        })
+}
+
 pub async fn has_at_least_presignatures<'a>(
     ctx: &MultichainTestContext<'a>,
     expected_presignature_count: usize,
@@ -170,9 +267,19 @@ pub async fn has_at_least_presignatures<'a>(
                 .await?;
 
             match state_view {
+                // Only count presignatures generated in the current epoch.
                 StateView::Running {
-                    presignature_count, ..
-                } if presignature_count >= expected_presignature_count => Ok(state_view),
+                    ref presignature_count_by_epoch,
+                    epoch,
+                    ..
+                } if presignature_count_by_epoch
+                    .get(&epoch)
+                    .copied()
+                    .unwrap_or(0)
+                    >= expected_presignature_count =>
+                {
+                    Ok(state_view)
+                }
                 StateView::Running { .. } => {
                     anyhow::bail!("node does not have enough presignatures yet")
                 }
@@ -375,6 +482,36 @@ pub async fn rogue_message_responded(status: AsyncTransactionStatus) -> anyhow::
     Ok(signature.clone())
 }
 
+/// Waits for a signature request to fail instead of being answered, e.g. because it timed out,
+/// and returns the failure of the transaction.
+pub async fn signature_timeout(status: AsyncTransactionStatus) -> anyhow::Result<String> {
+    let is_tx_failed = || async {
+        let Poll::Ready(outcome) = status
+            .status()
+            .await
+            .map_err(|err| WaitForError::JsonRpc(format!("{err:?}")))?
+        else {
+            return Err(WaitForError::Signature(SignatureError::NotYetAvailable));
+        };
+
+        match outcome.status() {
+            FinalExecutionStatus::Failure(failure) => Ok(Ok(format!("{failure:?}"))),
+            status => Ok(Err(format!("signature request did not fail: {status:?}"))),
+        }
+    };
+
+    // The contract only times out a request once its yield expires, which takes a few minutes.
+    let strategy = ConstantBuilder::default()
+        .with_delay(Duration::from_secs(20))
+        .with_max_times(20);
+
+    is_tx_failed
+        .retry(&strategy)
+        .await
+        .with_context(|| "failed to wait for signature request to time out")?
+        .map_err(|err| anyhow::anyhow!(err))
+}
+
 pub async fn batch_signature_responded(
     status: AsyncTransactionStatus,
 ) -> Result<Vec<FullSignature<Secp256k1>>, WaitForError> {
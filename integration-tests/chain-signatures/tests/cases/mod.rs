@@ -1,12 +1,14 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::actions::{self, add_latency, wait_for};
-use crate::with_multichain_nodes;
+use crate::{with_multichain_nodes, with_multichain_nodes_matrix};
 
 use cait_sith::protocol::Participant;
 use cait_sith::triples::{TriplePub, TripleShare};
 use cait_sith::PresignOutput;
 use crypto_shared::{self, derive_epsilon, derive_key, x_coordinate, ScalarExt};
+use deadpool_redis::redis::AsyncCommands;
 use deadpool_redis::Runtime;
 use elliptic_curve::CurveArithmetic;
 use integration_tests_chain_signatures::containers::{self, DockerClient};
@@ -14,11 +16,15 @@ use integration_tests_chain_signatures::MultichainConfig;
 use k256::elliptic_curve::point::AffineCoordinates;
 use k256::Secp256k1;
 use mpc_contract::config::Config;
+use mpc_contract::errors::SignError;
 use mpc_contract::update::ProposeUpdateArgs;
 use mpc_node::kdf::into_eth_sig;
+use mpc_node::protocol::contract::primitives::{ParticipantInfo, Participants};
 use mpc_node::protocol::presignature::{Presignature, PresignatureId, PresignatureManager};
-use mpc_node::protocol::triple::{Triple, TripleManager};
+use mpc_node::protocol::triple::{AuditEvent, Triple, TripleManager};
 use mpc_node::storage;
+use mpc_node::storage::presignature_storage::PresignatureStorage;
+use mpc_node::storage::triple_storage::TripleStorage;
 use mpc_node::util::NearPublicKeyExt;
 use near_account_id::AccountId;
 use test_log::test;
@@ -82,12 +88,35 @@ async fn test_multichain_reshare() -> anyhow::Result<()> {
     .await
 }
 
+#[test(tokio::test)]
+async fn test_multichain_double_reshare() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |mut ctx| {
+        Box::pin(async move {
+            let state = wait_for::running_mpc(&ctx, Some(0)).await?;
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state).await?;
+
+            actions::double_reshare(&mut ctx).await
+        })
+    })
+    .await
+}
+
 #[test(tokio::test)]
 async fn test_triples_and_presignatures() -> anyhow::Result<()> {
-    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+    let configs = [(3, 2), (5, 3), (7, 4)]
+        .into_iter()
+        .map(|(nodes, threshold)| MultichainConfig {
+            nodes,
+            threshold,
+            ..Default::default()
+        })
+        .collect();
+    with_multichain_nodes_matrix(configs, |ctx| {
         Box::pin(async move {
             let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
-            assert_eq!(state_0.participants.len(), 3);
+            assert_eq!(state_0.participants.len(), ctx.cfg.nodes);
             wait_for::has_at_least_triples(&ctx, 2).await?;
             wait_for::has_at_least_presignatures(&ctx, 2).await?;
             Ok(())
@@ -136,7 +165,10 @@ async fn test_signature_offline_node() -> anyhow::Result<()> {
             // Try again if the first attempt failed. This second portion should not be needed when the NEP
             // comes in for resumeable MPC.
             if presig_res.is_err() || sig_res.is_err() {
-                // Retry if the first attempt failed.
+                // Retry if the first attempt failed, once there are triples that don't rely on the
+                // offline node.
+                wait_for::has_at_least_mine_ready_triples(&ctx, &state_0, &[&account_id], 2)
+                    .await?;
                 wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
                 actions::single_signature_production(&ctx, &state_0).await?;
             }
@@ -147,6 +179,30 @@ async fn test_signature_offline_node() -> anyhow::Result<()> {
     .await
 }
 
+#[test(tokio::test)]
+async fn test_signature_request_timeout() -> anyhow::Result<()> {
+    let mut config = MultichainConfig::default();
+    // Never generate presignatures, so signature requests can not be answered.
+    config.protocol.presignature.min_presignatures = 0;
+    config.protocol.presignature.max_presignatures = 0;
+    config.protocol.signature.generation_timeout = 1000;
+    config.protocol.signature.generation_timeout_total = 2000;
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let _ = wait_for::running_mpc(&ctx, Some(0)).await?;
+            let (_, _, _, status) = actions::request_sign(&ctx).await?;
+            let err = wait_for::signature_timeout(status).await?;
+            assert!(
+                err.contains(&SignError::Timeout.to_string()),
+                "unexpected failure: {err}"
+            );
+
+            Ok(())
+        })
+    })
+    .await
+}
+
 #[test(tokio::test)]
 async fn test_key_derivation() -> anyhow::Result<()> {
     with_multichain_nodes(MultichainConfig::default(), |ctx| {
@@ -207,30 +263,162 @@ async fn test_key_derivation() -> anyhow::Result<()> {
     .await
 }
 
+#[test(tokio::test)]
+async fn test_key_derivation_paths() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 6).await?;
+            wait_for::has_at_least_presignatures(&ctx, 3).await?;
+
+            let mpc_pk: k256::AffinePoint = state_0.public_key.clone().into_affine_point();
+            let hd_paths = ["m/44'/60'/0'/0/0", "", "путь/🔑/路径"];
+            for hd_path in hd_paths {
+                let (_, payload_hashed, account, status) =
+                    actions::request_sign_with_path(&ctx, hd_path).await?;
+                let sig = wait_for::signature_responded(status).await?;
+
+                let user_pk = derive_key(mpc_pk, derive_epsilon(account.id(), hd_path));
+                assert!(
+                    sig.verify(&user_pk, &k256::Scalar::from_bytes(payload_hashed).unwrap()),
+                    "signature for path {hd_path:?} does not verify against its derived key"
+                );
+            }
+
+            // For a single account, every path has to derive a distinct key.
+            let account_id = AccountId::from_str("test.near").unwrap();
+            let derived_keys = hd_paths
+                .iter()
+                .map(|hd_path| derive_key(mpc_pk, derive_epsilon(&account_id, hd_path)))
+                .collect::<Vec<_>>();
+            for (i, key_i) in derived_keys.iter().enumerate() {
+                for key_j in derived_keys.iter().skip(i + 1) {
+                    assert_ne!(key_i, key_j, "derivation paths produced the same key");
+                }
+            }
+
+            Ok(())
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_key_derivation_isolation() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 6).await?;
+            wait_for::has_at_least_presignatures(&ctx, 3).await?;
+
+            let worker = &ctx.nodes.ctx().worker;
+            let alice = worker.dev_create_account().await?;
+            let bob = worker.dev_create_account().await?;
+
+            // Same path, different accounts.
+            actions::sign_and_verify_with_custom_hd_path(
+                &ctx, &state_0, &alice, "test", &bob, "test",
+            )
+            .await?;
+            // Same account, different paths.
+            actions::sign_and_verify_with_custom_hd_path(
+                &ctx,
+                &state_0,
+                &alice,
+                "m/44'/60'/0'/0/0",
+                &alice,
+                "m/44'/60'/0'/0/1",
+            )
+            .await?;
+            // Different accounts and paths.
+            actions::sign_and_verify_with_custom_hd_path(
+                &ctx, &state_0, &alice, "alice", &bob, "bob",
+            )
+            .await?;
+
+            Ok(())
+        })
+    })
+    .await
+}
+
 #[test(tokio::test)]
 async fn test_triple_persistence() -> anyhow::Result<()> {
     let docker_client = DockerClient::default();
-    let docker_network = "test-triple-persistence";
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-triple-persistence").await?;
+    let triple_storage =
+        storage::triple_storage::init(&redis_pool, &AccountId::from_str("test.near").unwrap());
+    // The dummy triples are generated by participants 1 and 2.
+    let triple_manager = TripleManager::for_participant(
+        Participant::from(1),
+        vec![Participant::from(1), Participant::from(2)],
+        2,
+        123,
+        &AccountId::from_str("test.near").unwrap(),
+        &triple_storage,
+    )?;
+    check_triple_persistence(triple_manager).await
+}
+
+#[test(tokio::test)]
+async fn test_triple_persistence_with_auth() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let docker_network = "test-triple-persistence-with-auth";
     docker_client.create_network(docker_network).await?;
-    let redis = containers::Redis::run(&docker_client, docker_network).await?;
+    let redis = containers::Redis::with_auth(&docker_client, docker_network, "s3cret/p@ss").await?;
     let redis_url = Url::parse(redis.internal_address.as_str())?;
+
+    // Without the password every command is rejected.
+    let mut anonymous_url = redis_url.clone();
+    anonymous_url.set_password(None).unwrap();
+    let anonymous_pool = deadpool_redis::Config::from_url(anonymous_url)
+        .create_pool(Some(Runtime::Tokio1))
+        .unwrap();
+    if let Ok(mut conn) = anonymous_pool.get().await {
+        let ping: Result<String, _> = deadpool_redis::redis::cmd("PING")
+            .query_async(&mut conn)
+            .await;
+        assert!(ping.is_err(), "redis accepted an unauthenticated command");
+    }
+
     let redis_cfg = deadpool_redis::Config::from_url(redis_url);
     let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
     let triple_storage =
         storage::triple_storage::init(&redis_pool, &AccountId::from_str("test.near").unwrap());
-
-    let mut triple_manager = TripleManager::new(
-        Participant::from(0),
-        5,
+    let triple_manager = TripleManager::for_participant(
+        Participant::from(1),
+        vec![Participant::from(1), Participant::from(2)],
+        2,
         123,
         &AccountId::from_str("test.near").unwrap(),
         &triple_storage,
-    );
+    )?;
+    check_triple_persistence(triple_manager).await
+}
 
-    let triple_id_1: u64 = 1;
-    let triple_1 = dummy_triple(triple_id_1);
-    let triple_id_2: u64 = 2;
-    let triple_2 = dummy_triple(triple_id_2);
+/// Same as `test_triple_persistence`, but runs without docker.
+#[test(tokio::test)]
+async fn test_triple_persistence_in_memory() -> anyhow::Result<()> {
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let triple_manager = TripleManager::for_participant(
+        Participant::from(1),
+        vec![Participant::from(1), Participant::from(2)],
+        2,
+        123,
+        &account_id,
+        &TripleStorage::in_memory(&account_id),
+    )?;
+    check_triple_persistence(triple_manager).await
+}
+
+async fn check_triple_persistence(mut triple_manager: TripleManager) -> anyhow::Result<()> {
+    let participants = vec![Participant::from(1), Participant::from(2)];
+    let triple_1 = dummy_triple(123, 1);
+    let triple_id_1 = triple_1.id;
+    let triple_2 = dummy_triple(123, 2);
+    let triple_id_2 = triple_2.id;
 
     // Check that the storage is empty at the start
     assert!(!triple_manager.contains(&triple_id_1).await);
@@ -254,7 +442,7 @@ async fn test_triple_persistence() -> anyhow::Result<()> {
 
     // Take triple and check that it is removed from the storage
     triple_manager
-        .take_two(triple_id_1, triple_id_2)
+        .take_two(triple_id_1, triple_id_2, &participants)
         .await
         .unwrap();
     assert!(!triple_manager.contains(&triple_id_1).await);
@@ -265,10 +453,10 @@ async fn test_triple_persistence() -> anyhow::Result<()> {
     assert_eq!(triple_manager.len_mine().await, 0);
     assert_eq!(triple_manager.len_potential().await, 0);
 
-    let mine_id_1: u64 = 3;
-    let mine_triple_1 = dummy_triple(mine_id_1);
-    let mine_id_2: u64 = 4;
-    let mine_triple_2 = dummy_triple(mine_id_2);
+    let mine_triple_1 = dummy_triple(123, 3);
+    let mine_id_1 = mine_triple_1.id;
+    let mine_triple_2 = dummy_triple(123, 4);
+    let mine_id_2 = mine_triple_2.id;
 
     // Add mine triple and check that it is in the storage
     triple_manager.insert_mine(mine_triple_1).await;
@@ -282,7 +470,7 @@ async fn test_triple_persistence() -> anyhow::Result<()> {
     assert_eq!(triple_manager.len_potential().await, 2);
 
     // Take mine triple and check that it is removed from the storage
-    triple_manager.take_two_mine().await.unwrap();
+    triple_manager.take_two_mine(&participants).await.unwrap();
     assert!(!triple_manager.contains(&mine_id_1).await);
     assert!(!triple_manager.contains(&mine_id_2).await);
     assert!(!triple_manager.contains_mine(&mine_id_1).await);
@@ -296,24 +484,282 @@ async fn test_triple_persistence() -> anyhow::Result<()> {
 }
 
 #[test(tokio::test)]
-async fn test_presignature_persistence() -> anyhow::Result<()> {
+async fn test_triple_survival_across_redis_restart() -> anyhow::Result<()> {
     let docker_client = DockerClient::default();
-    let docker_network = "test-presignature-persistence";
+    let docker_network = "test-triple-survival-redis-restart";
     docker_client.create_network(docker_network).await?;
-    let redis = containers::Redis::run(&docker_client, docker_network).await?;
-    let redis_url = Url::parse(redis.internal_address.as_str())?;
-    let redis_cfg = deadpool_redis::Config::from_url(redis_url);
+    let rdb_path = std::env::temp_dir().join(format!("mpc-redis-{}", rand::random::<u64>()));
+    std::fs::create_dir_all(&rdb_path)?;
+    let mut redis =
+        containers::Redis::with_persistence(&docker_client, docker_network, &rdb_path).await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+
+    let redis_cfg = deadpool_redis::Config::from_url(Url::parse(&redis.internal_address)?);
     let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
+    let triple_storage = storage::triple_storage::init(&redis_pool, &account_id);
+    let triple = dummy_triple(123, 1);
+    let mine_triple = dummy_triple(123, 2);
+    let (triple_id, mine_id) = (triple.id, mine_triple.id);
+    triple_storage.insert(triple).await?;
+    triple_storage.insert_mine(mine_triple).await?;
+
+    // Give redis time to take its `save 1 1` snapshot before restarting.
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    redis.restart(&docker_client, docker_network).await?;
+
+    let redis_cfg = deadpool_redis::Config::from_url(Url::parse(&redis.internal_address)?);
+    let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
+    let triple_storage = storage::triple_storage::init(&redis_pool, &account_id);
+    let mut len_generated = None;
+    for _ in 0..20 {
+        if let Ok(len) = triple_storage.len_generated().await {
+            len_generated = Some(len);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    assert_eq!(
+        len_generated,
+        Some(2),
+        "triples did not survive the restart"
+    );
+    assert!(triple_storage.contains(&triple_id).await?);
+    assert!(triple_storage.contains_mine(&mine_id).await?);
+
+    drop(redis);
+    let _ = std::fs::remove_dir_all(&rdb_path);
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_triple_persistence_synchronous_durability() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let docker_network = "test-triple-durability";
+    docker_client.create_network(docker_network).await?;
+    let primary = containers::Redis::run(&docker_client, docker_network).await?;
+    let replica = containers::Redis::run(&docker_client, docker_network).await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+
+    let pool = |address: &str| -> anyhow::Result<deadpool_redis::Pool> {
+        let redis_cfg = deadpool_redis::Config::from_url(Url::parse(address)?);
+        Ok(redis_cfg.create_pool(Some(Runtime::Tokio1))?)
+    };
+    let primary_pool = pool(&primary.internal_address)?;
+    let replica_pool = pool(&replica.internal_address)?;
+
+    // Make the second redis instance replicate the first one over the docker network.
+    let primary_url = Url::parse(&primary.external_address)?;
+    let mut replica_conn = replica_pool.get().await?;
+    let _: () = deadpool_redis::redis::cmd("REPLICAOF")
+        .arg(primary_url.host_str().unwrap())
+        .arg(primary_url.port().unwrap())
+        .query_async(&mut replica_conn)
+        .await?;
+
+    let triple_storage = storage::triple_storage::init(&primary_pool, &account_id)
+        .with_durability(storage::Durability::Synchronous);
+    let triple = dummy_triple(123, 1);
+    let triple_id = triple.id;
+
+    // The replica needs a moment to finish its initial sync before it can acknowledge writes.
+    let mut stored = false;
+    for _ in 0..20 {
+        if triple_storage.insert(triple.clone()).await.is_ok() {
+            stored = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    assert!(stored, "triple write was never acknowledged by the replica");
+
+    // Simulate a failover: the primary goes away and the replica gets promoted.
+    drop(primary);
+    let _: () = deadpool_redis::redis::cmd("REPLICAOF")
+        .arg("NO")
+        .arg("ONE")
+        .query_async(&mut replica_conn)
+        .await?;
+
+    let promoted_storage = storage::triple_storage::init(&replica_pool, &account_id);
+    assert!(promoted_storage.contains(&triple_id).await?);
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_triple_storage_list_ids() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-triple-storage-list-ids").await?;
+    let triple_storage =
+        storage::triple_storage::init(&redis_pool, &AccountId::from_str("test.near").unwrap());
+
+    assert!(triple_storage.list_ids(false).await?.is_empty());
+    assert!(triple_storage.list_ids(true).await?.is_empty());
+
+    let mut ids = Vec::new();
+    for index in [7, 3, 5, 1] {
+        let triple = dummy_triple(123, index);
+        ids.push(triple.id);
+        if index % 2 == 1 && index > 3 {
+            triple_storage.insert_mine(triple).await?;
+        } else {
+            triple_storage.insert(triple).await?;
+        }
+    }
+    ids.sort();
+
+    assert_eq!(triple_storage.list_ids(false).await?, ids);
+    assert_eq!(
+        triple_storage.list_ids(true).await?,
+        vec![
+            Triple::id_from_epoch_and_index(123, 5),
+            Triple::id_from_epoch_and_index(123, 7)
+        ]
+    );
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_used_triple_log() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-used-triple-log").await?;
+    let used_log =
+        storage::triple_storage::init(&redis_pool, &AccountId::from_str("test.near")?).used_log();
+
+    used_log.record(&[1, 2]).await?;
+    used_log.record(&[3, 4]).await?;
+    assert_eq!(used_log.entries().await?, vec![1, 2, 3, 4]);
+    assert!(used_log.reused().await?.is_empty());
+
+    used_log.record(&[4, 1]).await?;
+    used_log.record(&[1, 5]).await?;
+    assert_eq!(used_log.reused().await?, vec![1, 4]);
+
+    used_log.clear().await?;
+    assert!(used_log.entries().await?.is_empty());
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_triple_audit_log() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-triple-audit-log").await?;
+    let account_id = AccountId::from_str("test.near")?;
+    let triple_storage = storage::triple_storage::init(&redis_pool, &account_id);
+    let audit_log = triple_storage.audit_log();
+
+    let generated = AuditEvent::Generated {
+        id: 1,
+        epoch: 0,
+        timestamp: 1_700_000_000,
+        mine: true,
+    };
+    let expired = AuditEvent::Expired {
+        id: 2,
+        reason: "generation failed".to_string(),
+    };
+    audit_log
+        .append(&[generated.clone(), expired.clone()])
+        .await?;
+    assert_eq!(
+        audit_log.events().await?,
+        vec![generated.clone(), expired.clone()]
+    );
+
+    let triple_manager =
+        TripleManager::new(Participant::from(0), 2, 0, &account_id, &triple_storage);
+    triple_manager.log_used(&[1, 3], 42).await;
+    assert_eq!(
+        triple_manager.audit_log().await,
+        vec![
+            generated,
+            expired,
+            AuditEvent::Consumed {
+                id: 1,
+                used_for: 42
+            },
+            AuditEvent::Consumed {
+                id: 3,
+                used_for: 42
+            },
+        ]
+    );
+
+    audit_log.clear().await?;
+    assert!(triple_manager.audit_log().await.is_empty());
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_triple_cancel_all_generators() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) =
+        redis_pool(&docker_client, "test-triple-cancel-all-generators").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let triple_storage = storage::triple_storage::init(&redis_pool, &account_id);
+    let mut triple_manager =
+        TripleManager::new(Participant::from(0), 2, 123, &account_id, &triple_storage);
+
+    triple_manager.insert(dummy_triple(123, 1)).await;
+    triple_manager.insert_mine(dummy_triple(123, 2)).await;
+
+    let mut participants = Participants::default();
+    for id in 0..3 {
+        participants.insert(&Participant::from(id), ParticipantInfo::new(id));
+    }
+    for index in 10..15 {
+        let id = Triple::id_from_epoch_and_index(123, index);
+        triple_manager
+            .generate_with_id(id, &participants, 60_000)
+            .await?;
+    }
+    assert_eq!(triple_manager.len_active_generators(), 5);
+
+    let mut cancelled = triple_manager.cancel_all_generators();
+    cancelled.sort();
+    let expected: Vec<_> = (10..15)
+        .map(|index| Triple::id_from_epoch_and_index(123, index))
+        .collect();
+    assert_eq!(cancelled, expected);
+    assert_eq!(triple_manager.len_active_generators(), 0);
+    assert!(triple_manager.introduced.is_empty());
+    assert_eq!(triple_manager.len_generated().await, 2);
+    assert_eq!(triple_manager.len_mine().await, 1);
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_presignature_persistence() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-presignature-persistence").await?;
     let presignature_storage = storage::presignature_storage::init(
         &redis_pool,
         &AccountId::from_str("test.near").unwrap(),
     );
+    check_presignature_persistence(&presignature_storage).await
+}
+
+/// Same as `test_presignature_persistence`, but runs without docker.
+#[test(tokio::test)]
+async fn test_presignature_persistence_in_memory() -> anyhow::Result<()> {
+    let presignature_storage =
+        PresignatureStorage::in_memory(&AccountId::from_str("test.near").unwrap());
+    check_presignature_persistence(&presignature_storage).await
+}
+
+async fn check_presignature_persistence(
+    presignature_storage: &PresignatureStorage,
+) -> anyhow::Result<()> {
     let mut presignature_manager = PresignatureManager::new(
         Participant::from(0),
         5,
         123,
         &AccountId::from_str("test.near").unwrap(),
-        &presignature_storage,
+        presignature_storage,
     );
 
     let presignature = dummy_presignature();
@@ -354,34 +800,367 @@ async fn test_presignature_persistence() -> anyhow::Result<()> {
     assert_eq!(presignature_manager.len_generated().await, 1);
     assert_eq!(presignature_manager.len_mine().await, 1);
     assert_eq!(presignature_manager.len_potential().await, 1);
+    assert_eq!(
+        presignature_manager.len_by_epoch().await,
+        HashMap::from([(123, 1)])
+    );
 
     // Take mine presignature and check that it is removed from the storage
-    presignature_manager.take_mine().await.unwrap();
+    presignature_manager.take_newest_mine().await.unwrap();
     assert!(!presignature_manager.contains(&mine_presig_id).await);
     assert!(!presignature_manager.contains_mine(&mine_presig_id).await);
     assert_eq!(presignature_manager.len_generated().await, 0);
     assert_eq!(presignature_manager.len_mine().await, 0);
     assert!(presignature_manager.is_empty().await);
     assert_eq!(presignature_manager.len_potential().await, 0);
+    assert!(presignature_manager.len_by_epoch().await.is_empty());
 
     Ok(())
 }
 
+#[test(tokio::test)]
+async fn test_presignature_rehydrate_from_storage() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-presignature-rehydrate").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+
+    let participants = vec![Participant::from(0), Participant::from(1)];
+    {
+        let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+        let mut presignature_manager = PresignatureManager::new(
+            Participant::from(0),
+            5,
+            123,
+            &account_id,
+            &presignature_storage,
+        );
+        presignature_manager
+            .insert_mine(dummy_presignature_with(1, participants.clone()))
+            .await;
+        presignature_manager
+            .insert(dummy_presignature_with(2, participants.clone()))
+            .await;
+    }
+
+    // Presignatures stored by older versions have no epoch or mine age entries.
+    let mut conn = redis_pool.get().await?;
+    conn.del::<_, ()>("presignatures_epoch:v2:test.near")
+        .await?;
+    conn.del::<_, ()>("presignatures_mine_age:v2:test.near")
+        .await?;
+
+    let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+    let presignature_manager = PresignatureManager::new(
+        Participant::from(0),
+        5,
+        123,
+        &account_id,
+        &presignature_storage,
+    );
+    assert!(presignature_manager.len_by_epoch().await.is_empty());
+    assert_eq!(presignature_manager.rehydrate_from_storage().await?, 3);
+    assert_eq!(presignature_manager.rehydrate_from_storage().await?, 0);
+    assert!(presignature_manager.contains_mine(&1).await);
+    assert!(presignature_manager.contains(&2).await);
+    assert!(!presignature_manager.contains_mine(&2).await);
+    assert_eq!(presignature_manager.len_generated().await, 2);
+    assert_eq!(presignature_manager.len_mine().await, 1);
+    assert_eq!(
+        presignature_manager
+            .len_by_epoch()
+            .await
+            .values()
+            .sum::<usize>(),
+        2
+    );
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_presignature_reserve() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-presignature-reserve").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+    let mut presignature_manager = PresignatureManager::new(
+        Participant::from(0),
+        5,
+        123,
+        &account_id,
+        &presignature_storage,
+    );
+
+    let presignature = dummy_presignature();
+    let id = presignature.id;
+    presignature_manager.insert_mine(presignature).await;
+
+    // Only one of the concurrent reservations can succeed.
+    let (first, second) = tokio::join!(
+        presignature_manager.reserve(id),
+        presignature_manager.reserve(id)
+    );
+    let reserved = match (first, second) {
+        (Some(reserved), None) | (None, Some(reserved)) => reserved,
+        (Some(_), Some(_)) => panic!("presignature {id} was reserved twice"),
+        (None, None) => panic!("presignature {id} was not reserved"),
+    };
+    assert_eq!(reserved.id(), id);
+    assert!(!presignature_manager.contains_mine(&id).await);
+    assert!(presignature_manager.contains(&id).await);
+    // A reserved presignature can not be taken as a foreign one either.
+    assert!(presignature_storage.take(&id).await?.is_none());
+    assert!(presignature_manager.contains(&id).await);
+
+    // Rolling back makes it available again, including by age.
+    reserved.rollback().await?;
+    assert!(presignature_manager.contains_mine(&id).await);
+    assert_eq!(
+        presignature_manager.take_oldest_mine().await.unwrap().id,
+        id
+    );
+    presignature_manager.insert_mine(dummy_presignature()).await;
+
+    // Committing removes it for good.
+    let reserved = presignature_manager.reserve(id).await.unwrap();
+    assert!(presignature_manager.reserve(id).await.is_none());
+    assert_eq!(reserved.commit().await?.id, id);
+    assert!(!presignature_manager.contains(&id).await);
+    assert!(!presignature_manager.contains_mine(&id).await);
+    assert!(presignature_manager.is_empty().await);
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_presignature_compact_storage() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) =
+        redis_pool(&docker_client, "test-presignature-compact-storage").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+    let presignature_manager = PresignatureManager::new(
+        Participant::from(0),
+        5,
+        123,
+        &account_id,
+        &presignature_storage,
+    );
+
+    let presignature = dummy_presignature();
+    let id = presignature.id;
+    presignature_storage.insert_mine(presignature).await?;
+
+    // Duplicate the presignature under an older storage version, and leave a mine id behind
+    // that has no presignature data.
+    let mut conn = redis_pool.get().await?;
+    conn.hset::<_, _, _, ()>("presignatures:v1:test.near", id, dummy_presignature())
+        .await?;
+    conn.sadd::<_, _, ()>("presignatures_mine:v1:test.near", id)
+        .await?;
+    conn.sadd::<_, _, ()>("presignatures_mine:v2:test.near", 2u64)
+        .await?;
+
+    assert_eq!(presignature_manager.compact_storage().await?, 3);
+    assert_eq!(presignature_manager.compact_storage().await?, 0);
+
+    let legacy: bool = conn.exists("presignatures:v1:test.near").await?;
+    assert!(!legacy);
+    let legacy_mine: bool = conn.exists("presignatures_mine:v1:test.near").await?;
+    assert!(!legacy_mine);
+    assert_eq!(presignature_manager.len_generated().await, 1);
+    assert_eq!(presignature_manager.len_mine().await, 1);
+    assert!(presignature_manager.contains_mine(&id).await);
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_presignature_expire_for_participants() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) =
+        redis_pool(&docker_client, "test-presignature-expire-for-participants").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+    let mut presignature_manager = PresignatureManager::new(
+        Participant::from(0),
+        2,
+        123,
+        &account_id,
+        &presignature_storage,
+    );
+
+    let (p0, p1, p2) = (
+        Participant::from(0),
+        Participant::from(1),
+        Participant::from(2),
+    );
+    presignature_manager
+        .insert_mine(dummy_presignature_with(1, vec![p0, p1]))
+        .await;
+    presignature_manager
+        .insert(dummy_presignature_with(2, vec![p1, p2]))
+        .await;
+    presignature_manager
+        .insert(dummy_presignature_with(3, vec![p0, p2]))
+        .await;
+
+    // Participant 2 leaves, so only the presignature generated with 0 and 1 stays usable.
+    assert_eq!(presignature_manager.expire_for_participants(&[p2]).await, 2);
+    assert!(presignature_manager.contains(&1).await);
+    assert!(presignature_manager.contains_mine(&1).await);
+    assert!(!presignature_manager.contains(&2).await);
+    assert!(!presignature_manager.contains(&3).await);
+    assert_eq!(presignature_manager.len_generated().await, 1);
+    assert_eq!(
+        presignature_manager.len_by_epoch().await.get(&123),
+        Some(&1)
+    );
+
+    assert_eq!(presignature_manager.expire_for_participants(&[p2]).await, 0);
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_presignature_batch_insert() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-presignature-batch-insert").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+    let mut presignature_manager = PresignatureManager::new(
+        Participant::from(0),
+        5,
+        123,
+        &account_id,
+        &presignature_storage,
+    );
+
+    assert_eq!(presignature_manager.batch_insert(Vec::new()).await?, 0);
+
+    let participants = vec![Participant::from(1), Participant::from(2)];
+    let presignatures = (1..=100)
+        .map(|id| dummy_presignature_with(id, participants.clone()))
+        .collect();
+    assert_eq!(presignature_manager.batch_insert(presignatures).await?, 100);
+    assert_eq!(presignature_manager.len_generated().await, 100);
+    assert_eq!(presignature_manager.len_mine().await, 0);
+    assert!(presignature_manager.contains(&1).await);
+    assert!(presignature_manager.contains(&100).await);
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_presignature_delete_batch() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) = redis_pool(&docker_client, "test-presignature-delete-batch").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+
+    let participants = vec![Participant::from(1), Participant::from(2)];
+    presignature_storage
+        .insert_mine(dummy_presignature_with(1, participants.clone()))
+        .await?;
+    for id in 2..=3 {
+        presignature_storage
+            .insert(dummy_presignature_with(id, participants.clone()))
+            .await?;
+    }
+
+    assert_eq!(presignature_storage.delete_batch(&[]).await?, 0);
+    // Unknown ids are skipped without failing the batch.
+    assert_eq!(presignature_storage.delete_batch(&[1, 2, 99]).await?, 2);
+    assert!(!presignature_storage.contains(&1).await?);
+    assert!(!presignature_storage.contains_mine(&1).await?);
+    assert!(!presignature_storage.contains(&2).await?);
+    assert!(presignature_storage.contains(&3).await?);
+    assert_eq!(presignature_storage.len_generated().await?, 1);
+    assert_eq!(
+        presignature_storage
+            .len_by_epoch()
+            .await?
+            .values()
+            .sum::<usize>(),
+        1
+    );
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_presignature_contains_from_epoch() -> anyhow::Result<()> {
+    let docker_client = DockerClient::default();
+    let (_redis, redis_pool) =
+        redis_pool(&docker_client, "test-presignature-contains-from-epoch").await?;
+    let account_id = AccountId::from_str("test.near").unwrap();
+    let presignature_storage = storage::presignature_storage::init(&redis_pool, &account_id);
+
+    let mut epoch_1 = PresignatureManager::new(
+        Participant::from(0),
+        5,
+        1,
+        &account_id,
+        &presignature_storage,
+    );
+    epoch_1
+        .insert(Presignature {
+            origin_epoch: 1,
+            ..dummy_presignature()
+        })
+        .await;
+    assert!(epoch_1.contains_from_epoch(1, 1).await);
+
+    // After a reshare the node starts over with a manager for the new epoch.
+    let epoch_2 = PresignatureManager::new(
+        Participant::from(0),
+        5,
+        2,
+        &account_id,
+        &presignature_storage,
+    );
+    assert!(epoch_2.contains(&1).await);
+    assert!(!epoch_2.contains_from_epoch(1, 2).await);
+    assert!(!epoch_2.contains_from_epoch(2, 1).await);
+
+    Ok(())
+}
+
+/// Starts redis on a fresh docker network and returns it along with a connection pool. The
+/// container is stopped once it is dropped, so keep it around for as long as the pool is used.
+async fn redis_pool<'a>(
+    docker_client: &'a DockerClient,
+    network: &str,
+) -> anyhow::Result<(containers::Redis<'a>, deadpool_redis::Pool)> {
+    docker_client.create_network(network).await?;
+    let redis = containers::Redis::run(docker_client, network).await?;
+    let redis_url = Url::parse(redis.internal_address.as_str())?;
+    let redis_cfg = deadpool_redis::Config::from_url(redis_url);
+    let pool = redis_cfg.create_pool(Some(Runtime::Tokio1))?;
+    Ok((redis, pool))
+}
+
 fn dummy_presignature() -> Presignature {
+    dummy_presignature_with(1, vec![Participant::from(1), Participant::from(2)])
+}
+
+fn dummy_presignature_with(id: PresignatureId, participants: Vec<Participant>) -> Presignature {
     Presignature {
-        id: 1,
+        id,
         output: PresignOutput {
             big_r: <Secp256k1 as CurveArithmetic>::AffinePoint::default(),
             k: <Secp256k1 as CurveArithmetic>::Scalar::ZERO,
             sigma: <Secp256k1 as CurveArithmetic>::Scalar::ONE,
         },
-        participants: vec![Participant::from(1), Participant::from(2)],
+        participants,
+        origin_epoch: 123,
+        created_at: 1_700_000_000,
     }
 }
 
-fn dummy_triple(id: u64) -> Triple {
+fn dummy_triple(epoch: u64, index: u32) -> Triple {
     Triple {
-        id,
+        id: Triple::id_from_epoch_and_index(epoch, index),
         share: TripleShare {
             a: <Secp256k1 as CurveArithmetic>::Scalar::ZERO,
             b: <Secp256k1 as CurveArithmetic>::Scalar::ZERO,
@@ -406,6 +1185,7 @@ async fn test_signature_offline_node_back_online() -> anyhow::Result<()> {
             wait_for::has_at_least_triples(&ctx, 6).await?;
             wait_for::has_at_least_mine_triples(&ctx, 2).await?;
             wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+            actions::assert_triple_counts_consistent(&ctx).await?;
 
             // Kill node 2
             let account_id = near_workspaces::types::AccountId::from_str(
@@ -423,6 +1203,7 @@ async fn test_signature_offline_node_back_online() -> anyhow::Result<()> {
 
             wait_for::has_at_least_mine_triples(&ctx, 2).await?;
             wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+            actions::assert_triple_counts_consistent(&ctx).await?;
             // retry the same payload multiple times because we might pick many presignatures not present in node 2 repeatedly until yield/resume time out
             actions::single_payload_signature_production(&ctx, &state_0).await?;
 
@@ -432,6 +1213,28 @@ async fn test_signature_offline_node_back_online() -> anyhow::Result<()> {
     .await
 }
 
+#[test(tokio::test)]
+async fn test_full_cluster_restart() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |mut ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            wait_for::has_at_least_mine_triples(&ctx, 2).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+
+            actions::kill_and_restart_all_nodes(&mut ctx).await?;
+
+            // The network resumes in the same epoch instead of going through a reshare.
+            let state_1 = wait_for::running_mpc(&ctx, Some(state_0.epoch)).await?;
+            assert_eq!(state_1.epoch, state_0.epoch);
+            assert!(state_1.participants.keys().eq(state_0.participants.keys()));
+
+            wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+            actions::single_signature_production(&ctx, &state_1).await
+        })
+    })
+    .await
+}
+
 #[test(tokio::test)]
 async fn test_lake_congestion() -> anyhow::Result<()> {
     with_multichain_nodes(MultichainConfig::default(), |ctx| {
@@ -447,8 +1250,16 @@ async fn test_lake_congestion() -> anyhow::Result<()> {
             // with a 1s latency it fails to wait for signature response in time
             add_latency("lake-s3", false, 1.0, 100, 10).await?;
 
+            for node in 0..3 {
+                let proxy = ctx.nodes.proxy_name_for_node(node);
+                actions::assert_latency_applied(&proxy, true, 2_000, 200).await?;
+            }
+            actions::assert_latency_applied("lake-s3", false, 100, 10).await?;
+
             let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
             assert_eq!(state_0.participants.len(), 3);
+            // The latency should only slow the nodes down, not make them fail.
+            wait_for::no_errors_in_logs(&ctx, std::time::Duration::from_secs(30)).await?;
             wait_for::has_at_least_triples(&ctx, 2).await?;
             wait_for::has_at_least_presignatures(&ctx, 2).await?;
             actions::single_signature_rogue_responder(&ctx, &state_0).await?;
@@ -511,7 +1322,7 @@ async fn test_multichain_update_contract() -> anyhow::Result<()> {
             // up the new contract by first upgrading the contract, then trying to generate a new signature.
             let id = ctx.propose_update_contract_default().await;
             ctx.vote_update(id).await;
-            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+            wait_for::contract_at_version(&ctx, 1).await?;
             wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
             actions::single_payload_signature_production(&ctx, &state).await?;
 
@@ -523,7 +1334,7 @@ async fn test_multichain_update_contract() -> anyhow::Result<()> {
                 })
                 .await;
             ctx.vote_update(id).await;
-            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+            wait_for::contract_at_version(&ctx, 2).await?;
             wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
             actions::single_payload_signature_production(&ctx, &state).await?;
 
@@ -540,6 +1351,55 @@ async fn test_batch_random_signature() -> anyhow::Result<()> {
             let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
             assert_eq!(state_0.participants.len(), 3);
             actions::batch_random_signature_production(&ctx, &state_0).await?;
+            actions::verify_no_triple_reuse(&ctx).await?;
+            Ok(())
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_presignature_pool_recovery() -> anyhow::Result<()> {
+    // Upper bound on the batches needed to drain a pool of `max_presignatures`.
+    const MAX_DRAIN_BATCHES: usize = 10;
+
+    let config = MultichainConfig::default();
+    let min_presignatures = config.protocol.presignature.min_presignatures as usize;
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_presignatures(&ctx, min_presignatures).await?;
+
+            // Sign until the pool of node 0 runs low. It refills while the batches are served,
+            // so it may never be seen below the minimum.
+            for _ in 0..MAX_DRAIN_BATCHES {
+                actions::batch_random_signature_production(&ctx, &state_0).await?;
+                if ctx.nodes.triple_stats(0).await?.presignature_count < min_presignatures {
+                    break;
+                }
+            }
+
+            // The pool is self-healing: it refills and serves another batch.
+            wait_for::has_at_least_presignatures(&ctx, min_presignatures).await?;
+            actions::batch_random_signature_production(&ctx, &state_0).await?;
+            actions::verify_no_triple_reuse(&ctx).await?;
+            Ok(())
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_sequential_signatures() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            let signatures = actions::request_sign_batch_sequential(&ctx, &state_0, 5).await?;
+            assert_eq!(signatures.len(), 5);
+            actions::verify_no_triple_reuse(&ctx).await?;
             Ok(())
         })
     })
@@ -553,6 +1413,7 @@ async fn test_batch_duplicate_signature() -> anyhow::Result<()> {
             let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
             assert_eq!(state_0.participants.len(), 3);
             actions::batch_duplicate_signature_production(&ctx, &state_0).await?;
+            actions::verify_no_triple_reuse(&ctx).await?;
             Ok(())
         })
     })
@@ -25,6 +25,7 @@ async fn test_nightly_signature_production() -> anyhow::Result<()> {
             },
             ..Default::default()
         },
+        ..Default::default()
     };
 
     with_multichain_nodes(config, |ctx| {
@@ -207,3 +207,33 @@ where
 
     result
 }
+
+/// Runs the same test body sequentially for each of the given configs, each in its own
+/// docker network. All failures are collected and reported together at the end.
+pub async fn with_multichain_nodes_matrix<F>(
+    configs: Vec<MultichainConfig>,
+    f: F,
+) -> anyhow::Result<()>
+where
+    F: for<'a> Fn(MultichainTestContext<'a>) -> BoxFuture<'a, anyhow::Result<()>>,
+{
+    let mut failures = Vec::new();
+    for (i, mut cfg) in configs.into_iter().enumerate() {
+        cfg.docker_network = format!("{}-matrix-{i}", cfg.docker_network);
+        let label = format!("nodes={} threshold={}", cfg.nodes, cfg.threshold);
+        tracing::info!(%label, network = %cfg.docker_network, "running matrix config");
+        if let Err(err) = with_multichain_nodes(cfg, &f).await {
+            tracing::error!(%label, ?err, "matrix config failed");
+            failures.push(format!("[{label}]: {err:?}"));
+        }
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "{} matrix config(s) failed:\n{}",
+            failures.len(),
+            failures.join("\n")
+        );
+    }
+    Ok(())
+}